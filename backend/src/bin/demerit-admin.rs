@@ -0,0 +1,546 @@
+//! `demerit-admin`: operator commands run against the configured database,
+//! using the same configuration and services as the HTTP server.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use demerit_backend::config::{self, Config, DatabaseBackend};
+use demerit_backend::database::migrations::{self, Migration};
+#[cfg(feature = "postgres")]
+use demerit_backend::database::postgres::PostgresStore;
+use demerit_backend::database::repository::{RoleDetails, Store};
+use demerit_backend::database::sqlite::SqliteStore;
+use demerit_backend::database::{backup, db, init_db};
+use demerit_backend::logging;
+use demerit_backend::services::reports::ReportService;
+use demerit_backend::services::users::{NewUser, UserService};
+use demerit_backend::services::{import, password, secret};
+
+const USAGE: &str = "usage: demerit-admin <command> [options]
+
+commands:
+  create-user --role <admin|teacher|student|parent> --username <name> --email <address>
+              --first-name <name> --last-name <name> [--password <password>]
+              [--subject <subject> --department <department>]   teachers
+              [--grade <level> --class <section>]               students
+  reset-password <username or email> [--password <password>]
+  migrate status | up [--dry-run] | down <version> [--dry-run]
+  reset-db --yes
+  import-csv <file>
+  export <demerits | students> [--output <file>]
+  backup [<file>]
+  list-backups
+  verify-backup <file or snapshot name>
+  restore <file or snapshot name> --yes
+
+Without --password, a random password is generated and printed once. Generated
+passwords, and any password set by reset-password, must be changed at the next
+login.
+
+With the postgres backend, reset-db, backup and restore are not available; they
+work on the SQLite database.";
+
+/// Length of generated passwords.
+const GENERATED_PASSWORD_LENGTH: usize = 16;
+
+/// Positional arguments, `--name value` options and bare `--flag`s.
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    /// Parses `args`, rejecting options and flags the command does not take.
+    fn parse(args: &[String], options: &[&str], flags: &[&str]) -> Result<Args, String> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: HashMap::new(),
+            flags: Vec::new(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if flags.contains(&name) => parsed.flags.push(name.to_string()),
+                Some(name) if options.contains(&name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("--{} needs a value", name))?;
+                    parsed.options.insert(name.to_string(), value.clone());
+                }
+                Some(_) => return Err(format!("Unknown option: {}", arg)),
+                None => parsed.positional.push(arg.clone()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn required(&self, name: &str) -> Result<&str, String> {
+        self.option(name)
+            .ok_or_else(|| format!("--{} is required", name))
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    fn positional(&self) -> Vec<&str> {
+        self.positional.iter().map(String::as_str).collect()
+    }
+}
+
+/// Opens the configured SQLite database, creating its directory if needed.
+/// Fails for the postgres backend, which has no backups.
+fn open_database() -> Result<Connection, String> {
+    require_sqlite()?;
+    let path = &config::get().database.path;
+    db::create_parent_dir(path)?;
+    db::open(path).map_err(|e| format!("Failed to open database: {}", e))
+}
+
+/// Opens the database for commands that need the current schema.
+fn open_migrated_database() -> Result<Connection, String> {
+    let conn = open_database()?;
+    if !migrations::pending(&conn)?.is_empty() {
+        return Err(
+            "The database has pending migrations; run `demerit-admin migrate up` first".to_string(),
+        );
+    }
+    Ok(conn)
+}
+
+fn require_sqlite() -> Result<(), String> {
+    match config::get().database.backend {
+        DatabaseBackend::Sqlite => Ok(()),
+        DatabaseBackend::Postgres => {
+            Err("This command is only available with the sqlite backend".to_string())
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn open_postgres() -> Result<PostgresStore, String> {
+    let database = &config::get().database;
+    let url = database.url.as_deref().unwrap_or_default();
+    PostgresStore::connect(url, database.pool_size)
+}
+
+/// Opens the store of the configured backend, for commands that need the
+/// current schema.
+fn open_store() -> Result<Box<dyn Store>, String> {
+    match config::get().database.backend {
+        DatabaseBackend::Sqlite => {
+            open_migrated_database()?;
+            let pool = db::create_pool(&config::get().database)?;
+            Ok(Box::new(SqliteStore::new(pool)))
+        }
+        #[cfg(feature = "postgres")]
+        DatabaseBackend::Postgres => {
+            let store = open_postgres()?;
+            if !store.pending()?.is_empty() {
+                return Err(
+                    "The database has pending migrations; run `demerit-admin migrate up` first"
+                        .to_string(),
+                );
+            }
+            Ok(Box::new(store))
+        }
+        #[cfg(not(feature = "postgres"))]
+        DatabaseBackend::Postgres => Err("This build has no PostgreSQL support".to_string()),
+    }
+}
+
+/// The password given with `--password`, or a generated one that has to be
+/// changed at the next login.
+fn chosen_or_generated_password(args: &Args) -> (String, bool) {
+    match args.option("password") {
+        Some(password) => (password.to_string(), false),
+        None => (secret::generate(GENERATED_PASSWORD_LENGTH), true),
+    }
+}
+
+fn create_user(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(
+        args,
+        &[
+            "role",
+            "username",
+            "email",
+            "first-name",
+            "last-name",
+            "password",
+            "subject",
+            "department",
+            "grade",
+            "class",
+        ],
+        &[],
+    )?;
+
+    let details = match args.required("role")? {
+        "admin" => RoleDetails::Admin,
+        "parent" => RoleDetails::Parent,
+        "teacher" => RoleDetails::Teacher {
+            subject: args.required("subject")?.to_string(),
+            department: args.required("department")?.to_string(),
+        },
+        "student" => RoleDetails::Student {
+            grade_level: args
+                .required("grade")?
+                .parse()
+                .map_err(|_| "--grade must be a number".to_string())?,
+            class_section: args.required("class")?.to_string(),
+        },
+        other => return Err(format!("Unknown role: {}", other)),
+    };
+    let (password, generated) = chosen_or_generated_password(&args);
+
+    let store = open_store()?;
+    let user_id = UserService::new(store.as_ref()).create_user(NewUser {
+        username: args.required("username")?.trim().to_string(),
+        email: args.required("email")?.trim().to_string(),
+        first_name: args.required("first-name")?.trim().to_string(),
+        last_name: args.required("last-name")?.trim().to_string(),
+        password: password.clone(),
+        must_change_password: generated,
+        details,
+    })?;
+
+    println!("Created user {}", user_id);
+    if generated {
+        println!("Temporary password: {}", password);
+    }
+    Ok(())
+}
+
+fn reset_password(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["password"], &[])?;
+    let [user] = args.positional()[..] else {
+        return Err(USAGE.to_string());
+    };
+
+    let store = open_store()?;
+    let user_id = UserService::new(store.as_ref()).find(user)?;
+    let (password, generated) = chosen_or_generated_password(&args);
+    password::set_temporary_password(store.as_ref(), user_id, &password)?;
+
+    println!(
+        "Password reset for user {}; all sessions signed out",
+        user_id
+    );
+    if generated {
+        println!("Temporary password: {}", password);
+    }
+    Ok(())
+}
+
+fn print_migrations(heading: &str, migrations: &[&Migration], sql: impl Fn(&Migration) -> &str) {
+    if migrations.is_empty() {
+        println!("{}: none", heading);
+        return;
+    }
+    println!("{}:", heading);
+    for migration in migrations {
+        println!("  {:>4}  {}", migration.version, migration.name);
+        for line in sql(migration).lines() {
+            println!("        {}", line);
+        }
+    }
+}
+
+/// `--dry-run` prints what would run, with its SQL, and changes nothing.
+fn migrate(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[], &["dry-run"])?;
+    let dry_run = args.flag("dry-run");
+    #[cfg(feature = "postgres")]
+    if config::get().database.backend == DatabaseBackend::Postgres {
+        return migrate_postgres(&args, dry_run);
+    }
+    let conn = open_database()?;
+
+    match args.positional()[..] {
+        ["status"] => {
+            println!("Current version: {}", migrations::current_version(&conn)?);
+            let pending = migrations::pending(&conn)?;
+            print_migrations("Pending migrations", &pending, |_| "");
+        }
+        ["up"] if dry_run => {
+            let pending = migrations::pending(&conn)?;
+            print_migrations("Would apply", &pending, |m| m.up);
+        }
+        ["up"] => {
+            let applied = migrations::migrate_up(&conn)?;
+            print_migrations("Applied", &applied, |_| "");
+        }
+        ["down", target] => {
+            let target: i64 = target
+                .parse()
+                .map_err(|_| format!("Invalid target version: {}", target))?;
+            if dry_run {
+                let reverted = migrations::to_revert(&conn, target)?;
+                print_migrations("Would revert", &reverted, |m| m.down);
+            } else {
+                let reverted = migrations::migrate_down(&conn, target)?;
+                print_migrations("Reverted", &reverted, |_| "");
+            }
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+/// `migrate` for the postgres backend, with its own migrations.
+#[cfg(feature = "postgres")]
+fn migrate_postgres(args: &Args, dry_run: bool) -> Result<(), String> {
+    let store = open_postgres()?;
+
+    match args.positional()[..] {
+        ["status"] => {
+            println!("Current version: {}", store.current_version()?);
+            print_migrations("Pending migrations", &store.pending()?, |_| "");
+        }
+        ["up"] if dry_run => print_migrations("Would apply", &store.pending()?, |m| m.up),
+        ["up"] => print_migrations("Applied", &store.migrate_up()?, |_| ""),
+        ["down", target] => {
+            let target: i64 = target
+                .parse()
+                .map_err(|_| format!("Invalid target version: {}", target))?;
+            if dry_run {
+                print_migrations("Would revert", &store.to_revert(target)?, |m| m.down);
+            } else {
+                print_migrations("Reverted", &store.migrate_down(target)?, |_| "");
+            }
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+/// Refuses without `--yes`, so the database is never wiped by a mistyped
+/// command.
+fn reset_db(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[], &["yes"])?;
+    if !args.flag("yes") || !args.positional.is_empty() {
+        return Err(
+            "reset-db deletes the database and every record in it, then recreates it \
+             empty; pass --yes to confirm"
+                .to_string(),
+        );
+    }
+
+    require_sqlite()?;
+    init_db::reset_database()?;
+    println!(
+        "Database {} was reset to a fresh schema",
+        config::get().database.path
+    );
+    Ok(())
+}
+
+fn import_csv(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[], &[])?;
+    let [path] = args.positional()[..] else {
+        return Err(USAGE.to_string());
+    };
+
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let store = open_store()?;
+    let result = import::import_students(store.as_ref(), file)?;
+
+    println!(
+        "Imported {} students, {} rows failed",
+        result.success_count, result.failure_count
+    );
+    for error in &result.errors {
+        println!("  error: {}", error);
+    }
+    if !result.generated_passwords.is_empty() {
+        println!("Temporary passwords:");
+        for line in &result.generated_passwords {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+fn write_csv<T: Serialize>(out: impl Write, records: &[T]) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(out);
+    for record in records {
+        writer
+            .serialize(record)
+            .map_err(|e| format!("Failed to write CSV: {}", e))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write CSV: {}", e))
+}
+
+fn export(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["output"], &[])?;
+    let [report] = args.positional()[..] else {
+        return Err(USAGE.to_string());
+    };
+
+    let out: Box<dyn Write> = match args.option("output") {
+        Some(path) => {
+            Box::new(File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?)
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let store = open_store()?;
+    match report {
+        "demerits" => write_csv(out, &store.demerit_history()?),
+        "students" => write_csv(
+            out,
+            &ReportService::new(store.as_ref(), &config::get().points, &config::get().school)
+                .student_summaries()?,
+        ),
+        other => Err(format!("Unknown report: {}", other)),
+    }
+}
+
+/// Without a destination, writes a snapshot into the backup directory and
+/// applies retention, like the scheduled backups.
+fn backup(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[], &[])?;
+    require_sqlite()?;
+    let db_path = &config::get().database.path;
+    if !Path::new(db_path).exists() {
+        return Err(format!("There is no database at {}", db_path));
+    }
+
+    let conn = open_database()?;
+    let dest = match args.positional()[..] {
+        [dest] => {
+            backup::backup_to(&conn, Path::new(dest))?;
+            PathBuf::from(dest)
+        }
+        [] => backup::snapshot_with_retention(&conn, &config::get().backup)?,
+        _ => return Err(USAGE.to_string()),
+    };
+    println!("Backed up {} to {}", db_path, dest.display());
+    Ok(())
+}
+
+fn list_backups(args: &[String]) -> Result<(), String> {
+    Args::parse(args, &[], &[])?;
+    let dir = &config::get().backup.dir;
+    let snapshots = backup::list(Path::new(dir))?;
+    if snapshots.is_empty() {
+        println!("No backups in {}", dir);
+    }
+    for snapshot in snapshots {
+        println!("{}  {:>12} bytes", snapshot.name, snapshot.size_bytes);
+    }
+    Ok(())
+}
+
+/// A file path, or the name of a snapshot in the backup directory.
+fn backup_path(name: &str) -> PathBuf {
+    let in_backup_dir = Path::new(&config::get().backup.dir).join(name);
+    if !Path::new(name).exists() && in_backup_dir.exists() {
+        in_backup_dir
+    } else {
+        PathBuf::from(name)
+    }
+}
+
+fn verify_backup(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[], &[])?;
+    let [name] = args.positional()[..] else {
+        return Err(USAGE.to_string());
+    };
+
+    let path = backup_path(name);
+    backup::verify(&path)?;
+    println!("{} is intact", path.display());
+    Ok(())
+}
+
+/// Refuses without `--yes`. The server must be stopped first.
+fn restore(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[], &["yes"])?;
+    let [name] = args.positional()[..] else {
+        return Err(USAGE.to_string());
+    };
+    if !args.flag("yes") {
+        return Err(
+            "restore replaces the database with the backup; stop the server, then \
+             pass --yes to confirm"
+                .to_string(),
+        );
+    }
+
+    require_sqlite()?;
+    let config = config::get();
+    let source = backup_path(name);
+    let saved = backup::restore(
+        &source,
+        Path::new(&config.database.path),
+        Path::new(&config.backup.dir),
+    )?;
+
+    if let Some(saved) = saved {
+        println!("Saved the previous database as {}", saved.display());
+    }
+    println!(
+        "Restored {} from {}",
+        config.database.path,
+        source.display()
+    );
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(USAGE.to_string());
+    };
+
+    match command.as_str() {
+        "create-user" => create_user(rest),
+        "reset-password" => reset_password(rest),
+        "migrate" => migrate(rest),
+        "reset-db" => reset_db(rest),
+        "import-csv" => import_csv(rest),
+        "export" => export(rest),
+        "backup" => backup(rest),
+        "list-backups" => list_backups(rest),
+        "verify-backup" => verify_backup(rest),
+        "restore" => restore(rest),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(format!("Unknown command: {}\n\n{}", other, USAGE)),
+    }
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    logging::init_cli(&config.logging.filter);
+    config::init(config);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,392 @@
+//! Backups of the live database.
+//!
+//! Snapshots are taken with SQLite's online backup API, so they are consistent
+//! even while the server is writing. Every copy is written to a `.partial`
+//! file, checked with `PRAGMA integrity_check`, and only then renamed into
+//! place. Snapshots in the backup directory are named
+//! `demerit-YYYYMMDD-HHMMSS-mmm.db`, so sorting by name sorts by age.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use chrono::Utc;
+use rusqlite::backup::Backup;
+use rusqlite::Connection;
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::config::BackupConfig;
+use crate::database::db::{self, DbPool};
+use crate::database::migrations;
+
+const SNAPSHOT_PREFIX: &str = "demerit-";
+const SNAPSHOT_SUFFIX: &str = ".db";
+/// Pages copied per step. The source is unlocked between steps, so a long
+/// backup does not hold up writers.
+const PAGES_PER_STEP: i32 = 256;
+const STEP_PAUSE: Duration = Duration::from_millis(10);
+
+#[derive(Serialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+fn path_str(path: &Path) -> Result<&str, String> {
+    path.to_str()
+        .ok_or_else(|| format!("Path is not valid UTF-8: {}", path.display()))
+}
+
+/// Where a copy is staged before it is verified and renamed to `dest`.
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
+
+/// Copies the database behind `conn` into `dest` as a single self-contained
+/// file.
+fn copy_database(conn: &Connection, dest: &Path) -> Result<(), String> {
+    let mut target = Connection::open(dest)
+        .map_err(|e| format!("Failed to create {}: {}", dest.display(), e))?;
+    Backup::new(conn, &mut target)
+        .and_then(|backup| backup.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None))
+        .map_err(|e| format!("Backup failed: {}", e))?;
+    target
+        .pragma_update(None, "journal_mode", "DELETE")
+        .map_err(|e| format!("Failed to finalize {}: {}", dest.display(), e))
+}
+
+/// Copies into a staging file, verifies it and renames it to `dest`. The
+/// staging file is removed if anything fails.
+fn copy_verified(conn: &Connection, dest: &Path) -> Result<(), String> {
+    let partial = partial_path(dest);
+    // Left over from an interrupted backup
+    let _ = fs::remove_file(&partial);
+
+    let result = copy_database(conn, &partial)
+        .and_then(|()| verify(&partial))
+        .and_then(|()| {
+            fs::rename(&partial, dest)
+                .map_err(|e| format!("Failed to move backup into place: {}", e))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Checks that `path` is an intact database whose schema this build knows.
+pub fn verify(path: &Path) -> Result<(), String> {
+    if !path.is_file() {
+        return Err(format!("{} does not exist", path.display()));
+    }
+    let conn =
+        Connection::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| format!("{} is not a readable database: {}", path.display(), e))?;
+    if integrity != "ok" {
+        return Err(format!(
+            "{} failed the integrity check: {}",
+            path.display(),
+            integrity
+        ));
+    }
+
+    if migrations::current_version(&conn)? == 0 {
+        return Err(format!(
+            "{} does not contain a Demerit System database",
+            path.display()
+        ));
+    }
+    // Rejects snapshots from a newer build
+    migrations::pending(&conn)?;
+    Ok(())
+}
+
+/// Writes a verified copy of the database behind `conn` to `dest`. Safe
+/// while the server is running; refuses to overwrite an existing file.
+pub fn backup_to(conn: &Connection, dest: &Path) -> Result<(), String> {
+    if dest.exists() {
+        return Err(format!("{} already exists", dest.display()));
+    }
+    db::create_parent_dir(path_str(dest)?)?;
+    copy_verified(conn, dest)
+}
+
+/// Writes a timestamped snapshot into `dir` and returns its path.
+pub fn snapshot(conn: &Connection, dir: &Path) -> Result<PathBuf, String> {
+    let name = format!(
+        "{}{}{}",
+        SNAPSHOT_PREFIX,
+        Utc::now().format("%Y%m%d-%H%M%S-%3f"),
+        SNAPSHOT_SUFFIX
+    );
+    let dest = dir.join(name);
+    backup_to(conn, &dest)?;
+    Ok(dest)
+}
+
+fn is_snapshot(name: &str) -> bool {
+    name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(SNAPSHOT_SUFFIX)
+}
+
+/// Snapshots in `dir`, newest first.
+pub fn list(dir: &Path) -> Result<Vec<SnapshotInfo>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_snapshot(&name) {
+            continue;
+        }
+        let size_bytes = entry
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?
+            .len();
+        snapshots.push(SnapshotInfo { name, size_bytes });
+    }
+
+    snapshots.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(snapshots)
+}
+
+/// Deletes all but the newest `keep` snapshots in `dir` and returns the names
+/// of the deleted ones.
+pub fn prune(dir: &Path, keep: usize) -> Result<Vec<String>, String> {
+    let mut removed = Vec::new();
+    for snapshot in list(dir)?.into_iter().skip(keep) {
+        fs::remove_file(dir.join(&snapshot.name))
+            .map_err(|e| format!("Failed to delete {}: {}", snapshot.name, e))?;
+        removed.push(snapshot.name);
+    }
+    Ok(removed)
+}
+
+/// Takes a snapshot into the configured directory, then applies retention.
+pub fn snapshot_with_retention(
+    conn: &Connection,
+    config: &BackupConfig,
+) -> Result<PathBuf, String> {
+    let dir = Path::new(&config.dir);
+    let path = snapshot(conn, dir)?;
+    for name in prune(dir, config.keep)? {
+        info!(snapshot = %name, "Deleted old backup");
+    }
+    Ok(path)
+}
+
+/// Time until the next scheduled snapshot, counted from the newest one so
+/// restarts do not postpone it.
+fn next_due(config: &BackupConfig, interval: Duration) -> Duration {
+    let dir = Path::new(&config.dir);
+    let newest_age = list(dir)
+        .ok()
+        .and_then(|snapshots| snapshots.into_iter().next())
+        .and_then(|newest| fs::metadata(dir.join(newest.name)).ok())
+        .and_then(|metadata| metadata.modified().ok())
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+
+    match newest_age {
+        Some(age) => interval.saturating_sub(age),
+        None => Duration::ZERO,
+    }
+}
+
+/// Starts a background thread taking a snapshot every
+/// `config.interval_hours`, unless that is 0.
+pub fn spawn_scheduler(pool: DbPool, config: BackupConfig) {
+    if config.interval_hours == 0 {
+        info!("Scheduled backups are off");
+        return;
+    }
+    let interval = Duration::from_secs(config.interval_hours * 60 * 60);
+
+    thread::spawn(move || loop {
+        thread::sleep(next_due(&config, interval));
+
+        let result = pool
+            .get()
+            .map_err(|e| format!("Database connection error: {}", e))
+            .and_then(|conn| snapshot_with_retention(&conn, &config));
+        match result {
+            Ok(path) => info!(path = %path.display(), "Wrote scheduled backup"),
+            Err(e) => {
+                error!("Scheduled backup failed: {}", e);
+                // Do not retry in a tight loop while the newest snapshot is old
+                thread::sleep(interval);
+            }
+        }
+    });
+}
+
+/// Replaces the database at `db_path` with the snapshot at `source`. Only run
+/// this while the server is stopped.
+///
+/// The snapshot is verified, copied next to the database and verified again
+/// before the files are swapped. The database being replaced is first saved
+/// as a snapshot in `backup_dir`, so a restore can itself be undone; its path
+/// is returned.
+pub fn restore(
+    source: &Path,
+    db_path: &Path,
+    backup_dir: &Path,
+) -> Result<Option<PathBuf>, String> {
+    verify(source)?;
+
+    let staged = partial_path(db_path);
+    let _ = fs::remove_file(&staged);
+    let source_conn = Connection::open(source)
+        .map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let staged_ok = copy_database(&source_conn, &staged).and_then(|()| verify(&staged));
+    if let Err(e) = staged_ok {
+        let _ = fs::remove_file(&staged);
+        return Err(e);
+    }
+
+    let saved = if db_path.exists() {
+        let current = db::open(path_str(db_path)?)
+            .map_err(|e| format!("Failed to open {}: {}", db_path.display(), e))?;
+        let saved = snapshot(&current, backup_dir);
+        // Closing the last connection checkpoints and removes the WAL
+        drop(current);
+        match saved {
+            Ok(path) => Some(path),
+            Err(e) => {
+                let _ = fs::remove_file(&staged);
+                return Err(format!("Could not save the current database first: {}", e));
+            }
+        }
+    } else {
+        None
+    };
+
+    for suffix in ["-wal", "-shm"] {
+        let mut stale = db_path.as_os_str().to_owned();
+        stale.push(suffix);
+        let stale = PathBuf::from(stale);
+        if stale.exists() {
+            fs::remove_file(&stale)
+                .map_err(|e| format!("Failed to remove {}: {}", stale.display(), e))?;
+        }
+    }
+    fs::rename(&staged, db_path)
+        .map_err(|e| format!("Failed to move restored database into place: {}", e))?;
+
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("demerit-backup-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn database_with_category(path: &Path, name: &str) -> Connection {
+        let conn = db::open(path.to_str().unwrap()).unwrap();
+        migrations::migrate_up(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO demerit_categories (category_name, default_points) VALUES (?1, 1)",
+            [name],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn has_category(path: &Path, name: &str) -> bool {
+        Connection::open(path)
+            .unwrap()
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM demerit_categories WHERE category_name = ?1)",
+                [name],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn snapshot_is_verified_and_never_overwritten() {
+        let dir = temp_dir();
+        let conn = database_with_category(&dir.join("live.db"), "Snapshotted");
+
+        let path = snapshot(&conn, &dir.join("backups")).unwrap();
+        verify(&path).unwrap();
+        assert!(has_category(&path, "Snapshotted"));
+        assert!(!partial_path(&path).exists());
+        assert!(backup_to(&conn, &path).is_err());
+    }
+
+    #[test]
+    fn prune_keeps_newest_snapshots() {
+        let dir = temp_dir();
+        for name in [
+            "demerit-20240101-000000-000.db",
+            "demerit-20240102-000000-000.db",
+            "demerit-20240103-000000-000.db",
+            "notes.txt",
+        ] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let removed = prune(&dir, 2).unwrap();
+        assert_eq!(removed, vec!["demerit-20240101-000000-000.db"]);
+        let left: Vec<String> = list(&dir).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(
+            left,
+            vec![
+                "demerit-20240103-000000-000.db",
+                "demerit-20240102-000000-000.db"
+            ]
+        );
+        assert!(dir.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn restore_swaps_in_snapshot_and_keeps_previous_database() {
+        let dir = temp_dir();
+        let live = dir.join("live.db");
+        let backups = dir.join("backups");
+        let conn = database_with_category(&live, "Before");
+        let before = snapshot(&conn, &backups).unwrap();
+        conn.execute(
+            "INSERT INTO demerit_categories (category_name, default_points) VALUES ('After', 1)",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+
+        let saved = restore(&before, &live, &backups).unwrap().unwrap();
+        assert!(has_category(&live, "Before"));
+        assert!(!has_category(&live, "After"));
+        assert!(has_category(&saved, "After"));
+    }
+
+    #[test]
+    fn corrupt_snapshot_is_rejected_and_database_left_alone() {
+        let dir = temp_dir();
+        let live = dir.join("live.db");
+        drop(database_with_category(&live, "Kept"));
+        let bogus = dir.join("bogus.db");
+        fs::write(&bogus, b"not a database").unwrap();
+
+        assert!(verify(&bogus).is_err());
+        assert!(restore(&bogus, &live, &dir.join("backups")).is_err());
+        assert!(has_category(&live, "Kept"));
+        assert!(!partial_path(&live).exists());
+    }
+}
@@ -1,19 +1,74 @@
-use crate::models::{self, LoginRequest};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use r2d2::{ManageConnection, Pool};
 use rusqlite::{Connection, Result};
 
-pub fn get_db_connection() -> Result<Connection> {
-    Connection::open("demerit.db")
+use crate::config::DatabaseConfig;
+
+/// How long a statement waits for another writer before failing with
+/// `database is locked`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type DbPool = Pool<SqliteConnectionManager>;
+
+/// Applies the settings every connection needs: WAL so readers do not block
+/// the writer, a busy timeout instead of failing immediately on contention,
+/// and enforcement of the foreign keys declared in the migrations.
+pub fn configure(conn: &Connection) -> Result<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         PRAGMA foreign_keys = ON;",
+    )
+}
+
+/// Creates the directory holding the database file, if it is missing.
+pub fn create_parent_dir(path: &str) -> std::result::Result<(), String> {
+    match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create data directory {}: {}", dir.display(), e)),
+        _ => Ok(()),
+    }
+}
+
+/// Opens a configured connection outside the pool, for setup and tests.
+pub fn open(path: &str) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    configure(&conn)?;
+    Ok(conn)
 }
 
-pub fn execute_sql(conn: Connection, query: &str) -> Result<()> {
-    match conn.execute(query, [1i32]) {
-        Ok(updated) => {
-            println!("Updated {} rows", updated);
-            Ok(())
-        }
-        Err(e) => {
-            eprintln!("Failed to execute query: {}", e);
-            Err(e)
-        }
+/// Opens pooled connections to one SQLite file.
+pub struct SqliteConnectionManager {
+    path: String,
+}
+
+impl ManageConnection for SqliteConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> Result<Connection> {
+        open(&self.path)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> Result<()> {
+        conn.execute_batch("SELECT 1")
     }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
+
+/// Builds the pool shared by all workers.
+pub fn create_pool(config: &DatabaseConfig) -> std::result::Result<DbPool, String> {
+    Pool::builder()
+        .max_size(config.pool_size)
+        .build(SqliteConnectionManager {
+            path: config.path.clone(),
+        })
+        .map_err(|e| format!("Failed to open database pool: {}", e))
 }
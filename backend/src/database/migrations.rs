@@ -0,0 +1,252 @@
+//! Versioned schema migrations. Each migration is a pair of SQL files in
+//! `migrations/`, embedded in the binary. Applied versions are recorded in
+//! `schema_migrations`; pending ones run in order at startup, each in its own
+//! transaction.
+//!
+//! Databases created before migrations existed have the tables of
+//! `0001_initial_schema` but no `schema_migrations` table. They are adopted as
+//! being at version 1 and upgraded from there.
+
+use rusqlite::{params, Connection};
+use std::collections::BTreeSet;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+macro_rules! migration {
+    ($version:literal, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("migrations/", $name, ".up.sql")),
+            down: include_str!(concat!("migrations/", $name, ".down.sql")),
+        }
+    };
+}
+
+/// Every migration, in the order it is applied. Never edit one that has been
+/// released; add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_initial_schema"),
+    migration!(2, "0002_sessions"),
+    migration!(3, "0003_password_management"),
+    migration!(4, "0004_login_failures"),
+    migration!(5, "0005_two_factor"),
+    migration!(6, "0006_registration_invites"),
+    migration!(7, "0007_email_verification"),
+    migration!(8, "0008_demerit_revisions"),
+    migration!(9, "0009_demerit_appeals"),
+    migration!(10, "0010_escalations"),
+    migration!(11, "0011_merits"),
+    migration!(12, "0012_escalation_windows"),
+];
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        params![name],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to inspect database: {}", e))
+}
+
+/// Versions already applied, without changing the database.
+pub fn applied_versions(conn: &Connection) -> Result<BTreeSet<i64>, String> {
+    if !table_exists(conn, "schema_migrations")? {
+        // A database from before migrations is at the initial schema
+        return Ok(if table_exists(conn, "users")? {
+            BTreeSet::from([1])
+        } else {
+            BTreeSet::new()
+        });
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT version FROM schema_migrations")
+        .map_err(|e| format!("Query preparation error: {}", e))?;
+    let versions = stmt
+        .query_map([], |row| row.get(0))
+        .and_then(|mapped| mapped.collect());
+
+    versions.map_err(|e| format!("Failed to read applied migrations: {}", e))
+}
+
+/// Migrations that have not been applied yet, in order. Fails if the database
+/// has versions this build does not know about.
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>, String> {
+    let applied = applied_versions(conn)?;
+    if let Some(unknown) = applied
+        .iter()
+        .find(|version| !MIGRATIONS.iter().any(|m| m.version == **version))
+    {
+        return Err(format!(
+            "Database has migration {} applied, which this build does not know; \
+             refusing to touch a newer schema",
+            unknown
+        ));
+    }
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .collect())
+}
+
+/// Applied migrations above `target`, newest first, as `migrate_down` would
+/// revert them.
+pub fn to_revert(conn: &Connection, target: i64) -> Result<Vec<&'static Migration>, String> {
+    let applied = applied_versions(conn)?;
+    Ok(MIGRATIONS
+        .iter()
+        .rev()
+        .filter(|m| m.version > target && applied.contains(&m.version))
+        .collect())
+}
+
+/// Creates `schema_migrations`, recording the initial schema as applied when
+/// adopting a database from before migrations.
+fn ensure_migrations_table(conn: &Connection) -> Result<(), String> {
+    if table_exists(conn, "schema_migrations")? {
+        return Ok(());
+    }
+    let legacy = table_exists(conn, "users")?;
+
+    conn.execute_batch(
+        "CREATE TABLE schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );",
+    )
+    .map_err(|e| format!("Failed to create schema_migrations: {}", e))?;
+
+    if legacy {
+        conn.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)",
+            params![MIGRATIONS[0].version, MIGRATIONS[0].name],
+        )
+        .map_err(|e| format!("Failed to record initial schema: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Applies every pending migration and returns the ones that ran.
+pub fn migrate_up(conn: &Connection) -> Result<Vec<&'static Migration>, String> {
+    let pending = pending(conn)?;
+    ensure_migrations_table(conn)?;
+
+    for migration in &pending {
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        tx.execute_batch(migration.up)
+            .map_err(|e| format!("Migration {} failed: {}", migration.name, e))?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)",
+            params![migration.version, migration.name],
+        )
+        .map_err(|e| format!("Failed to record migration {}: {}", migration.name, e))?;
+        tx.commit()
+            .map_err(|e| format!("Failed to commit migration {}: {}", migration.name, e))?;
+    }
+
+    Ok(pending)
+}
+
+/// Reverts applied migrations newer than `target`, newest first, and returns
+/// the ones that ran. A target of 0 empties the database.
+pub fn migrate_down(conn: &Connection, target: i64) -> Result<Vec<&'static Migration>, String> {
+    let reverted = to_revert(conn, target)?;
+    ensure_migrations_table(conn)?;
+
+    for migration in &reverted {
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        tx.execute_batch(migration.down)
+            .map_err(|e| format!("Reverting {} failed: {}", migration.name, e))?;
+        tx.execute(
+            "DELETE FROM schema_migrations WHERE version = ?1",
+            params![migration.version],
+        )
+        .map_err(|e| format!("Failed to record revert of {}: {}", migration.name, e))?;
+        tx.commit()
+            .map_err(|e| format!("Failed to commit revert of {}: {}", migration.name, e))?;
+    }
+
+    Ok(reverted)
+}
+
+/// Highest applied version, or 0 for an empty database.
+pub fn current_version(conn: &Connection) -> Result<i64, String> {
+    Ok(applied_versions(conn)?.last().copied().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get(1))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn fresh_database_gets_every_migration() {
+        let conn = Connection::open_in_memory().unwrap();
+        let applied = migrate_up(&conn).unwrap();
+
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(current_version(&conn).unwrap(), MIGRATIONS.len() as i64);
+        assert!(pending(&conn).unwrap().is_empty());
+        assert!(migrate_up(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn legacy_database_is_adopted_and_upgraded() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].up).unwrap();
+        conn.execute(
+            "INSERT INTO users (username, password_hash, email, user_type, first_name, last_name)
+             VALUES ('old', 'x', 'old@school.edu', 'teacher', 'Old', 'User')",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(pending(&conn).unwrap().len(), MIGRATIONS.len() - 1);
+        migrate_up(&conn).unwrap();
+
+        assert!(columns(&conn, "users").contains(&"status".to_string()));
+        let status: String = conn
+            .query_row(
+                "SELECT status FROM users WHERE username = 'old'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(status, "active");
+    }
+
+    #[test]
+    fn down_migrations_revert_to_target() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate_up(&conn).unwrap();
+
+        let reverted = migrate_down(&conn, 2).unwrap();
+        assert_eq!(reverted.len(), MIGRATIONS.len() - 2);
+        assert_eq!(current_version(&conn).unwrap(), 2);
+        assert!(!columns(&conn, "users").contains(&"must_change_password".to_string()));
+
+        migrate_up(&conn).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), MIGRATIONS.len() as i64);
+    }
+}
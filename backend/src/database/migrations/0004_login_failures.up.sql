@@ -0,0 +1,9 @@
+-- Recent failed logins, counted per email address and per client address
+CREATE TABLE login_failures (
+    scope TEXT CHECK (scope IN ('account', 'ip')) NOT NULL,
+    subject TEXT NOT NULL,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    last_failed_at TIMESTAMP,
+    locked_until TIMESTAMP,
+    PRIMARY KEY (scope, subject)
+);
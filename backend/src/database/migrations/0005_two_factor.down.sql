@@ -0,0 +1,3 @@
+DROP TABLE totp_recovery_codes;
+DROP TABLE user_totp;
+ALTER TABLE users DROP COLUMN two_factor_required;
@@ -0,0 +1,21 @@
+-- Set by an admin to make two-factor authentication mandatory
+ALTER TABLE users ADD COLUMN two_factor_required INTEGER NOT NULL DEFAULT 0;
+
+-- TOTP secrets for two-factor authentication. A secret is pending until a
+-- code from it has been confirmed, which sets enabled_at.
+CREATE TABLE user_totp (
+    user_id INTEGER PRIMARY KEY,
+    secret TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    enabled_at TIMESTAMP,
+    last_used_step INTEGER,
+    FOREIGN KEY (user_id) REFERENCES users (user_id)
+);
+
+-- Single-use recovery codes for users who lose their authenticator
+CREATE TABLE totp_recovery_codes (
+    code_hash TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    used_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (user_id)
+);
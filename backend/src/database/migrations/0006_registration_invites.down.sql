@@ -0,0 +1,2 @@
+DROP TABLE invite_students;
+DROP TABLE invite_codes;
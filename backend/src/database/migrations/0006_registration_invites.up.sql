@@ -0,0 +1,23 @@
+-- Invite codes for parent registration, created by admins. Only a hash of
+-- the code is stored. If email is set, only that address may redeem it.
+CREATE TABLE invite_codes (
+    invite_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    code_hash TEXT UNIQUE NOT NULL,
+    created_by INTEGER NOT NULL,
+    email TEXT,
+    max_uses INTEGER NOT NULL DEFAULT 1,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    FOREIGN KEY (created_by) REFERENCES users (user_id)
+);
+
+-- Students a parent is linked to when registering with an invite
+CREATE TABLE invite_students (
+    invite_id INTEGER NOT NULL,
+    student_id INTEGER NOT NULL,
+    PRIMARY KEY (invite_id, student_id),
+    FOREIGN KEY (invite_id) REFERENCES invite_codes (invite_id),
+    FOREIGN KEY (student_id) REFERENCES students (student_id)
+);
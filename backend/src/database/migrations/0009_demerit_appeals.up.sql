@@ -0,0 +1,17 @@
+-- At most one appeal per demerit. It moves from 'open' to 'under_review' and
+-- is closed as 'upheld' (the demerit stands) or 'overturned' (the demerit was
+-- voided or its points reduced).
+CREATE TABLE demerit_appeals (
+    appeal_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    demerit_id INTEGER NOT NULL UNIQUE,
+    filed_by INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    status TEXT CHECK (status IN ('open', 'under_review', 'upheld', 'overturned')) NOT NULL DEFAULT 'open',
+    reviewed_by INTEGER,
+    decision_comment TEXT,
+    filed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    decided_at TIMESTAMP,
+    FOREIGN KEY (demerit_id) REFERENCES demerit_records (demerit_id),
+    FOREIGN KEY (filed_by) REFERENCES users (user_id),
+    FOREIGN KEY (reviewed_by) REFERENCES users (user_id)
+);
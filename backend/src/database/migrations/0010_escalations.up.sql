@@ -0,0 +1,38 @@
+-- Thresholds set by admins. A rule counts the points of a student's demerits
+-- issued in the last window_days days, or in the current term when
+-- window_days is NULL, and fires when a new demerit takes that total to
+-- points or above.
+CREATE TABLE escalation_rules (
+    rule_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    points INTEGER NOT NULL CHECK (points > 0),
+    window_days INTEGER CHECK (window_days > 0),
+    action TEXT CHECK (action IN ('warning_letter', 'detention', 'parent_meeting', 'suspension_review')) NOT NULL,
+    assigned_to INTEGER NOT NULL,
+    active INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (assigned_to) REFERENCES users (user_id)
+);
+
+-- One per rule crossing, copying the rule's action and assignee at the time.
+CREATE TABLE escalations (
+    escalation_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule_id INTEGER NOT NULL,
+    student_id INTEGER NOT NULL,
+    demerit_id INTEGER NOT NULL,
+    points INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    assigned_to INTEGER NOT NULL,
+    status TEXT CHECK (status IN ('open', 'resolved')) NOT NULL DEFAULT 'open',
+    resolution TEXT,
+    resolved_by INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    resolved_at TIMESTAMP,
+    FOREIGN KEY (rule_id) REFERENCES escalation_rules (rule_id),
+    FOREIGN KEY (student_id) REFERENCES students (student_id),
+    FOREIGN KEY (demerit_id) REFERENCES demerit_records (demerit_id),
+    FOREIGN KEY (assigned_to) REFERENCES users (user_id),
+    FOREIGN KEY (resolved_by) REFERENCES users (user_id)
+);
+
+CREATE INDEX idx_escalations_status ON escalations (status);
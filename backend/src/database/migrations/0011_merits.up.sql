@@ -0,0 +1,47 @@
+-- Merits reward good behaviour. Their points offset demerit points in net
+-- totals, at the rate set by points.merits_per_demerit_point.
+CREATE TABLE merit_categories (
+    category_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    category_name TEXT NOT NULL,
+    description TEXT,
+    default_points INTEGER NOT NULL
+);
+
+CREATE TABLE merit_records (
+    merit_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    student_id INTEGER NOT NULL,
+    teacher_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    points INTEGER NOT NULL CHECK (points > 0),
+    description TEXT,
+    date_awarded TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (student_id) REFERENCES students (student_id),
+    FOREIGN KEY (teacher_id) REFERENCES teachers (teacher_id),
+    FOREIGN KEY (category_id) REFERENCES merit_categories (category_id)
+);
+
+CREATE INDEX idx_merit_records_student ON merit_records (student_id);
+
+INSERT INTO
+    merit_categories (category_name, description, default_points)
+VALUES
+    (
+        'Helping Others',
+        'Went out of their way to help a classmate or staff member',
+        1
+    ),
+    (
+        'Academic Excellence',
+        'Outstanding work or marked improvement in class',
+        2
+    ),
+    (
+        'Leadership',
+        'Took the lead in a class, team or school activity',
+        2
+    ),
+    (
+        'Community Service',
+        'Volunteered for the school or the wider community',
+        3
+    );
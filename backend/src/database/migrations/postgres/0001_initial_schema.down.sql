@@ -0,0 +1,7 @@
+DROP TABLE demerit_records;
+DROP TABLE demerit_categories;
+DROP TABLE parent_student;
+DROP TABLE parents;
+DROP TABLE teachers;
+DROP TABLE students;
+DROP TABLE users;
@@ -0,0 +1,89 @@
+-- Accounts and the records the repositories read and write. Sign-in state
+-- (sessions, lockouts, two-factor, invites, verification) is SQLite-only for
+-- now.
+CREATE TABLE users (
+    user_id SERIAL PRIMARY KEY,
+    username TEXT UNIQUE NOT NULL,
+    password_hash TEXT NOT NULL,
+    email TEXT UNIQUE NOT NULL,
+    user_type TEXT NOT NULL CHECK (
+        user_type IN ('admin', 'teacher', 'student', 'parent')
+    ),
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    must_change_password BOOLEAN NOT NULL DEFAULT FALSE,
+    two_factor_required BOOLEAN NOT NULL DEFAULT FALSE,
+    status TEXT NOT NULL DEFAULT 'active' CHECK (status IN ('pending', 'active'))
+);
+
+CREATE TABLE students (
+    student_id SERIAL PRIMARY KEY,
+    user_id INTEGER UNIQUE REFERENCES users (user_id),
+    grade_level INTEGER NOT NULL,
+    class_section TEXT NOT NULL
+);
+
+CREATE TABLE teachers (
+    teacher_id SERIAL PRIMARY KEY,
+    user_id INTEGER UNIQUE REFERENCES users (user_id),
+    subject TEXT NOT NULL,
+    department TEXT NOT NULL
+);
+
+CREATE TABLE parents (
+    parent_id SERIAL PRIMARY KEY,
+    user_id INTEGER UNIQUE REFERENCES users (user_id)
+);
+
+CREATE TABLE parent_student (
+    id SERIAL PRIMARY KEY,
+    parent_id INTEGER REFERENCES parents (parent_id),
+    student_id INTEGER REFERENCES students (student_id)
+);
+
+CREATE TABLE demerit_categories (
+    category_id SERIAL PRIMARY KEY,
+    category_name TEXT NOT NULL,
+    description TEXT,
+    default_points INTEGER NOT NULL
+);
+
+CREATE TABLE demerit_records (
+    demerit_id SERIAL PRIMARY KEY,
+    student_id INTEGER NOT NULL REFERENCES students (student_id),
+    teacher_id INTEGER NOT NULL REFERENCES teachers (teacher_id),
+    category_id INTEGER NOT NULL REFERENCES demerit_categories (category_id),
+    points INTEGER NOT NULL,
+    description TEXT,
+    date_issued TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO
+    demerit_categories (category_name, description, default_points)
+VALUES
+    (
+        'Late to Class',
+        'Student arrived late to class without valid reason',
+        1
+    ),
+    (
+        'Misconduct',
+        'Inappropriate behavior during class',
+        2
+    ),
+    (
+        'Incomplete Homework',
+        'Failed to complete assigned homework',
+        1
+    ),
+    (
+        'Dress Code Violation',
+        'Not following school dress code',
+        1
+    ),
+    (
+        'Disruptive Behavior',
+        'Causing disruption during school activities',
+        3
+    );
@@ -0,0 +1,3 @@
+DROP TABLE demerit_revisions;
+ALTER TABLE demerit_records DROP COLUMN void_reason;
+ALTER TABLE demerit_records DROP COLUMN voided_at;
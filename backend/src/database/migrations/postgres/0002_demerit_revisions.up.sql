@@ -0,0 +1,28 @@
+-- Voided demerits stay on record but no longer count towards any total
+ALTER TABLE demerit_records ADD COLUMN voided_at TIMESTAMP;
+ALTER TABLE demerit_records ADD COLUMN void_reason TEXT;
+
+-- Every change to a demerit: the values it had afterwards, who made the
+-- change and why. Demerits issued before this table existed get a 'created'
+-- revision from their current values.
+CREATE TABLE demerit_revisions (
+    revision_id SERIAL PRIMARY KEY,
+    demerit_id INTEGER NOT NULL REFERENCES demerit_records (demerit_id),
+    changed_by INTEGER NOT NULL REFERENCES users (user_id),
+    action TEXT NOT NULL CHECK (action IN ('created', 'edited', 'voided')),
+    student_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    points INTEGER NOT NULL,
+    description TEXT,
+    reason TEXT,
+    changed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_demerit_revisions_demerit_id ON demerit_revisions (demerit_id);
+
+INSERT INTO demerit_revisions
+    (demerit_id, changed_by, action, student_id, category_id, points, description, changed_at)
+SELECT dr.demerit_id, t.user_id, 'created', dr.student_id, dr.category_id, dr.points,
+       dr.description, dr.date_issued
+FROM demerit_records dr
+JOIN teachers t ON dr.teacher_id = t.teacher_id;
@@ -0,0 +1 @@
+DROP TABLE demerit_appeals;
@@ -0,0 +1,15 @@
+-- At most one appeal per demerit. It moves from 'open' to 'under_review' and
+-- is closed as 'upheld' (the demerit stands) or 'overturned' (the demerit was
+-- voided or its points reduced).
+CREATE TABLE demerit_appeals (
+    appeal_id SERIAL PRIMARY KEY,
+    demerit_id INTEGER NOT NULL UNIQUE REFERENCES demerit_records (demerit_id),
+    filed_by INTEGER NOT NULL REFERENCES users (user_id),
+    reason TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'under_review', 'upheld', 'overturned')),
+    reviewed_by INTEGER REFERENCES users (user_id),
+    decision_comment TEXT,
+    filed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    decided_at TIMESTAMP
+);
@@ -0,0 +1,33 @@
+-- Thresholds set by admins. A rule counts the points of a student's demerits
+-- issued in the last window_days days, or in the current term when
+-- window_days is NULL, and fires when a new demerit takes that total to
+-- points or above.
+CREATE TABLE escalation_rules (
+    rule_id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    points INTEGER NOT NULL CHECK (points > 0),
+    window_days INTEGER CHECK (window_days > 0),
+    action TEXT NOT NULL
+        CHECK (action IN ('warning_letter', 'detention', 'parent_meeting', 'suspension_review')),
+    assigned_to INTEGER NOT NULL REFERENCES users (user_id),
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- One per rule crossing, copying the rule's action and assignee at the time.
+CREATE TABLE escalations (
+    escalation_id SERIAL PRIMARY KEY,
+    rule_id INTEGER NOT NULL REFERENCES escalation_rules (rule_id),
+    student_id INTEGER NOT NULL REFERENCES students (student_id),
+    demerit_id INTEGER NOT NULL REFERENCES demerit_records (demerit_id),
+    points INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    assigned_to INTEGER NOT NULL REFERENCES users (user_id),
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'resolved')),
+    resolution TEXT,
+    resolved_by INTEGER REFERENCES users (user_id),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    resolved_at TIMESTAMP
+);
+
+CREATE INDEX idx_escalations_status ON escalations (status);
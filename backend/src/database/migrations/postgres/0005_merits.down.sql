@@ -0,0 +1,2 @@
+DROP TABLE merit_records;
+DROP TABLE merit_categories;
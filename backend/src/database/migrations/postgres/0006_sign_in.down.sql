@@ -0,0 +1,8 @@
+DROP TABLE email_verifications;
+DROP TABLE invite_students;
+DROP TABLE invite_codes;
+DROP TABLE totp_recovery_codes;
+DROP TABLE user_totp;
+DROP TABLE login_failures;
+DROP TABLE password_resets;
+DROP TABLE sessions;
@@ -0,0 +1,84 @@
+-- Sign-in state, so the HTTP server can run on PostgreSQL: sessions, password
+-- resets, login lockouts, two-factor, invites and email verification.
+
+-- Login sessions, one per signed-in device. Only a hash of the current
+-- refresh token is stored; it is replaced every time the token is rotated.
+CREATE TABLE sessions (
+    session_id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    refresh_token_hash TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    last_refreshed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP
+);
+
+CREATE INDEX idx_sessions_user_id ON sessions (user_id);
+
+-- Single-use password reset tokens issued by an admin
+CREATE TABLE password_resets (
+    token_hash TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    issued_by INTEGER NOT NULL REFERENCES users (user_id),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP
+);
+
+-- Recent failed logins, counted per email address and per client address
+CREATE TABLE login_failures (
+    scope TEXT NOT NULL CHECK (scope IN ('account', 'ip')),
+    subject TEXT NOT NULL,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    last_failed_at TIMESTAMP,
+    locked_until TIMESTAMP,
+    PRIMARY KEY (scope, subject)
+);
+
+-- TOTP secrets for two-factor authentication. A secret is pending until a
+-- code from it has been confirmed, which sets enabled_at.
+CREATE TABLE user_totp (
+    user_id INTEGER PRIMARY KEY REFERENCES users (user_id),
+    secret TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    enabled_at TIMESTAMP,
+    last_used_step BIGINT
+);
+
+-- Single-use recovery codes for users who lose their authenticator
+CREATE TABLE totp_recovery_codes (
+    code_hash TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    used_at TIMESTAMP
+);
+
+-- Invite codes for parent registration, created by admins. Only a hash of
+-- the code is stored. If email is set, only that address may redeem it.
+CREATE TABLE invite_codes (
+    invite_id SERIAL PRIMARY KEY,
+    code_hash TEXT UNIQUE NOT NULL,
+    created_by INTEGER NOT NULL REFERENCES users (user_id),
+    email TEXT,
+    max_uses INTEGER NOT NULL DEFAULT 1,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP
+);
+
+-- Students a parent is linked to when registering with an invite
+CREATE TABLE invite_students (
+    invite_id INTEGER NOT NULL REFERENCES invite_codes (invite_id),
+    student_id INTEGER NOT NULL REFERENCES students (student_id),
+    PRIMARY KEY (invite_id, student_id)
+);
+
+-- Single-use links confirming the email address of a self-registered
+-- account, which stays pending until one is used
+CREATE TABLE email_verifications (
+    token_hash TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP
+);
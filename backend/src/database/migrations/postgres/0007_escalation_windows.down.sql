@@ -0,0 +1,2 @@
+DROP INDEX idx_escalations_window;
+ALTER TABLE escalations DROP COLUMN window_start;
@@ -0,0 +1,6 @@
+-- Start of the rule window an escalation was raised in. A rule raises at most
+-- one escalation per student and window, even if two demerits cross its
+-- threshold at the same time.
+ALTER TABLE escalations ADD COLUMN window_start TIMESTAMP;
+
+CREATE UNIQUE INDEX idx_escalations_window ON escalations (student_id, rule_id, window_start);
@@ -1,13 +1,12 @@
+pub mod backup;
 pub mod db;
 pub mod init_db;
+pub mod migrations;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod repository;
+pub mod sqlite;
 
-pub fn initialize_db() {
-    init_db::initialize_database().unwrap();
-}
-
-pub fn connectto_db() {
-    match db::get_db_connection() {
-        Ok(_) => println!("Connected to the database"),
-        Err(e) => eprintln!("Failed to connect to the database: {}", e),
-    }
+pub fn initialize_db() -> Result<(), String> {
+    init_db::initialize_database()
 }
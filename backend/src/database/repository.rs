@@ -0,0 +1,1592 @@
+//! Storage-independent access to users, students, demerits, appeals,
+//! escalations, categories and sign-in state (sessions, passwords, lockouts,
+//! two-factor, invites and email verification).
+//!
+//! Handlers and services talk to a [`Store`] rather than to a particular
+//! database. `sqlite::SqliteStore` is always available; `postgres::PostgresStore`
+//! is built with the `postgres` feature. Which one is used comes from
+//! `database.backend` in the configuration.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{AppealStatus, EscalationAction, ParentRecord, Role, TeacherRecord, User};
+
+/// Role-specific details stored alongside an account.
+pub enum RoleDetails {
+    Admin,
+    Parent,
+    Teacher {
+        subject: String,
+        department: String,
+    },
+    Student {
+        grade_level: i32,
+        class_section: String,
+    },
+}
+
+impl RoleDetails {
+    pub fn role(&self) -> Role {
+        match self {
+            RoleDetails::Admin => Role::Admin,
+            RoleDetails::Parent => Role::Parent,
+            RoleDetails::Teacher { .. } => Role::Teacher,
+            RoleDetails::Student { .. } => Role::Student,
+        }
+    }
+}
+
+/// Changes an admin makes to an existing account. When the role changes, the
+/// old role record is removed and one is created from `details`; otherwise
+/// only a student record is updated from it.
+pub struct AccountChanges {
+    pub user_id: i32,
+    pub username: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub details: RoleDetails,
+}
+
+/// An account as listed to admins, with its student record if it has one.
+pub struct AccountListing {
+    pub user_id: i32,
+    pub username: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub user_type: String,
+    pub created_at: String,
+    pub student_id: Option<i32>,
+    pub grade_level: Option<i32>,
+    pub class_section: Option<String>,
+}
+
+/// A student linked to the parent account `parent_user_id`.
+pub struct LinkedChild {
+    pub parent_user_id: i32,
+    pub student_id: i32,
+    pub student_name: String,
+}
+
+/// An account ready to be stored; the password is already hashed.
+pub struct NewAccount {
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub must_change_password: bool,
+    pub details: RoleDetails,
+}
+
+pub struct NewDemerit {
+    pub student_id: i32,
+    pub teacher_id: i32,
+    pub category_id: i32,
+    pub points: i32,
+    pub description: String,
+}
+
+/// A demerit as stored, before any names are joined in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredDemerit {
+    pub demerit_id: i32,
+    pub student_id: i32,
+    pub teacher_id: i32,
+    pub category_id: i32,
+    pub points: i32,
+    pub description: String,
+    pub voided: bool,
+}
+
+/// New values for a demerit, and who changed it and why.
+pub struct DemeritChange {
+    pub student_id: i32,
+    pub category_id: i32,
+    pub points: i32,
+    pub description: String,
+    pub changed_by: i32,
+    pub reason: Option<String>,
+}
+
+/// One entry in the history of a demerit: the values it had after the change.
+#[derive(Serialize)]
+pub struct DemeritRevision {
+    pub revision_id: i32,
+    pub action: String,
+    pub changed_by: String,
+    pub student_id: i32,
+    pub category_id: i32,
+    pub points: i32,
+    pub description: String,
+    pub reason: Option<String>,
+    pub changed_at: String,
+}
+
+pub struct NewAppeal {
+    pub demerit_id: i32,
+    pub filed_by: i32,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredAppeal {
+    pub appeal_id: i32,
+    pub demerit_id: i32,
+    pub status: AppealStatus,
+}
+
+/// What happens to the demerit when an appeal is closed. `reason` is kept
+/// with the demerit's revision.
+pub enum AppealOutcome {
+    Upheld,
+    ReducePoints { points: i32, reason: String },
+    Void { reason: String },
+}
+
+pub struct AppealDecision {
+    pub decided_by: i32,
+    pub outcome: AppealOutcome,
+    pub comment: Option<String>,
+}
+
+/// Which appeals to list.
+#[derive(Debug, Clone, Copy)]
+pub enum AppealScope {
+    All,
+    /// Appeals against demerits issued by one teacher.
+    IssuedBy(i32),
+    /// Appeals against demerits of one student.
+    Student(i32),
+    /// Appeals against demerits of any child of one parent.
+    Children(i32),
+}
+
+#[derive(Serialize)]
+pub struct Appeal {
+    pub appeal_id: i32,
+    pub demerit_id: i32,
+    pub student_name: String,
+    pub category_name: String,
+    pub points: i32,
+    pub filed_by: String,
+    pub reason: String,
+    pub status: String,
+    pub reviewed_by: Option<String>,
+    pub decision_comment: Option<String>,
+    pub filed_at: String,
+    pub decided_at: Option<String>,
+}
+
+/// The settings of an escalation rule.
+pub struct EscalationRuleValues {
+    pub name: String,
+    pub points: i32,
+    /// `None` counts the current term.
+    pub window_days: Option<i32>,
+    pub action: EscalationAction,
+    pub assigned_to: i32,
+    pub active: bool,
+}
+
+#[derive(Serialize)]
+pub struct EscalationRule {
+    pub rule_id: i32,
+    pub name: String,
+    pub points: i32,
+    pub window_days: Option<i32>,
+    pub action: String,
+    pub assigned_to: i32,
+    pub assignee_name: String,
+    pub active: bool,
+}
+
+/// An active escalation rule, with its window resolved for a new demerit.
+pub struct EscalationCheck {
+    pub rule_id: i32,
+    /// The threshold.
+    pub points: i32,
+    /// `YYYY-MM-DD HH:MM:SS`, UTC.
+    pub window_start: String,
+    pub action: EscalationAction,
+    pub assigned_to: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredEscalation {
+    pub escalation_id: i32,
+    pub assigned_to: i32,
+    pub resolved: bool,
+}
+
+#[derive(Serialize)]
+pub struct Escalation {
+    pub escalation_id: i32,
+    pub rule_name: String,
+    pub student_id: i32,
+    pub student_name: String,
+    pub demerit_id: i32,
+    pub points: i32,
+    pub action: String,
+    pub assigned_to: i32,
+    pub assignee_name: String,
+    pub created_at: String,
+}
+
+/// Which students' demerit points to list.
+#[derive(Debug, Clone, Copy)]
+pub enum StudentScope {
+    All,
+    Student(i32),
+    /// Every child of one parent.
+    Children(i32),
+}
+
+/// A demerit as it counts towards totals.
+pub struct DemeritPoints {
+    pub student_id: i32,
+    pub points: i32,
+    pub date_issued: String,
+}
+
+pub struct NewMerit {
+    pub student_id: i32,
+    pub teacher_id: i32,
+    pub category_id: i32,
+    pub points: i32,
+    pub description: String,
+}
+
+#[derive(Serialize)]
+pub struct MeritRecord {
+    pub merit_id: i32,
+    pub student_id: i32,
+    pub student_name: String,
+    pub category_name: String,
+    pub points: i32,
+    pub teacher_name: String,
+    pub description: String,
+    pub date_awarded: String,
+}
+
+#[derive(Serialize)]
+pub struct StudentOption {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct StudentInfo {
+    pub student_id: i32,
+    pub grade_level: i32,
+    pub class_section: String,
+}
+
+#[derive(Serialize)]
+pub struct ParentOption {
+    pub parent_id: i32,
+    pub name: String,
+    pub user_id: i32,
+}
+
+#[derive(Serialize)]
+pub struct CategoryOption {
+    pub id: i32,
+    pub name: String,
+    pub default_points: i32,
+}
+
+#[derive(Serialize)]
+pub struct DemeritHistoryRecord {
+    pub demerit_id: i32,
+    pub student_name: String,
+    pub category_name: String,
+    pub points: i32,
+    pub teacher_name: String,
+    pub description: String,
+    pub date_issued: String,
+    pub voided_at: Option<String>,
+    pub void_reason: Option<String>,
+    pub appeal_status: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct StudentDemeritDetail {
+    pub demerit_id: i32,
+    pub category_name: String,
+    pub points: i32,
+    pub teacher_name: String,
+    pub description: String,
+    pub date_issued: String,
+    pub voided_at: Option<String>,
+    pub void_reason: Option<String>,
+    pub appeal_status: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StudentDemeritSummary {
+    pub student_id: i32,
+    pub student_name: String,
+    /// Points of every demerit that is not voided, however old.
+    pub total_points: i32,
+    /// Demerit points that still count under the decay policy.
+    pub effective_points: i32,
+    pub merit_points: i32,
+    /// Effective points left once merits offset them.
+    pub net_points: i32,
+    pub recent_demerit: Option<String>,
+    pub grade_level: Option<i32>,
+    pub class_section: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DemeritCategoryCount {
+    pub category_name: String,
+    pub count: i32,
+}
+
+#[derive(Serialize)]
+pub struct GradeDemeritCount {
+    pub grade: i32,
+    pub count: i32,
+}
+
+#[derive(Serialize)]
+pub struct DemeritTimePoint {
+    pub date: String,
+    pub count: i32,
+}
+
+/// A login session to store. Only a hash of its refresh token is kept.
+pub struct NewSession {
+    pub session_id: String,
+    pub user_id: i32,
+    pub refresh_token_hash: String,
+    /// `YYYY-MM-DD HH:MM:SS`, UTC.
+    pub expires_at: String,
+}
+
+/// The account behind an active session. It is read on every request, so a
+/// changed role or a new password or two-factor requirement applies at once
+/// rather than when the access token expires.
+#[derive(Debug, PartialEq)]
+pub struct SessionUser {
+    pub user_id: i32,
+    pub user_type: String,
+    pub must_change_password: bool,
+    pub must_enroll_two_factor: bool,
+}
+
+/// A single-use password reset token to store, by its hash.
+pub struct NewResetToken {
+    pub token_hash: String,
+    pub user_id: i32,
+    pub issued_by: i32,
+    pub expires_at: String,
+}
+
+/// Failed logins counted against an account and a client address together.
+#[derive(Debug, Default, PartialEq)]
+pub struct LoginThrottle {
+    /// The higher failure count of the two since the start of the window.
+    pub recent_failures: i64,
+    /// The later lock of the two, if either is still locked.
+    pub locked_until: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LockoutEntry {
+    pub scope: String,
+    pub subject: String,
+    pub failed_count: i64,
+    pub last_failed_at: Option<String>,
+    pub locked_until: Option<String>,
+    pub locked: bool,
+}
+
+/// A TOTP secret with the last time step a code from it was accepted for.
+#[derive(Debug, PartialEq)]
+pub struct TotpSecret {
+    pub secret: String,
+    pub last_used_step: Option<i64>,
+}
+
+/// A registration invite to store, by the hash of its code.
+pub struct NewInviteCode {
+    pub code_hash: String,
+    pub created_by: i32,
+    /// Lowercased; only this address may redeem the invite when set.
+    pub email: Option<String>,
+    pub max_uses: i32,
+    pub expires_at: String,
+    pub student_ids: Vec<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InviteSummary {
+    pub invite_id: i32,
+    pub created_by: i32,
+    pub email: Option<String>,
+    pub student_ids: Vec<i32>,
+    pub max_uses: i32,
+    pub use_count: i32,
+    pub created_at: String,
+    pub expires_at: String,
+    pub revoked: bool,
+}
+
+/// A self-registered parent account; the password is already hashed.
+pub struct NewRegistration {
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+    pub first_name: String,
+    pub last_name: String,
+    /// Hash of the invite code the parent registers with, if any.
+    pub invite_code_hash: Option<String>,
+}
+
+/// Why a repository call failed. Only `Other` is a fault in the store itself;
+/// the rest describe the request and carry a message fit for the client.
+#[derive(Debug, PartialEq)]
+pub enum StoreError {
+    /// The record to change does not exist.
+    NotFound(String),
+    /// The change clashes with existing data, e.g. a username already taken.
+    Conflict(String),
+    /// Anything else. The detail is only logged.
+    Other(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NotFound(message)
+            | StoreError::Conflict(message)
+            | StoreError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Database drivers report failures as strings.
+impl From<String> for StoreError {
+    fn from(message: String) -> Self {
+        StoreError::Other(message)
+    }
+}
+
+/// For `demerit-admin` and the migrations, which report errors as plain text.
+impl From<StoreError> for String {
+    fn from(error: StoreError) -> Self {
+        error.to_string()
+    }
+}
+
+pub trait UserRepository {
+    /// Looks up a user id by username or email.
+    fn find_user_id(&self, username_or_email: &str) -> Result<Option<i32>, StoreError>;
+    /// Creates an active account together with its teacher, student or
+    /// parent record, and returns the new user id.
+    fn create_user(&self, account: &NewAccount) -> Result<i32, StoreError>;
+    /// The teacher record of `user_id`, if the user is a teacher.
+    fn find_teacher_id(&self, user_id: i32) -> Result<Option<i32>, StoreError>;
+    /// Some teacher, to stand as the issuer of imported demerits.
+    fn any_teacher_id(&self) -> Result<Option<i32>, StoreError>;
+    /// Changes the role of `user_id`; false if there is no such user.
+    fn set_role(&self, user_id: i32, role: Role) -> Result<bool, StoreError>;
+    fn find_role(&self, user_id: i32) -> Result<Option<Role>, StoreError>;
+    /// Every account, oldest first.
+    fn list_accounts(&self) -> Result<Vec<AccountListing>, StoreError>;
+    /// Applies `changes` atomically; false if there is no such user.
+    fn update_account(&self, changes: &AccountChanges) -> Result<bool, StoreError>;
+}
+
+pub trait StudentRepository {
+    fn list_students(&self) -> Result<Vec<StudentOption>, StoreError>;
+    fn student_exists(&self, student_id: i32) -> Result<bool, StoreError>;
+    /// The student record of `user_id`, if the user is a student.
+    fn find_student_id(&self, user_id: i32) -> Result<Option<i32>, StoreError>;
+    fn student_info(&self, user_id: i32) -> Result<Option<StudentInfo>, StoreError>;
+    /// Creates or updates the student record of `user_id` and returns its id.
+    fn save_student(
+        &self,
+        user_id: i32,
+        grade_level: i32,
+        class_section: &str,
+    ) -> Result<i32, StoreError>;
+}
+
+pub trait ParentRepository {
+    fn list_parents(&self) -> Result<Vec<ParentOption>, StoreError>;
+    /// The parent record of `user_id`, if the user is a parent.
+    fn find_parent_id(&self, user_id: i32) -> Result<Option<i32>, StoreError>;
+    fn is_linked(&self, parent_id: i32, student_id: i32) -> Result<bool, StoreError>;
+    fn link_student(&self, parent_id: i32, student_id: i32) -> Result<(), StoreError>;
+    /// Replaces every child of `parent_id` with `student_ids`, atomically.
+    fn replace_students(&self, parent_id: i32, student_ids: &[i32]) -> Result<(), StoreError>;
+    /// The children of every parent.
+    fn linked_children(&self) -> Result<Vec<LinkedChild>, StoreError>;
+}
+
+pub trait CategoryRepository {
+    fn list_categories(&self) -> Result<Vec<CategoryOption>, StoreError>;
+    fn category_exists(&self, category_id: i32) -> Result<bool, StoreError>;
+    fn find_category_id(&self, name: &str) -> Result<Option<i32>, StoreError>;
+}
+
+pub trait DemeritRepository {
+    /// Records a demerit with its first revision and returns its id.
+    fn add_demerit(&self, demerit: &NewDemerit) -> Result<i32, StoreError>;
+    /// Like `add_demerit`, and in the same transaction raises an escalation
+    /// for every check whose threshold the demerit crosses: the student's
+    /// points in the window were below it and now reach it. Returns the
+    /// demerit id and the ids of the escalations raised.
+    fn record_demerit(
+        &self,
+        demerit: &NewDemerit,
+        checks: &[EscalationCheck],
+    ) -> Result<(i32, Vec<i32>), StoreError>;
+    fn find_demerit(&self, demerit_id: i32) -> Result<Option<StoredDemerit>, StoreError>;
+    /// Replaces the values of a demerit and records the revision.
+    fn update_demerit(&self, demerit_id: i32, change: &DemeritChange) -> Result<(), StoreError>;
+    /// Marks a demerit as voided and records the revision. A conflict if it
+    /// is already voided.
+    fn void_demerit(
+        &self,
+        demerit_id: i32,
+        changed_by: i32,
+        reason: &str,
+    ) -> Result<(), StoreError>;
+    /// Revisions of one demerit, oldest first.
+    fn demerit_revisions(&self, demerit_id: i32) -> Result<Vec<DemeritRevision>, StoreError>;
+    /// Every demerit, newest first. Voided ones are included and marked; here
+    /// and below, they never count towards totals.
+    fn demerit_history(&self) -> Result<Vec<DemeritHistoryRecord>, StoreError>;
+    /// Demerits of one student, newest first.
+    fn student_demerits(&self, student_id: i32) -> Result<Vec<StudentDemeritDetail>, StoreError>;
+    /// Demerits issued by one teacher, newest first.
+    fn teacher_demerits(&self, teacher_id: i32) -> Result<Vec<TeacherRecord>, StoreError>;
+    /// Demerits of every child of one parent, newest first.
+    fn children_demerits(&self, parent_id: i32) -> Result<Vec<ParentRecord>, StoreError>;
+    /// Points and issue dates of the demerits of the students in `scope`.
+    fn demerit_points(&self, scope: StudentScope) -> Result<Vec<DemeritPoints>, StoreError>;
+    /// Demerit and merit points per student, highest demerit total first.
+    /// Stores leave `effective_points` and `net_points` at `total_points`;
+    /// `ReportService` applies the configured policies.
+    fn student_summaries(&self) -> Result<Vec<StudentDemeritSummary>, StoreError>;
+    /// Like `student_summaries`, for one student.
+    fn student_summary(&self, student_id: i32)
+        -> Result<Option<StudentDemeritSummary>, StoreError>;
+    /// Like `student_summaries`, for the children of one parent.
+    fn children_summaries(&self, parent_id: i32) -> Result<Vec<StudentDemeritSummary>, StoreError>;
+    /// Number of demerits per category, most common first.
+    fn demerits_by_category(&self) -> Result<Vec<DemeritCategoryCount>, StoreError>;
+    /// Number of demerits per grade level, in grade order.
+    fn demerits_by_grade(&self) -> Result<Vec<GradeDemeritCount>, StoreError>;
+    /// Demerits per day, oldest first, for the first 60 days with any.
+    fn demerit_trend(&self) -> Result<Vec<DemeritTimePoint>, StoreError>;
+}
+
+pub trait AppealRepository {
+    /// Files an appeal in the open state and returns its id.
+    fn file_appeal(&self, appeal: &NewAppeal) -> Result<i32, StoreError>;
+    fn find_appeal(&self, appeal_id: i32) -> Result<Option<StoredAppeal>, StoreError>;
+    /// The appeal against a demerit, if one was filed.
+    fn demerit_appeal(&self, demerit_id: i32) -> Result<Option<StoredAppeal>, StoreError>;
+    /// Puts an open appeal under review by `reviewer`. A conflict if the
+    /// appeal is no longer open.
+    fn start_appeal_review(&self, appeal_id: i32, reviewer: i32) -> Result<(), StoreError>;
+    /// Closes an appeal under review and applies its outcome to the demerit,
+    /// atomically. A conflict if the appeal is not under review.
+    fn decide_appeal(&self, appeal_id: i32, decision: &AppealDecision) -> Result<(), StoreError>;
+    /// Appeals in `scope`, newest first.
+    fn appeals(&self, scope: AppealScope) -> Result<Vec<Appeal>, StoreError>;
+}
+
+pub trait MeritRepository {
+    fn list_merit_categories(&self) -> Result<Vec<CategoryOption>, StoreError>;
+    fn merit_category_exists(&self, category_id: i32) -> Result<bool, StoreError>;
+    /// Records a merit and returns its id.
+    fn add_merit(&self, merit: &NewMerit) -> Result<i32, StoreError>;
+    /// Merits of one student, newest first.
+    fn student_merits(&self, student_id: i32) -> Result<Vec<MeritRecord>, StoreError>;
+    /// Merits awarded by one teacher, newest first.
+    fn teacher_merits(&self, teacher_id: i32) -> Result<Vec<MeritRecord>, StoreError>;
+    /// Merits of every child of one parent, newest first.
+    fn children_merits(&self, parent_id: i32) -> Result<Vec<MeritRecord>, StoreError>;
+}
+
+pub trait EscalationRepository {
+    fn escalation_rules(&self) -> Result<Vec<EscalationRule>, StoreError>;
+    fn add_escalation_rule(&self, rule: &EscalationRuleValues) -> Result<i32, StoreError>;
+    /// Replaces the settings of a rule; false if there is no such rule.
+    fn update_escalation_rule(
+        &self,
+        rule_id: i32,
+        rule: &EscalationRuleValues,
+    ) -> Result<bool, StoreError>;
+    fn find_escalation(&self, escalation_id: i32) -> Result<Option<StoredEscalation>, StoreError>;
+    /// Open escalations, oldest first; only those assigned to `assigned_to`
+    /// when given.
+    fn open_escalations(&self, assigned_to: Option<i32>) -> Result<Vec<Escalation>, StoreError>;
+    fn resolve_escalation(
+        &self,
+        escalation_id: i32,
+        resolved_by: i32,
+        resolution: &str,
+    ) -> Result<(), StoreError>;
+}
+
+pub trait SessionRepository {
+    fn create_session(&self, session: &NewSession) -> Result<(), StoreError>;
+    /// The user of a session that is neither revoked nor expired.
+    fn active_session_owner(&self, session_id: &str) -> Result<Option<i32>, StoreError>;
+    /// Replaces the refresh token hash of an active session, but only while
+    /// `current_hash` is still the current one; false otherwise.
+    fn rotate_refresh_token(
+        &self,
+        session_id: &str,
+        current_hash: &str,
+        new_hash: &str,
+    ) -> Result<bool, StoreError>;
+    /// The account of a session that is neither revoked nor expired.
+    fn session_user(&self, session_id: &str) -> Result<Option<SessionUser>, StoreError>;
+    fn revoke_session(&self, session_id: &str) -> Result<(), StoreError>;
+    /// Revokes every open session of a user and returns how many were closed.
+    fn revoke_user_sessions(&self, user_id: i32) -> Result<usize, StoreError>;
+}
+
+pub trait CredentialRepository {
+    /// An account with what signing in needs to know about it, by email.
+    fn sign_in_account_by_email(&self, email: &str) -> Result<Option<User>, StoreError>;
+    fn sign_in_account(&self, user_id: i32) -> Result<Option<User>, StoreError>;
+    /// Stores a new password hash, sets the forced-change flag to
+    /// `must_change` and revokes every session of the user, atomically. False
+    /// if there is no such user.
+    fn set_password(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        must_change: bool,
+    ) -> Result<bool, StoreError>;
+    /// Stores a reset token, invalidating earlier unused ones of the user.
+    fn add_reset_token(&self, reset: &NewResetToken) -> Result<(), StoreError>;
+    /// Consumes a valid reset token and sets the password like
+    /// `set_password` does, atomically. Returns the user, or `None` if the
+    /// token is unknown, used or expired.
+    fn redeem_reset_token(
+        &self,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> Result<Option<i32>, StoreError>;
+}
+
+/// Failed logins per scope (`account` or `ip`) and subject. Times are
+/// `YYYY-MM-DD HH:MM:SS`, UTC.
+pub trait LoginFailureRepository {
+    /// Failures of an account and an address since `since`, and their locks.
+    fn login_throttle(
+        &self,
+        account: &str,
+        ip: &str,
+        since: &str,
+    ) -> Result<LoginThrottle, StoreError>;
+    /// Counts a failure, starting over when the last one was before `since`,
+    /// and locks the subject until `locked_until` once it reaches `limit`.
+    /// Returns whether the subject is locked.
+    fn record_login_failure(
+        &self,
+        scope: &str,
+        subject: &str,
+        since: &str,
+        locked_until: &str,
+        limit: i64,
+    ) -> Result<bool, StoreError>;
+    /// Forgets the failures of a subject; false if there were none.
+    fn clear_login_failures(&self, scope: &str, subject: &str) -> Result<bool, StoreError>;
+    /// Subjects that are locked or have failed since `since`, latest first.
+    fn login_failures(&self, since: &str) -> Result<Vec<LockoutEntry>, StoreError>;
+}
+
+pub trait TwoFactorRepository {
+    /// The confirmed TOTP secret of a user, or with `pending` the one that
+    /// still awaits confirmation.
+    fn totp_secret(&self, user_id: i32, pending: bool) -> Result<Option<TotpSecret>, StoreError>;
+    /// Stores an unconfirmed secret, replacing the user's earlier one.
+    fn save_totp_secret(&self, user_id: i32, secret: &str) -> Result<(), StoreError>;
+    /// Records that a code for `step` was accepted. False if that step or a
+    /// later one already was, so each code works once.
+    fn use_totp_step(&self, user_id: i32, step: i64) -> Result<bool, StoreError>;
+    /// Confirms the pending secret and stores the first recovery codes,
+    /// atomically.
+    fn enable_totp(&self, user_id: i32, recovery_code_hashes: &[String]) -> Result<(), StoreError>;
+    /// Replaces every recovery code of the user, atomically.
+    fn replace_recovery_codes(
+        &self,
+        user_id: i32,
+        code_hashes: &[String],
+    ) -> Result<(), StoreError>;
+    /// Marks an unused recovery code of the user as used; false if there is
+    /// no such code.
+    fn use_recovery_code(&self, user_id: i32, code_hash: &str) -> Result<bool, StoreError>;
+    /// Removes the user's secret and recovery codes.
+    fn remove_two_factor(&self, user_id: i32) -> Result<(), StoreError>;
+    /// False if there is no such user.
+    fn set_two_factor_required(&self, user_id: i32, required: bool) -> Result<bool, StoreError>;
+}
+
+pub trait RegistrationRepository {
+    /// Stores an invite with the students it links to and returns its id.
+    fn create_invite(&self, invite: &NewInviteCode) -> Result<i32, StoreError>;
+    /// Every invite, newest first.
+    fn list_invites(&self) -> Result<Vec<InviteSummary>, StoreError>;
+    /// Stops an invite from being used again; false if there is no such
+    /// invite.
+    fn revoke_invite(&self, invite_id: i32) -> Result<bool, StoreError>;
+    /// Creates a pending parent account and returns its user id. With an
+    /// invite, one use of it is consumed and the parent is linked to its
+    /// students in the same transaction. `None` if the invite is unknown,
+    /// revoked, used up, expired or meant for another address.
+    fn register_parent(&self, registration: &NewRegistration) -> Result<Option<i32>, StoreError>;
+    /// Stores an email verification token, invalidating earlier unused ones
+    /// of the user.
+    fn add_verification_token(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: &str,
+    ) -> Result<(), StoreError>;
+    /// Consumes a valid verification token and activates its account,
+    /// atomically. `None` if the token is unknown, used or expired.
+    fn redeem_verification_token(&self, token_hash: &str) -> Result<Option<i32>, StoreError>;
+    /// The pending account with `email`, unless it was sent a verification
+    /// link after `sent_after`.
+    fn pending_account(&self, email: &str, sent_after: &str) -> Result<Option<i32>, StoreError>;
+}
+
+/// Everything the application stores, behind one object.
+pub trait Store:
+    UserRepository
+    + StudentRepository
+    + ParentRepository
+    + CategoryRepository
+    + DemeritRepository
+    + AppealRepository
+    + EscalationRepository
+    + MeritRepository
+    + SessionRepository
+    + CredentialRepository
+    + LoginFailureRepository
+    + TwoFactorRepository
+    + RegistrationRepository
+    + Send
+    + Sync
+{
+}
+
+impl<T> Store for T where
+    T: UserRepository
+        + StudentRepository
+        + ParentRepository
+        + CategoryRepository
+        + DemeritRepository
+        + AppealRepository
+        + EscalationRepository
+        + MeritRepository
+        + SessionRepository
+        + CredentialRepository
+        + LoginFailureRepository
+        + TwoFactorRepository
+        + RegistrationRepository
+        + Send
+        + Sync
+{
+}
+
+/// Behaviour every [`Store`] must share, run against each implementation by
+/// its own tests. Expects a freshly migrated, otherwise empty database.
+#[cfg(test)]
+pub(crate) fn check_store(store: &dyn Store) {
+    let teacher_user = store
+        .create_user(&NewAccount {
+            username: "tsmith".to_string(),
+            email: "tsmith@school.edu".to_string(),
+            password_hash: "hash".to_string(),
+            first_name: "Tess".to_string(),
+            last_name: "Smith".to_string(),
+            must_change_password: false,
+            details: RoleDetails::Teacher {
+                subject: "Math".to_string(),
+                department: "Science".to_string(),
+            },
+        })
+        .unwrap();
+    let student_user = store
+        .create_user(&NewAccount {
+            username: "jdoe".to_string(),
+            email: "jdoe@school.edu".to_string(),
+            password_hash: "hash".to_string(),
+            first_name: "Jane".to_string(),
+            last_name: "Doe".to_string(),
+            must_change_password: true,
+            details: RoleDetails::Student {
+                grade_level: 7,
+                class_section: "A".to_string(),
+            },
+        })
+        .unwrap();
+
+    assert_eq!(store.find_user_id("jdoe").unwrap(), Some(student_user));
+    assert_eq!(
+        store.find_user_id("tsmith@school.edu").unwrap(),
+        Some(teacher_user)
+    );
+    assert_eq!(store.find_user_id("nobody").unwrap(), None);
+    assert!(matches!(
+        store.create_user(&NewAccount {
+            username: "jdoe".to_string(),
+            email: "other@school.edu".to_string(),
+            password_hash: "hash".to_string(),
+            first_name: "J".to_string(),
+            last_name: "D".to_string(),
+            must_change_password: false,
+            details: RoleDetails::Parent,
+        }),
+        Err(StoreError::Conflict(_))
+    ));
+
+    let teacher_id = store.find_teacher_id(teacher_user).unwrap().unwrap();
+    assert_eq!(store.any_teacher_id().unwrap(), Some(teacher_id));
+    assert_eq!(store.find_teacher_id(student_user).unwrap(), None);
+
+    let student_id = store.find_student_id(student_user).unwrap().unwrap();
+    assert_eq!(
+        store.save_student(student_user, 8, "B").unwrap(),
+        student_id
+    );
+    assert_eq!(store.find_student_id(teacher_user).unwrap(), None);
+    assert!(store.student_exists(student_id).unwrap());
+    assert!(!store.student_exists(student_id + 100).unwrap());
+    assert_eq!(
+        store
+            .student_info(student_user)
+            .unwrap()
+            .unwrap()
+            .grade_level,
+        8
+    );
+    let students = store.list_students().unwrap();
+    assert_eq!(students.len(), 1);
+    assert_eq!(students[0].name, "Jane Doe");
+
+    let categories = store.list_categories().unwrap();
+    assert_eq!(categories.len(), 5);
+    let late = store.find_category_id("Late to Class").unwrap().unwrap();
+    assert!(store.category_exists(late).unwrap());
+    assert_eq!(store.find_category_id("Unknown").unwrap(), None);
+
+    for points in [1, 2] {
+        store
+            .add_demerit(&NewDemerit {
+                student_id,
+                teacher_id,
+                category_id: late,
+                points,
+                description: "Late again".to_string(),
+            })
+            .unwrap();
+    }
+
+    let history = store.demerit_history().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].teacher_name, "Tess Smith");
+    assert_eq!(history[0].date_issued.len(), "2024-01-31 08:00:00".len());
+
+    let summaries = store.student_summaries().unwrap();
+    assert_eq!(summaries[0].total_points, 3);
+    assert_eq!(summaries[0].grade_level, Some(8));
+    assert_eq!(
+        summaries[0].recent_demerit.as_deref(),
+        Some("Late to Class")
+    );
+
+    let by_category = store.demerits_by_category().unwrap();
+    assert_eq!(by_category[0].category_name, "Late to Class");
+    assert_eq!(by_category[0].count, 2);
+    assert_eq!(store.demerits_by_grade().unwrap()[0].grade, 8);
+
+    assert_eq!(store.student_demerits(student_id).unwrap().len(), 2);
+
+    let first = history[1].demerit_id;
+    let stored = store.find_demerit(first).unwrap().unwrap();
+    assert_eq!((stored.points, stored.voided), (1, false));
+    assert_eq!(store.find_demerit(first + 100).unwrap(), None);
+    store
+        .update_demerit(
+            first,
+            &DemeritChange {
+                points: 4,
+                changed_by: teacher_user,
+                reason: Some("Wrong points".to_string()),
+                student_id: stored.student_id,
+                category_id: stored.category_id,
+                description: stored.description,
+            },
+        )
+        .unwrap();
+    assert_eq!(store.student_summaries().unwrap()[0].total_points, 6);
+    store
+        .void_demerit(first, teacher_user, "Issued in error")
+        .unwrap();
+    assert!(store.find_demerit(first).unwrap().unwrap().voided);
+    assert!(matches!(
+        store.void_demerit(first, teacher_user, "Again"),
+        Err(StoreError::Conflict(_))
+    ));
+    assert_eq!(store.student_summaries().unwrap()[0].total_points, 2);
+    let revisions = store.demerit_revisions(first).unwrap();
+    let actions: Vec<&str> = revisions.iter().map(|r| r.action.as_str()).collect();
+    assert_eq!(actions, ["created", "edited", "voided"]);
+    assert_eq!(revisions[1].points, 4);
+    assert_eq!(revisions[2].reason.as_deref(), Some("Issued in error"));
+    assert_eq!(revisions[0].changed_by, "Tess Smith");
+    let voided = store.student_demerits(student_id).unwrap();
+    assert_eq!(voided[1].void_reason.as_deref(), Some("Issued in error"));
+    assert_eq!(store.demerits_by_category().unwrap()[0].count, 1);
+
+    let second = history[0].demerit_id;
+    assert_eq!(store.demerit_appeal(second).unwrap(), None);
+    let appeal_id = store
+        .file_appeal(&NewAppeal {
+            demerit_id: second,
+            filed_by: student_user,
+            reason: "I was at the nurse".to_string(),
+        })
+        .unwrap();
+    assert_eq!(
+        store.demerit_appeal(second).unwrap(),
+        Some(StoredAppeal {
+            appeal_id,
+            demerit_id: second,
+            status: AppealStatus::Open,
+        })
+    );
+    assert!(matches!(
+        store.file_appeal(&NewAppeal {
+            demerit_id: second,
+            filed_by: student_user,
+            reason: "Again".to_string(),
+        }),
+        Err(StoreError::Conflict(_))
+    ));
+    store.start_appeal_review(appeal_id, teacher_user).unwrap();
+    assert!(matches!(
+        store.start_appeal_review(appeal_id, teacher_user),
+        Err(StoreError::Conflict(_))
+    ));
+    assert_eq!(
+        store.find_appeal(appeal_id).unwrap().unwrap().status,
+        AppealStatus::UnderReview
+    );
+    store
+        .decide_appeal(
+            appeal_id,
+            &AppealDecision {
+                decided_by: teacher_user,
+                outcome: AppealOutcome::ReducePoints {
+                    points: 1,
+                    reason: "Appeal overturned".to_string(),
+                },
+                comment: Some("Nurse confirmed".to_string()),
+            },
+        )
+        .unwrap();
+    assert!(matches!(
+        store.decide_appeal(
+            appeal_id,
+            &AppealDecision {
+                decided_by: teacher_user,
+                outcome: AppealOutcome::Void {
+                    reason: "Decided twice".to_string(),
+                },
+                comment: None,
+            },
+        ),
+        Err(StoreError::Conflict(_))
+    ));
+    assert_eq!(store.student_summaries().unwrap()[0].total_points, 1);
+    assert_eq!(store.demerit_revisions(second).unwrap().len(), 2);
+    assert_eq!(store.demerit_revisions(second).unwrap()[1].points, 1);
+    let appeals = store.appeals(AppealScope::Student(student_id)).unwrap();
+    assert_eq!(appeals.len(), 1);
+    assert_eq!(appeals[0].status, "overturned");
+    assert_eq!(appeals[0].filed_by, "Jane Doe");
+    assert_eq!(appeals[0].reviewed_by.as_deref(), Some("Tess Smith"));
+    assert_eq!(
+        appeals[0].decision_comment.as_deref(),
+        Some("Nurse confirmed")
+    );
+    assert!(appeals[0].decided_at.is_some());
+    assert_eq!(store.appeals(AppealScope::All).unwrap().len(), 1);
+    assert_eq!(
+        store
+            .appeals(AppealScope::IssuedBy(teacher_id))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(store
+        .appeals(AppealScope::IssuedBy(teacher_id + 100))
+        .unwrap()
+        .is_empty());
+    let demerits = store.student_demerits(student_id).unwrap();
+    assert_eq!(demerits[0].appeal_status.as_deref(), Some("overturned"));
+    assert_eq!(demerits[1].appeal_status, None);
+    assert_eq!(
+        store.teacher_demerits(teacher_id).unwrap()[0]
+            .appeal_status
+            .as_deref(),
+        Some("overturned")
+    );
+    assert_eq!(
+        store.demerit_history().unwrap()[0].appeal_status.as_deref(),
+        Some("overturned")
+    );
+    assert_eq!(
+        store.teacher_demerits(teacher_id).unwrap()[0].student_name,
+        "Jane Doe"
+    );
+
+    let parent_user = store
+        .create_user(&NewAccount {
+            username: "pdoe".to_string(),
+            email: "pdoe@school.edu".to_string(),
+            password_hash: "hash".to_string(),
+            first_name: "Pat".to_string(),
+            last_name: "Doe".to_string(),
+            must_change_password: false,
+            details: RoleDetails::Parent,
+        })
+        .unwrap();
+    let parent_id = store.find_parent_id(parent_user).unwrap().unwrap();
+    assert_eq!(store.list_parents().unwrap()[0].name, "Pat Doe");
+    assert!(!store.is_linked(parent_id, student_id).unwrap());
+    store.link_student(parent_id, student_id).unwrap();
+    assert!(store.is_linked(parent_id, student_id).unwrap());
+    let children = store.linked_children().unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(
+        (children[0].parent_user_id, children[0].student_id),
+        (parent_user, student_id)
+    );
+    assert_eq!(children[0].student_name, "Jane Doe");
+    assert_eq!(
+        store.children_summaries(parent_id).unwrap()[0].total_points,
+        1
+    );
+    assert_eq!(
+        store
+            .demerit_points(StudentScope::Children(parent_id))
+            .unwrap()
+            .iter()
+            .map(|d| d.points)
+            .sum::<i32>(),
+        1
+    );
+    assert_eq!(
+        store
+            .appeals(AppealScope::Children(parent_id))
+            .unwrap()
+            .len(),
+        1
+    );
+    let children_demerits = store.children_demerits(parent_id).unwrap();
+    assert_eq!(children_demerits.len(), 2);
+    assert!(children_demerits[1].voided_at.is_some());
+    assert_eq!(children_demerits[0].student_name, "Jane Doe");
+    assert_eq!(children_demerits[0].teacher_name, "Tess Smith");
+    assert_eq!(
+        children_demerits[0].appeal_status.as_deref(),
+        Some("overturned")
+    );
+
+    let merit_categories = store.list_merit_categories().unwrap();
+    assert_eq!(merit_categories.len(), 4);
+    let helping = merit_categories[0].id;
+    assert!(store.merit_category_exists(helping).unwrap());
+    assert!(!store.merit_category_exists(helping + 100).unwrap());
+    let merit_id = store
+        .add_merit(&NewMerit {
+            student_id,
+            teacher_id,
+            category_id: helping,
+            points: 4,
+            description: "Helped tidy the lab".to_string(),
+        })
+        .unwrap();
+    let merits = store.children_merits(parent_id).unwrap();
+    assert_eq!(merits.len(), 1);
+    assert_eq!(merits[0].merit_id, merit_id);
+    assert_eq!(merits[0].student_name, "Jane Doe");
+    assert_eq!(merits[0].teacher_name, "Tess Smith");
+    assert_eq!(merits[0].category_name, "Helping Others");
+    assert_eq!(merits[0].date_awarded.len(), "2024-01-31 08:00:00".len());
+    assert_eq!(store.student_merits(student_id).unwrap().len(), 1);
+    assert_eq!(store.teacher_merits(teacher_id).unwrap().len(), 1);
+    assert!(store.student_merits(student_id + 100).unwrap().is_empty());
+    let summary = store.student_summary(student_id).unwrap().unwrap();
+    assert_eq!(
+        (
+            summary.total_points,
+            summary.merit_points,
+            summary.net_points
+        ),
+        (1, 4, 1)
+    );
+    assert_eq!(
+        store.children_summaries(parent_id).unwrap()[0].merit_points,
+        4
+    );
+    assert!(store.student_summary(student_id + 100).unwrap().is_none());
+
+    assert!(store
+        .replace_students(parent_id, &[student_id + 100])
+        .is_err());
+    assert!(store.is_linked(parent_id, student_id).unwrap());
+    store.replace_students(parent_id, &[]).unwrap();
+    assert!(store.children_summaries(parent_id).unwrap().is_empty());
+    assert!(store
+        .demerit_points(StudentScope::Children(parent_id))
+        .unwrap()
+        .is_empty());
+    assert!(store
+        .appeals(AppealScope::Children(parent_id))
+        .unwrap()
+        .is_empty());
+    assert!(store.children_demerits(parent_id).unwrap().is_empty());
+
+    assert!(store.set_role(parent_user, Role::Teacher).unwrap());
+    assert!(!store.set_role(parent_user + 100, Role::Teacher).unwrap());
+
+    assert_eq!(store.find_role(teacher_user).unwrap(), Some(Role::Teacher));
+    assert_eq!(store.find_role(teacher_user + 100).unwrap(), None);
+    let counted = store.demerit_points(StudentScope::All).unwrap();
+    assert_eq!(counted.iter().map(|d| d.points).sum::<i32>(), 1);
+    assert!(counted.iter().all(|d| d.student_id == student_id));
+    assert_eq!(counted[0].date_issued.len(), "2024-01-31 08:00:00".len());
+    assert_eq!(
+        store
+            .demerit_points(StudentScope::Student(student_id))
+            .unwrap()
+            .len(),
+        counted.len()
+    );
+    assert!(store
+        .demerit_points(StudentScope::Student(student_id + 100))
+        .unwrap()
+        .is_empty());
+
+    let mut rule = EscalationRuleValues {
+        name: "Detention at 5".to_string(),
+        points: 5,
+        window_days: Some(30),
+        action: EscalationAction::Detention,
+        assigned_to: teacher_user,
+        active: true,
+    };
+    let rule_id = store.add_escalation_rule(&rule).unwrap();
+    rule.window_days = None;
+    assert!(store.update_escalation_rule(rule_id, &rule).unwrap());
+    assert!(!store.update_escalation_rule(rule_id + 100, &rule).unwrap());
+    let rules = store.escalation_rules().unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].window_days, None);
+    assert_eq!(rules[0].action, "detention");
+    assert_eq!(rules[0].assignee_name, "Tess Smith");
+    assert!(rules[0].active);
+
+    // The student has 1 point; 4 more cross 5, but not 1 (already reached) or 9
+    let check = |points: i32| EscalationCheck {
+        rule_id,
+        points,
+        window_start: "2000-01-01 00:00:00".to_string(),
+        action: EscalationAction::Detention,
+        assigned_to: teacher_user,
+    };
+    let late_again = NewDemerit {
+        student_id,
+        teacher_id,
+        category_id: late,
+        points: 4,
+        description: "Late again".to_string(),
+    };
+    let (crossing, raised) = store
+        .record_demerit(&late_again, &[check(5), check(1), check(9)])
+        .unwrap();
+    assert_eq!(raised.len(), 1);
+    let escalation_id = raised[0];
+    assert_eq!(store.find_demerit(crossing).unwrap().unwrap().points, 4);
+    assert_eq!(store.demerit_revisions(crossing).unwrap().len(), 1);
+
+    // Crossing again in the same window raises nothing new
+    store
+        .void_demerit(crossing, teacher_user, "Issued in error")
+        .unwrap();
+    let (recrossing, raised) = store.record_demerit(&late_again, &[check(5)]).unwrap();
+    assert!(raised.is_empty());
+    store
+        .void_demerit(recrossing, teacher_user, "Issued in error")
+        .unwrap();
+
+    let open = store.open_escalations(Some(teacher_user)).unwrap();
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0].student_name, "Jane Doe");
+    assert_eq!(open[0].rule_name, "Detention at 5");
+    assert!(store
+        .open_escalations(Some(teacher_user + 100))
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        store.find_escalation(escalation_id).unwrap(),
+        Some(StoredEscalation {
+            escalation_id,
+            assigned_to: teacher_user,
+            resolved: false,
+        })
+    );
+    store
+        .resolve_escalation(escalation_id, teacher_user, "Detention served")
+        .unwrap();
+    assert!(
+        store
+            .find_escalation(escalation_id)
+            .unwrap()
+            .unwrap()
+            .resolved
+    );
+    assert!(store.open_escalations(None).unwrap().is_empty());
+
+    let trend = store.demerit_trend().unwrap();
+    assert_eq!(trend.len(), 1);
+    assert_eq!(trend[0].count, 1);
+    assert_eq!(trend[0].date.len(), "2024-01-31".len());
+
+    let accounts = store.list_accounts().unwrap();
+    assert_eq!(accounts.len(), 3);
+    assert_eq!(accounts[1].user_id, student_user);
+    assert_eq!(
+        (accounts[1].student_id, accounts[1].grade_level),
+        (Some(student_id), Some(8))
+    );
+    assert_eq!(accounts[2].user_type, "teacher");
+    assert_eq!(accounts[2].student_id, None);
+    assert_eq!(accounts[0].created_at.len(), "2024-01-31 08:00:00".len());
+
+    let changes = |user_id: i32, username: &str, details: RoleDetails| AccountChanges {
+        user_id,
+        username: username.to_string(),
+        email: format!("{}@school.edu", username),
+        first_name: "Pat".to_string(),
+        last_name: "Doe".to_string(),
+        details,
+    };
+    let student = RoleDetails::Student {
+        grade_level: 9,
+        class_section: "C".to_string(),
+    };
+    assert!(store
+        .update_account(&changes(student_user, "jdoe", student))
+        .unwrap());
+    let jane = store.student_info(student_user).unwrap().unwrap();
+    assert_eq!((jane.grade_level, jane.class_section.as_str()), (9, "C"));
+
+    // A parent who becomes a teacher leaves no parent records behind
+    let guardian = store
+        .create_user(&NewAccount {
+            username: "gdoe".to_string(),
+            email: "gdoe@school.edu".to_string(),
+            password_hash: "hash".to_string(),
+            first_name: "Gus".to_string(),
+            last_name: "Doe".to_string(),
+            must_change_password: false,
+            details: RoleDetails::Parent,
+        })
+        .unwrap();
+    let guardian_id = store.find_parent_id(guardian).unwrap().unwrap();
+    store.link_student(guardian_id, student_id).unwrap();
+    let teacher = || RoleDetails::Teacher {
+        subject: "Art".to_string(),
+        department: "Arts".to_string(),
+    };
+    assert!(store
+        .update_account(&changes(guardian, "pat", teacher()))
+        .unwrap());
+    assert_eq!(store.find_role(guardian).unwrap(), Some(Role::Teacher));
+    assert!(store.find_teacher_id(guardian).unwrap().is_some());
+    assert_eq!(store.find_parent_id(guardian).unwrap(), None);
+    assert!(store.linked_children().unwrap().is_empty());
+    assert_eq!(
+        store.find_user_id("pat@school.edu").unwrap(),
+        Some(guardian)
+    );
+    assert!(!store
+        .update_account(&changes(guardian + 100, "nobody", teacher()))
+        .unwrap());
+    assert!(matches!(
+        store.update_account(&changes(guardian, "jdoe", teacher())),
+        Err(StoreError::Conflict(_))
+    ));
+
+    check_sign_in_state(store, teacher_user, student_user, student_id);
+}
+
+/// The sign-in half of [`check_store`]: sessions, passwords, lockouts,
+/// two-factor, invites and email verification.
+#[cfg(test)]
+fn check_sign_in_state(store: &dyn Store, admin_user: i32, user_id: i32, student_id: i32) {
+    const PAST: &str = "2000-01-01 00:00:00";
+    const FUTURE: &str = "2999-01-01 00:00:00";
+
+    let session = |session_id: &str, expires_at: &str| NewSession {
+        session_id: session_id.to_string(),
+        user_id,
+        refresh_token_hash: format!("{}-refresh", session_id),
+        expires_at: expires_at.to_string(),
+    };
+    store.create_session(&session("s1", FUTURE)).unwrap();
+    store.create_session(&session("s2", FUTURE)).unwrap();
+    store.create_session(&session("old", PAST)).unwrap();
+    assert_eq!(store.active_session_owner("s1").unwrap(), Some(user_id));
+    assert_eq!(store.active_session_owner("old").unwrap(), None);
+    assert!(store
+        .rotate_refresh_token("s1", "s1-refresh", "s1-next")
+        .unwrap());
+    assert!(!store
+        .rotate_refresh_token("s1", "s1-refresh", "s1-again")
+        .unwrap());
+    assert_eq!(
+        store.session_user("s1").unwrap(),
+        Some(SessionUser {
+            user_id,
+            user_type: "student".to_string(),
+            must_change_password: true,
+            must_enroll_two_factor: false,
+        })
+    );
+    store.revoke_session("s1").unwrap();
+    assert_eq!(store.session_user("s1").unwrap(), None);
+    assert_eq!(store.revoke_user_sessions(user_id).unwrap(), 2);
+    assert_eq!(store.active_session_owner("s2").unwrap(), None);
+
+    let account = store
+        .sign_in_account_by_email("jdoe@school.edu")
+        .unwrap()
+        .unwrap();
+    assert_eq!((account.id, account.status.as_str()), (user_id, "active"));
+    assert!(!account.two_factor_enabled);
+    store.create_session(&session("s3", FUTURE)).unwrap();
+    assert!(store.set_password(user_id, "new-hash", false).unwrap());
+    assert!(!store
+        .set_password(user_id + 100, "new-hash", false)
+        .unwrap());
+    let account = store.sign_in_account(user_id).unwrap().unwrap();
+    assert_eq!(account.password_hash, "new-hash");
+    assert!(!account.must_change_password);
+    assert_eq!(store.active_session_owner("s3").unwrap(), None);
+
+    let reset = |token_hash: &str, expires_at: &str| NewResetToken {
+        token_hash: token_hash.to_string(),
+        user_id,
+        issued_by: admin_user,
+        expires_at: expires_at.to_string(),
+    };
+    store.add_reset_token(&reset("expired", PAST)).unwrap();
+    assert_eq!(store.redeem_reset_token("expired", "x").unwrap(), None);
+    store.add_reset_token(&reset("first", FUTURE)).unwrap();
+    store.add_reset_token(&reset("second", FUTURE)).unwrap();
+    assert_eq!(store.redeem_reset_token("first", "x").unwrap(), None);
+    assert_eq!(
+        store.redeem_reset_token("second", "reset-hash").unwrap(),
+        Some(user_id)
+    );
+    assert_eq!(store.redeem_reset_token("second", "x").unwrap(), None);
+    assert_eq!(
+        store
+            .sign_in_account(user_id)
+            .unwrap()
+            .unwrap()
+            .password_hash,
+        "reset-hash"
+    );
+
+    let throttle = store
+        .login_throttle("jdoe@school.edu", "10.0.0.1", PAST)
+        .unwrap();
+    assert_eq!(throttle, LoginThrottle::default());
+    assert!(!store
+        .record_login_failure("account", "jdoe@school.edu", PAST, FUTURE, 2)
+        .unwrap());
+    assert!(store
+        .record_login_failure("account", "jdoe@school.edu", PAST, FUTURE, 2)
+        .unwrap());
+    let throttle = store
+        .login_throttle("jdoe@school.edu", "10.0.0.1", PAST)
+        .unwrap();
+    assert_eq!(throttle.recent_failures, 2);
+    assert_eq!(throttle.locked_until.as_deref(), Some(FUTURE));
+    assert_eq!(
+        store
+            .login_throttle("jdoe@school.edu", "10.0.0.1", FUTURE)
+            .unwrap()
+            .recent_failures,
+        0
+    );
+    let lockouts = store.login_failures(PAST).unwrap();
+    assert_eq!(lockouts.len(), 1);
+    assert_eq!((lockouts[0].failed_count, lockouts[0].locked), (2, true));
+    assert!(store
+        .clear_login_failures("account", "jdoe@school.edu")
+        .unwrap());
+    assert!(!store
+        .clear_login_failures("account", "jdoe@school.edu")
+        .unwrap());
+    assert!(store.login_failures(PAST).unwrap().is_empty());
+
+    assert_eq!(store.totp_secret(user_id, true).unwrap(), None);
+    store.save_totp_secret(user_id, "SECRET").unwrap();
+    assert_eq!(
+        store.totp_secret(user_id, true).unwrap(),
+        Some(TotpSecret {
+            secret: "SECRET".to_string(),
+            last_used_step: None,
+        })
+    );
+    assert_eq!(store.totp_secret(user_id, false).unwrap(), None);
+    store
+        .enable_totp(user_id, &["r1".to_string(), "r2".to_string()])
+        .unwrap();
+    assert!(store.use_totp_step(user_id, 10).unwrap());
+    assert!(!store.use_totp_step(user_id, 10).unwrap());
+    assert_eq!(
+        store
+            .totp_secret(user_id, false)
+            .unwrap()
+            .unwrap()
+            .last_used_step,
+        Some(10)
+    );
+    assert!(
+        store
+            .sign_in_account(user_id)
+            .unwrap()
+            .unwrap()
+            .two_factor_enabled
+    );
+    assert!(store.use_recovery_code(user_id, "r1").unwrap());
+    assert!(!store.use_recovery_code(user_id, "r1").unwrap());
+    store
+        .replace_recovery_codes(user_id, &["r3".to_string()])
+        .unwrap();
+    assert!(!store.use_recovery_code(user_id, "r2").unwrap());
+    assert!(store.set_two_factor_required(user_id, true).unwrap());
+    assert!(!store.set_two_factor_required(user_id + 100, true).unwrap());
+    store.remove_two_factor(user_id).unwrap();
+    assert_eq!(store.totp_secret(user_id, false).unwrap(), None);
+    assert!(!store.use_recovery_code(user_id, "r3").unwrap());
+    store.create_session(&session("s4", FUTURE)).unwrap();
+    assert!(
+        store
+            .session_user("s4")
+            .unwrap()
+            .unwrap()
+            .must_enroll_two_factor
+    );
+
+    let invite = |code_hash: &str, email: Option<&str>| NewInviteCode {
+        code_hash: code_hash.to_string(),
+        created_by: admin_user,
+        email: email.map(str::to_string),
+        max_uses: 1,
+        expires_at: FUTURE.to_string(),
+        student_ids: vec![student_id, student_id],
+    };
+    let invite_id = store.create_invite(&invite("open", None)).unwrap();
+    let restricted = store
+        .create_invite(&invite("restricted", Some("kim@home.org")))
+        .unwrap();
+    let invites = store.list_invites().unwrap();
+    assert_eq!(invites.len(), 2);
+    assert_eq!(invites[0].invite_id, restricted);
+    assert_eq!(invites[1].student_ids, [student_id]);
+    assert!(!invites[1].revoked);
+
+    let registration = |username: &str, code_hash: Option<&str>| NewRegistration {
+        username: username.to_string(),
+        email: format!("{}@home.org", username),
+        password_hash: "hash".to_string(),
+        first_name: "Kim".to_string(),
+        last_name: "Doe".to_string(),
+        invite_code_hash: code_hash.map(str::to_string),
+    };
+    assert_eq!(
+        store
+            .register_parent(&registration("lee", Some("restricted")))
+            .unwrap(),
+        None
+    );
+    let kim = store
+        .register_parent(&registration("kim", Some("restricted")))
+        .unwrap()
+        .unwrap();
+    let kim_parent = store.find_parent_id(kim).unwrap().unwrap();
+    assert!(store.is_linked(kim_parent, student_id).unwrap());
+    assert_eq!(
+        store
+            .register_parent(&registration("max", Some("restricted")))
+            .unwrap(),
+        None
+    );
+    assert!(store.revoke_invite(invite_id).unwrap());
+    assert!(!store.revoke_invite(invite_id + 100).unwrap());
+    assert_eq!(
+        store
+            .register_parent(&registration("ann", Some("open")))
+            .unwrap(),
+        None
+    );
+    let uninvited = store
+        .register_parent(&registration("ann", None))
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        store.register_parent(&registration("ann", None)),
+        Err(StoreError::Conflict(_))
+    ));
+    assert!(store.find_parent_id(uninvited).unwrap().is_some());
+
+    assert_eq!(
+        store.pending_account("ann@home.org", PAST).unwrap(),
+        Some(uninvited)
+    );
+    store
+        .add_verification_token(uninvited, "expired", PAST)
+        .unwrap();
+    assert_eq!(store.pending_account("ann@home.org", PAST).unwrap(), None);
+    assert_eq!(store.redeem_verification_token("expired").unwrap(), None);
+    store
+        .add_verification_token(uninvited, "valid", FUTURE)
+        .unwrap();
+    assert_eq!(
+        store.redeem_verification_token("valid").unwrap(),
+        Some(uninvited)
+    );
+    assert_eq!(store.redeem_verification_token("valid").unwrap(), None);
+    assert_eq!(
+        store.sign_in_account(uninvited).unwrap().unwrap().status,
+        "active"
+    );
+    assert_eq!(store.pending_account("ann@home.org", FUTURE).unwrap(), None);
+}
@@ -0,0 +1,2514 @@
+//! [`Store`](super::repository::Store) backed by the SQLite connection pool.
+
+use r2d2::PooledConnection;
+use rusqlite::{
+    params, params_from_iter, Connection, OptionalExtension, ToSql, Transaction,
+    TransactionBehavior,
+};
+
+use super::db::{DbPool, SqliteConnectionManager};
+use super::repository::{
+    AccountChanges, AccountListing, Appeal, AppealDecision, AppealOutcome, AppealRepository,
+    AppealScope, CategoryOption, CategoryRepository, CredentialRepository, DemeritCategoryCount,
+    DemeritChange, DemeritHistoryRecord, DemeritPoints, DemeritRepository, DemeritRevision,
+    DemeritTimePoint, Escalation, EscalationCheck, EscalationRepository, EscalationRule,
+    EscalationRuleValues, GradeDemeritCount, InviteSummary, LinkedChild, LockoutEntry,
+    LoginFailureRepository, LoginThrottle, MeritRecord, MeritRepository, NewAccount, NewAppeal,
+    NewDemerit, NewInviteCode, NewMerit, NewRegistration, NewResetToken, NewSession, ParentOption,
+    ParentRepository, RegistrationRepository, RoleDetails, SessionRepository, SessionUser,
+    StoreError, StoredAppeal, StoredDemerit, StoredEscalation, StudentDemeritDetail,
+    StudentDemeritSummary, StudentInfo, StudentOption, StudentRepository, StudentScope, TotpSecret,
+    TwoFactorRepository, UserRepository,
+};
+use crate::models::{AppealStatus, ParentRecord, Role, TeacherRecord, User};
+
+/// Sent when a username or email is already in use.
+const ACCOUNT_EXISTS: &str = "Username or email already exists";
+
+pub struct SqliteStore {
+    pool: DbPool,
+}
+
+impl SqliteStore {
+    pub fn new(pool: DbPool) -> Self {
+        SqliteStore { pool }
+    }
+
+    fn conn(&self) -> Result<PooledConnection<SqliteConnectionManager>, String> {
+        self.pool
+            .get()
+            .map_err(|e| format!("Database connection error: {}", e))
+    }
+
+    /// A raw connection, for tests that check what was stored. Drop it before
+    /// using the store again: the pool of [`memory_store`] has one connection.
+    #[cfg(test)]
+    pub(crate) fn connection(&self) -> PooledConnection<SqliteConnectionManager> {
+        self.pool.get().unwrap()
+    }
+}
+
+/// A migrated in-memory database behind a single-connection pool, for tests.
+#[cfg(test)]
+pub(crate) fn memory_store() -> SqliteStore {
+    let pool = super::db::create_pool(&crate::config::DatabaseConfig {
+        path: ":memory:".to_string(),
+        pool_size: 1,
+        ..Default::default()
+    })
+    .unwrap();
+    super::migrations::migrate_up(&pool.get().unwrap()).unwrap();
+    SqliteStore::new(pool)
+}
+
+/// Accounts and records for service tests against [`memory_store`]. Every
+/// account is named "<username> Test" and has the email
+/// "<username>@school.edu".
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::SqliteStore;
+    use crate::database::repository::{
+        DemeritRepository, MeritRepository, NewAccount, NewDemerit, NewMerit, ParentRepository,
+        RoleDetails, StudentRepository, UserRepository,
+    };
+
+    pub(crate) fn account(username: &str, details: RoleDetails) -> NewAccount {
+        NewAccount {
+            username: username.to_string(),
+            email: format!("{}@school.edu", username),
+            password_hash: "hash".to_string(),
+            first_name: username.to_string(),
+            last_name: "Test".to_string(),
+            must_change_password: false,
+            details,
+        }
+    }
+
+    pub(crate) fn user(store: &SqliteStore, username: &str, details: RoleDetails) -> i32 {
+        store.create_user(&account(username, details)).unwrap()
+    }
+
+    pub(crate) fn teacher(store: &SqliteStore, username: &str) -> i32 {
+        user(
+            store,
+            username,
+            RoleDetails::Teacher {
+                subject: "Math".to_string(),
+                department: "Science".to_string(),
+            },
+        )
+    }
+
+    /// A grade 7 student, as its user id and student id.
+    pub(crate) fn student(store: &SqliteStore, username: &str) -> (i32, i32) {
+        let user_id = user(
+            store,
+            username,
+            RoleDetails::Student {
+                grade_level: 7,
+                class_section: "A".to_string(),
+            },
+        );
+        (user_id, store.find_student_id(user_id).unwrap().unwrap())
+    }
+
+    /// A parent linked to `children`, as its user id and parent id.
+    pub(crate) fn parent(store: &SqliteStore, username: &str, children: &[i32]) -> (i32, i32) {
+        let user_id = user(store, username, RoleDetails::Parent);
+        let parent_id = store.find_parent_id(user_id).unwrap().unwrap();
+        for &student_id in children {
+            store.link_student(parent_id, student_id).unwrap();
+        }
+        (user_id, parent_id)
+    }
+
+    /// Records a demerit in the first category, issued by `teacher_user`.
+    pub(crate) fn demerit(
+        store: &SqliteStore,
+        teacher_user: i32,
+        student_id: i32,
+        points: i32,
+    ) -> i32 {
+        store
+            .add_demerit(&NewDemerit {
+                student_id,
+                teacher_id: store.find_teacher_id(teacher_user).unwrap().unwrap(),
+                category_id: 1,
+                points,
+                description: "Late".to_string(),
+            })
+            .unwrap()
+    }
+
+    /// Records a merit in the first category, awarded by `teacher_user`.
+    pub(crate) fn merit(
+        store: &SqliteStore,
+        teacher_user: i32,
+        student_id: i32,
+        points: i32,
+    ) -> i32 {
+        store
+            .add_merit(&NewMerit {
+                student_id,
+                teacher_id: store.find_teacher_id(teacher_user).unwrap().unwrap(),
+                category_id: 1,
+                points,
+                description: "Helped out".to_string(),
+            })
+            .unwrap()
+    }
+}
+
+impl UserRepository for SqliteStore {
+    fn find_user_id(&self, username_or_email: &str) -> Result<Option<i32>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT user_id FROM users WHERE username = ?1 OR email = ?1",
+                params![username_or_email.trim()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to look up user: {}", e)))
+    }
+
+    fn create_user(&self, account: &NewAccount) -> Result<i32, StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let user_id: i32 = tx
+            .query_row(
+                "INSERT INTO users
+                 (username, password_hash, email, user_type, first_name, last_name, must_change_password)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 RETURNING user_id",
+                params![
+                    account.username,
+                    account.password_hash,
+                    account.email,
+                    account.details.role().as_str(),
+                    account.first_name,
+                    account.last_name,
+                    account.must_change_password
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| conflict_on_unique(e, ACCOUNT_EXISTS))?;
+
+        insert_role_record(&tx, user_id, &account.details).map_err(|e| {
+            format!(
+                "Failed to create {} record: {}",
+                account.details.role().as_str(),
+                e
+            )
+        })?;
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(user_id)
+    }
+
+    fn find_teacher_id(&self, user_id: i32) -> Result<Option<i32>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT teacher_id FROM teachers WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to get teacher ID: {}", e)))
+    }
+
+    fn any_teacher_id(&self) -> Result<Option<i32>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT teacher_id FROM teachers
+                 JOIN users ON teachers.user_id = users.user_id
+                 ORDER BY teacher_id
+                 LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to find a teacher: {}", e)))
+    }
+
+    fn set_role(&self, user_id: i32, role: Role) -> Result<bool, StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE users SET user_type = ?1 WHERE user_id = ?2",
+                params![role.as_str(), user_id],
+            )
+            .map(|updated| updated > 0)
+            .map_err(|e| StoreError::Other(format!("Failed to update user role: {}", e)))
+    }
+
+    fn find_role(&self, user_id: i32) -> Result<Option<Role>, StoreError> {
+        let role: Option<String> = self
+            .conn()?
+            .query_row(
+                "SELECT user_type FROM users WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to find user role: {}", e))?;
+        Ok(role.map(|role| role.parse()).transpose()?)
+    }
+
+    fn list_accounts(&self) -> Result<Vec<AccountListing>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT u.user_id, u.username, u.email, u.first_name, u.last_name, u.user_type,
+                        u.created_at, s.student_id, s.grade_level, s.class_section
+                 FROM users u
+                 LEFT JOIN students s ON u.user_id = s.user_id
+                 ORDER BY u.user_id",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let accounts = stmt
+            .query_map([], |row| {
+                Ok(AccountListing {
+                    user_id: row.get(0)?,
+                    username: row.get(1)?,
+                    email: row.get(2)?,
+                    first_name: row.get(3)?,
+                    last_name: row.get(4)?,
+                    user_type: row.get(5)?,
+                    created_at: row.get(6)?,
+                    student_id: row.get(7)?,
+                    grade_level: row.get(8)?,
+                    class_section: row.get(9)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        accounts.map_err(|e| StoreError::Other(format!("Failed to fetch users: {}", e)))
+    }
+
+    fn update_account(&self, changes: &AccountChanges) -> Result<bool, StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let current: Option<String> = tx
+            .query_row(
+                "SELECT user_type FROM users WHERE user_id = ?1",
+                params![changes.user_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to look up user: {}", e))?;
+        let Some(current) = current else {
+            return Ok(false);
+        };
+        let role = changes.details.role();
+
+        tx.execute(
+            "UPDATE users SET first_name = ?1, last_name = ?2, email = ?3, username = ?4,
+                              user_type = ?5
+             WHERE user_id = ?6",
+            params![
+                changes.first_name,
+                changes.last_name,
+                changes.email,
+                changes.username,
+                role.as_str(),
+                changes.user_id
+            ],
+        )
+        .map_err(|e| conflict_on_unique(e, ACCOUNT_EXISTS))?;
+
+        if current != role.as_str() {
+            // Parent links go before the parent record they point to
+            for statement in [
+                "DELETE FROM teachers WHERE user_id = ?1",
+                "DELETE FROM students WHERE user_id = ?1",
+                "DELETE FROM parent_student WHERE parent_id IN
+                     (SELECT parent_id FROM parents WHERE user_id = ?1)",
+                "DELETE FROM parents WHERE user_id = ?1",
+            ] {
+                tx.execute(statement, params![changes.user_id])
+                    .map_err(|e| format!("Failed to remove {} record: {}", current, e))?;
+            }
+            insert_role_record(&tx, changes.user_id, &changes.details)
+                .map_err(|e| format!("Failed to create {} record: {}", role.as_str(), e))?;
+        } else if let RoleDetails::Student {
+            grade_level,
+            class_section,
+        } = &changes.details
+        {
+            tx.execute(
+                "UPDATE students SET grade_level = ?1, class_section = ?2 WHERE user_id = ?3",
+                params![grade_level, class_section, changes.user_id],
+            )
+            .map_err(|e| format!("Failed to update student information: {}", e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(true)
+    }
+}
+
+fn insert_role_record(
+    conn: &Connection,
+    user_id: i32,
+    details: &RoleDetails,
+) -> rusqlite::Result<usize> {
+    match details {
+        RoleDetails::Admin => Ok(0),
+        RoleDetails::Parent => conn.execute(
+            "INSERT INTO parents (user_id) VALUES (?1)",
+            params![user_id],
+        ),
+        RoleDetails::Teacher {
+            subject,
+            department,
+        } => conn.execute(
+            "INSERT INTO teachers (user_id, subject, department) VALUES (?1, ?2, ?3)",
+            params![user_id, subject, department],
+        ),
+        RoleDetails::Student {
+            grade_level,
+            class_section,
+        } => conn.execute(
+            "INSERT INTO students (user_id, grade_level, class_section) VALUES (?1, ?2, ?3)",
+            params![user_id, grade_level, class_section],
+        ),
+    }
+}
+
+impl StudentRepository for SqliteStore {
+    fn list_students(&self) -> Result<Vec<StudentOption>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT s.student_id, u.first_name || ' ' || u.last_name as name
+                 FROM students s
+                 JOIN users u ON s.user_id = u.user_id
+                 ORDER BY u.last_name, u.first_name",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let students = stmt
+            .query_map([], |row| {
+                Ok(StudentOption {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        students.map_err(|e| StoreError::Other(format!("Failed to fetch students: {}", e)))
+    }
+
+    fn student_exists(&self, student_id: i32) -> Result<bool, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM students WHERE student_id = ?1)",
+                params![student_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| StoreError::Other(format!("Error verifying student: {}", e)))
+    }
+
+    fn find_student_id(&self, user_id: i32) -> Result<Option<i32>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT student_id FROM students WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to get student ID: {}", e)))
+    }
+
+    fn student_info(&self, user_id: i32) -> Result<Option<StudentInfo>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT student_id, grade_level, class_section FROM students WHERE user_id = ?1",
+                params![user_id],
+                |row| {
+                    Ok(StudentInfo {
+                        student_id: row.get(0)?,
+                        grade_level: row.get(1)?,
+                        class_section: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to get student info: {}", e)))
+    }
+
+    fn save_student(
+        &self,
+        user_id: i32,
+        grade_level: i32,
+        class_section: &str,
+    ) -> Result<i32, StoreError> {
+        self.conn()?
+            .query_row(
+                "INSERT INTO students (user_id, grade_level, class_section)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT (user_id) DO UPDATE
+                 SET grade_level = excluded.grade_level, class_section = excluded.class_section
+                 RETURNING student_id",
+                params![user_id, grade_level, class_section],
+                |row| row.get(0),
+            )
+            .map_err(|e| StoreError::Other(format!("Failed to save student record: {}", e)))
+    }
+}
+
+impl ParentRepository for SqliteStore {
+    fn list_parents(&self) -> Result<Vec<ParentOption>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT p.parent_id, u.first_name || ' ' || u.last_name as full_name, p.user_id
+                 FROM parents p
+                 JOIN users u ON p.user_id = u.user_id
+                 ORDER BY u.last_name, u.first_name",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let parents = stmt
+            .query_map([], |row| {
+                Ok(ParentOption {
+                    parent_id: row.get(0)?,
+                    name: row.get(1)?,
+                    user_id: row.get(2)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        parents.map_err(|e| StoreError::Other(format!("Failed to fetch parents: {}", e)))
+    }
+
+    fn find_parent_id(&self, user_id: i32) -> Result<Option<i32>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT parent_id FROM parents WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to get parent ID: {}", e)))
+    }
+
+    fn is_linked(&self, parent_id: i32, student_id: i32) -> Result<bool, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT EXISTS(
+                    SELECT 1 FROM parent_student
+                    WHERE parent_id = ?1 AND student_id = ?2
+                 )",
+                params![parent_id, student_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| StoreError::Other(format!("Error checking relationship: {}", e)))
+    }
+
+    fn link_student(&self, parent_id: i32, student_id: i32) -> Result<(), StoreError> {
+        self.conn()?
+            .execute(
+                "INSERT INTO parent_student (parent_id, student_id) VALUES (?1, ?2)",
+                params![parent_id, student_id],
+            )
+            .map(|_| ())
+            .map_err(|e| StoreError::Other(format!("Failed to add relationship: {}", e)))
+    }
+
+    fn replace_students(&self, parent_id: i32, student_ids: &[i32]) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        tx.execute(
+            "DELETE FROM parent_student WHERE parent_id = ?1",
+            params![parent_id],
+        )
+        .map_err(|e| format!("Failed to remove existing relationships: {}", e))?;
+
+        for student_id in student_ids {
+            tx.execute(
+                "INSERT INTO parent_student (parent_id, student_id) VALUES (?1, ?2)",
+                params![parent_id, student_id],
+            )
+            .map_err(|e| {
+                format!(
+                    "Failed to add relationship for student {}: {}",
+                    student_id, e
+                )
+            })?;
+        }
+
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn linked_children(&self) -> Result<Vec<LinkedChild>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT p.user_id, s.student_id, u.first_name || ' ' || u.last_name
+                 FROM parent_student ps
+                 JOIN parents p ON ps.parent_id = p.parent_id
+                 JOIN students s ON ps.student_id = s.student_id
+                 JOIN users u ON s.user_id = u.user_id
+                 ORDER BY p.user_id, s.student_id",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let children = stmt
+            .query_map([], |row| {
+                Ok(LinkedChild {
+                    parent_user_id: row.get(0)?,
+                    student_id: row.get(1)?,
+                    student_name: row.get(2)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        children.map_err(|e| StoreError::Other(format!("Failed to fetch children: {}", e)))
+    }
+}
+
+impl CategoryRepository for SqliteStore {
+    fn list_categories(&self) -> Result<Vec<CategoryOption>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT category_id, category_name, default_points
+                 FROM demerit_categories
+                 ORDER BY category_id",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let categories = stmt
+            .query_map([], |row| {
+                Ok(CategoryOption {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    default_points: row.get(2)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        categories.map_err(|e| StoreError::Other(format!("Failed to fetch categories: {}", e)))
+    }
+
+    fn category_exists(&self, category_id: i32) -> Result<bool, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM demerit_categories WHERE category_id = ?1)",
+                params![category_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| StoreError::Other(format!("Error verifying category: {}", e)))
+    }
+
+    fn find_category_id(&self, name: &str) -> Result<Option<i32>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT category_id FROM demerit_categories WHERE category_name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to find a demerit category: {}", e)))
+    }
+}
+
+impl DemeritRepository for SqliteStore {
+    fn add_demerit(&self, demerit: &NewDemerit) -> Result<i32, StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let demerit_id = insert_demerit(&tx, demerit)?;
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(demerit_id)
+    }
+
+    fn record_demerit(
+        &self,
+        demerit: &NewDemerit,
+        checks: &[EscalationCheck],
+    ) -> Result<(i32, Vec<i32>), StoreError> {
+        let conn = self.conn()?;
+        // Immediate, so that a concurrent demerit for the student waits for
+        // this one instead of reading the same totals.
+        let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let mut before = Vec::with_capacity(checks.len());
+        for check in checks {
+            let points: i32 = tx
+                .query_row(
+                    "SELECT COALESCE(SUM(points), 0) FROM demerit_records
+                     WHERE student_id = ?1 AND voided_at IS NULL AND date_issued >= ?2",
+                    params![demerit.student_id, check.window_start],
+                    |row| row.get(0),
+                )
+                .map_err(|e| format!("Failed to count demerit points: {}", e))?;
+            before.push(points);
+        }
+        let demerit_id = insert_demerit(&tx, demerit)?;
+
+        let mut raised = Vec::new();
+        for (check, before) in checks.iter().zip(before) {
+            let total = before + demerit.points;
+            if before >= check.points || total < check.points {
+                continue;
+            }
+            let escalation_id: Option<i32> = tx
+                .query_row(
+                    "INSERT INTO escalations
+                        (rule_id, student_id, demerit_id, points, action, assigned_to, window_start)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                     ON CONFLICT (student_id, rule_id, window_start) DO NOTHING
+                     RETURNING escalation_id",
+                    params![
+                        check.rule_id,
+                        demerit.student_id,
+                        demerit_id,
+                        total,
+                        check.action.as_str(),
+                        check.assigned_to,
+                        check.window_start
+                    ],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| format!("Failed to add escalation: {}", e))?;
+            raised.extend(escalation_id);
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok((demerit_id, raised))
+    }
+
+    fn find_demerit(&self, demerit_id: i32) -> Result<Option<StoredDemerit>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT demerit_id, student_id, teacher_id, category_id, points,
+                        COALESCE(description, ''), voided_at IS NOT NULL
+                 FROM demerit_records
+                 WHERE demerit_id = ?1",
+                params![demerit_id],
+                |row| {
+                    Ok(StoredDemerit {
+                        demerit_id: row.get(0)?,
+                        student_id: row.get(1)?,
+                        teacher_id: row.get(2)?,
+                        category_id: row.get(3)?,
+                        points: row.get(4)?,
+                        description: row.get(5)?,
+                        voided: row.get(6)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to find demerit: {}", e)))
+    }
+
+    fn update_demerit(&self, demerit_id: i32, change: &DemeritChange) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        tx.execute(
+            "UPDATE demerit_records
+             SET student_id = ?1, category_id = ?2, points = ?3, description = ?4
+             WHERE demerit_id = ?5",
+            params![
+                change.student_id,
+                change.category_id,
+                change.points,
+                change.description,
+                demerit_id
+            ],
+        )
+        .map_err(|e| format!("Failed to update demerit: {}", e))?;
+        record_revision(
+            &tx,
+            demerit_id,
+            "edited",
+            Some(change.changed_by),
+            change.reason.as_deref(),
+        )?;
+
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn void_demerit(
+        &self,
+        demerit_id: i32,
+        changed_by: i32,
+        reason: &str,
+    ) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let voided = tx
+            .execute(
+                "UPDATE demerit_records
+                 SET voided_at = CURRENT_TIMESTAMP, void_reason = ?1
+                 WHERE demerit_id = ?2 AND voided_at IS NULL",
+                params![reason, demerit_id],
+            )
+            .map_err(|e| format!("Failed to void demerit: {}", e))?;
+        if voided == 0 {
+            return Err(StoreError::Conflict(
+                "The demerit is already voided".to_string(),
+            ));
+        }
+        record_revision(&tx, demerit_id, "voided", Some(changed_by), Some(reason))?;
+
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn demerit_revisions(&self, demerit_id: i32) -> Result<Vec<DemeritRevision>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT r.revision_id, r.action, u.first_name || ' ' || u.last_name,
+                        r.student_id, r.category_id, r.points, COALESCE(r.description, ''),
+                        r.reason, r.changed_at
+                 FROM demerit_revisions r
+                 JOIN users u ON r.changed_by = u.user_id
+                 WHERE r.demerit_id = ?1
+                 ORDER BY r.revision_id",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let revisions = stmt
+            .query_map(params![demerit_id], |row| {
+                Ok(DemeritRevision {
+                    revision_id: row.get(0)?,
+                    action: row.get(1)?,
+                    changed_by: row.get(2)?,
+                    student_id: row.get(3)?,
+                    category_id: row.get(4)?,
+                    points: row.get(5)?,
+                    description: row.get(6)?,
+                    reason: row.get(7)?,
+                    changed_at: row.get(8)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        revisions
+            .map_err(|e| StoreError::Other(format!("Failed to fetch demerit revisions: {}", e)))
+    }
+
+    fn demerit_history(&self) -> Result<Vec<DemeritHistoryRecord>, StoreError> {
+        let query = r#"
+            SELECT
+                d.demerit_id,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = s.user_id) as student_name,
+                c.category_name,
+                d.points,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = t.user_id) as teacher_name,
+                d.description,
+                d.date_issued,
+                d.voided_at,
+                d.void_reason,
+                a.status
+            FROM
+                demerit_records d
+            JOIN
+                students s ON d.student_id = s.student_id
+            JOIN
+                teachers t ON d.teacher_id = t.teacher_id
+            JOIN
+                demerit_categories c ON d.category_id = c.category_id
+            LEFT JOIN
+                demerit_appeals a ON a.demerit_id = d.demerit_id
+            ORDER BY
+                d.date_issued DESC, d.demerit_id DESC
+        "#;
+
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(query)
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let records = stmt
+            .query_map([], |row| {
+                Ok(DemeritHistoryRecord {
+                    demerit_id: row.get(0)?,
+                    student_name: row.get(1)?,
+                    category_name: row.get(2)?,
+                    points: row.get(3)?,
+                    teacher_name: row.get(4)?,
+                    description: row.get(5)?,
+                    date_issued: row.get(6)?,
+                    voided_at: row.get(7)?,
+                    void_reason: row.get(8)?,
+                    appeal_status: row.get(9)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        records.map_err(|e| StoreError::Other(format!("Failed to collect demerit records: {}", e)))
+    }
+
+    fn student_demerits(&self, student_id: i32) -> Result<Vec<StudentDemeritDetail>, StoreError> {
+        let query = r#"
+            SELECT
+                dr.demerit_id,
+                c.category_name,
+                dr.points,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = t.user_id) as teacher_name,
+                dr.description,
+                dr.date_issued,
+                dr.voided_at,
+                dr.void_reason,
+                a.status
+            FROM
+                demerit_records dr
+            JOIN
+                demerit_categories c ON dr.category_id = c.category_id
+            JOIN
+                teachers t ON dr.teacher_id = t.teacher_id
+            LEFT JOIN
+                demerit_appeals a ON a.demerit_id = dr.demerit_id
+            WHERE
+                dr.student_id = ?1
+            ORDER BY
+                dr.date_issued DESC, dr.demerit_id DESC
+        "#;
+
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(query)
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let demerits = stmt
+            .query_map(params![student_id], |row| {
+                Ok(StudentDemeritDetail {
+                    demerit_id: row.get(0)?,
+                    category_name: row.get(1)?,
+                    points: row.get(2)?,
+                    teacher_name: row.get(3)?,
+                    description: row.get(4)?,
+                    date_issued: row.get(5)?,
+                    voided_at: row.get(6)?,
+                    void_reason: row.get(7)?,
+                    appeal_status: row.get(8)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        demerits.map_err(|e| StoreError::Other(format!("Error collecting records: {}", e)))
+    }
+
+    fn teacher_demerits(&self, teacher_id: i32) -> Result<Vec<TeacherRecord>, StoreError> {
+        let query = r#"
+            SELECT
+                dr.demerit_id,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = s.user_id) as student_name,
+                c.category_name,
+                dr.points,
+                dr.date_issued,
+                dr.voided_at,
+                dr.void_reason,
+                a.status
+            FROM
+                demerit_records dr
+            JOIN
+                students s ON dr.student_id = s.student_id
+            JOIN
+                demerit_categories c ON dr.category_id = c.category_id
+            LEFT JOIN
+                demerit_appeals a ON a.demerit_id = dr.demerit_id
+            WHERE
+                dr.teacher_id = ?1
+            ORDER BY
+                dr.date_issued DESC, dr.demerit_id DESC
+        "#;
+
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(query)
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let records = stmt
+            .query_map(params![teacher_id], |row| {
+                Ok(TeacherRecord {
+                    id: row.get(0)?,
+                    student_name: row.get(1)?,
+                    category: row.get(2)?,
+                    points: row.get(3)?,
+                    date_issued: row.get(4)?,
+                    voided_at: row.get(5)?,
+                    void_reason: row.get(6)?,
+                    appeal_status: row.get(7)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        records.map_err(|e| StoreError::Other(format!("Error collecting records: {}", e)))
+    }
+
+    fn children_demerits(&self, parent_id: i32) -> Result<Vec<ParentRecord>, StoreError> {
+        let query = r#"
+            SELECT
+                dr.demerit_id,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = s.user_id) as student_name,
+                c.category_name,
+                dr.points,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = t.user_id) as teacher_name,
+                dr.date_issued,
+                dr.voided_at,
+                dr.void_reason,
+                a.status
+            FROM
+                parent_student ps
+            JOIN
+                demerit_records dr ON ps.student_id = dr.student_id
+            JOIN
+                students s ON dr.student_id = s.student_id
+            JOIN
+                teachers t ON dr.teacher_id = t.teacher_id
+            JOIN
+                demerit_categories c ON dr.category_id = c.category_id
+            LEFT JOIN
+                demerit_appeals a ON a.demerit_id = dr.demerit_id
+            WHERE
+                ps.parent_id = ?1
+            ORDER BY
+                dr.date_issued DESC, dr.demerit_id DESC
+        "#;
+
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(query)
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let records = stmt
+            .query_map(params![parent_id], |row| {
+                Ok(ParentRecord {
+                    id: row.get(0)?,
+                    student_name: row.get(1)?,
+                    category: row.get(2)?,
+                    points: row.get(3)?,
+                    teacher_name: row.get(4)?,
+                    date_issued: row.get(5)?,
+                    voided_at: row.get(6)?,
+                    void_reason: row.get(7)?,
+                    appeal_status: row.get(8)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        records.map_err(|e| StoreError::Other(format!("Error collecting records: {}", e)))
+    }
+
+    fn demerit_points(&self, scope: StudentScope) -> Result<Vec<DemeritPoints>, StoreError> {
+        let (filter, id) = match scope {
+            StudentScope::All => ("1 = 1", None),
+            StudentScope::Student(student_id) => ("student_id = ?1", Some(student_id)),
+            StudentScope::Children(parent_id) => (
+                "student_id IN (SELECT student_id FROM parent_student WHERE parent_id = ?1)",
+                Some(parent_id),
+            ),
+        };
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT student_id, points, date_issued FROM demerit_records
+                 WHERE voided_at IS NULL AND {}",
+                filter
+            ))
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let points = stmt
+            .query_map(params_from_iter(id), |row| {
+                Ok(DemeritPoints {
+                    student_id: row.get(0)?,
+                    points: row.get(1)?,
+                    date_issued: row.get(2)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        points.map_err(|e| StoreError::Other(format!("Failed to fetch demerit points: {}", e)))
+    }
+
+    fn student_summaries(&self) -> Result<Vec<StudentDemeritSummary>, StoreError> {
+        summaries_where(&*self.conn()?, "1 = 1", None)
+    }
+
+    fn student_summary(
+        &self,
+        student_id: i32,
+    ) -> Result<Option<StudentDemeritSummary>, StoreError> {
+        let summaries = summaries_where(&*self.conn()?, "s.student_id = ?1", Some(student_id))?;
+        Ok(summaries.into_iter().next())
+    }
+
+    fn children_summaries(&self, parent_id: i32) -> Result<Vec<StudentDemeritSummary>, StoreError> {
+        summaries_where(
+            &*self.conn()?,
+            "s.student_id IN (SELECT student_id FROM parent_student WHERE parent_id = ?1)",
+            Some(parent_id),
+        )
+    }
+
+    fn demerits_by_category(&self) -> Result<Vec<DemeritCategoryCount>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT c.category_name, COUNT(*) as count
+                 FROM demerit_records dr
+                 JOIN demerit_categories c ON dr.category_id = c.category_id
+                 WHERE dr.voided_at IS NULL
+                 GROUP BY c.category_name
+                 ORDER BY count DESC",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let categories = stmt
+            .query_map([], |row| {
+                Ok(DemeritCategoryCount {
+                    category_name: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        categories
+            .map_err(|e| StoreError::Other(format!("Failed to fetch category distribution: {}", e)))
+    }
+
+    fn demerits_by_grade(&self) -> Result<Vec<GradeDemeritCount>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT s.grade_level, COUNT(*) as count
+                 FROM demerit_records dr
+                 JOIN students s ON dr.student_id = s.student_id
+                 WHERE dr.voided_at IS NULL
+                 GROUP BY s.grade_level
+                 ORDER BY s.grade_level",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let grades = stmt
+            .query_map([], |row| {
+                Ok(GradeDemeritCount {
+                    grade: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        grades.map_err(|e| StoreError::Other(format!("Failed to fetch grade distribution: {}", e)))
+    }
+
+    fn demerit_trend(&self) -> Result<Vec<DemeritTimePoint>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT
+                    strftime('%Y-%m-%d', date_issued) as date,
+                    COUNT(*) as count
+                 FROM demerit_records
+                 WHERE voided_at IS NULL
+                 GROUP BY date
+                 ORDER BY date ASC
+                 LIMIT 60",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let trend = stmt
+            .query_map([], |row| {
+                Ok(DemeritTimePoint {
+                    date: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        trend.map_err(|e| StoreError::Other(format!("Failed to fetch trend data: {}", e)))
+    }
+}
+
+impl AppealRepository for SqliteStore {
+    fn file_appeal(&self, appeal: &NewAppeal) -> Result<i32, StoreError> {
+        self.conn()?
+            .query_row(
+                "INSERT INTO demerit_appeals (demerit_id, filed_by, reason)
+                 VALUES (?1, ?2, ?3)
+                 RETURNING appeal_id",
+                params![appeal.demerit_id, appeal.filed_by, appeal.reason],
+                |row| row.get(0),
+            )
+            .map_err(|e| conflict_on_unique(e, "This demerit has already been appealed"))
+    }
+
+    fn find_appeal(&self, appeal_id: i32) -> Result<Option<StoredAppeal>, StoreError> {
+        find_appeal_where(&*self.conn()?, "appeal_id", appeal_id)
+    }
+
+    fn demerit_appeal(&self, demerit_id: i32) -> Result<Option<StoredAppeal>, StoreError> {
+        find_appeal_where(&*self.conn()?, "demerit_id", demerit_id)
+    }
+
+    fn start_appeal_review(&self, appeal_id: i32, reviewer: i32) -> Result<(), StoreError> {
+        let started = self
+            .conn()?
+            .execute(
+                "UPDATE demerit_appeals SET status = 'under_review', reviewed_by = ?1
+                 WHERE appeal_id = ?2 AND status = 'open'",
+                params![reviewer, appeal_id],
+            )
+            .map_err(|e| format!("Failed to start appeal review: {}", e))?;
+        if started == 0 {
+            return Err(StoreError::Conflict(
+                "Only an open appeal can be taken under review".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn decide_appeal(&self, appeal_id: i32, decision: &AppealDecision) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let status = match &decision.outcome {
+            AppealOutcome::Upheld => AppealStatus::Upheld,
+            AppealOutcome::ReducePoints { .. } | AppealOutcome::Void { .. } => {
+                AppealStatus::Overturned
+            }
+        };
+        // Closing the appeal first makes a concurrent second decision fail
+        // before it touches the demerit
+        let demerit_id: Option<i32> = tx
+            .query_row(
+                "UPDATE demerit_appeals
+                 SET status = ?1, reviewed_by = ?2, decision_comment = ?3,
+                     decided_at = CURRENT_TIMESTAMP
+                 WHERE appeal_id = ?4 AND status = 'under_review'
+                 RETURNING demerit_id",
+                params![
+                    status.as_str(),
+                    decision.decided_by,
+                    decision.comment,
+                    appeal_id
+                ],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to decide appeal: {}", e))?;
+        let Some(demerit_id) = demerit_id else {
+            return Err(StoreError::Conflict(
+                "Only an appeal under review can be decided".to_string(),
+            ));
+        };
+
+        match &decision.outcome {
+            AppealOutcome::Upheld => {}
+            AppealOutcome::ReducePoints { points, reason } => {
+                tx.execute(
+                    "UPDATE demerit_records SET points = ?1 WHERE demerit_id = ?2",
+                    params![points, demerit_id],
+                )
+                .map_err(|e| format!("Failed to update demerit: {}", e))?;
+                record_revision(
+                    &tx,
+                    demerit_id,
+                    "edited",
+                    Some(decision.decided_by),
+                    Some(reason),
+                )?;
+            }
+            AppealOutcome::Void { reason } => {
+                let voided = tx
+                    .execute(
+                        "UPDATE demerit_records
+                         SET voided_at = CURRENT_TIMESTAMP, void_reason = ?1
+                         WHERE demerit_id = ?2 AND voided_at IS NULL",
+                        params![reason, demerit_id],
+                    )
+                    .map_err(|e| format!("Failed to void demerit: {}", e))?;
+                if voided == 0 {
+                    return Err(StoreError::Conflict(
+                        "The demerit is already voided".to_string(),
+                    ));
+                }
+                record_revision(
+                    &tx,
+                    demerit_id,
+                    "voided",
+                    Some(decision.decided_by),
+                    Some(reason),
+                )?;
+            }
+        }
+
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn appeals(&self, scope: AppealScope) -> Result<Vec<Appeal>, StoreError> {
+        let (filter, id) = match scope {
+            AppealScope::All => ("1 = 1", None),
+            AppealScope::IssuedBy(teacher_id) => ("dr.teacher_id = ?1", Some(teacher_id)),
+            AppealScope::Student(student_id) => ("dr.student_id = ?1", Some(student_id)),
+            AppealScope::Children(parent_id) => (
+                "dr.student_id IN (SELECT student_id FROM parent_student WHERE parent_id = ?1)",
+                Some(parent_id),
+            ),
+        };
+        let query = format!(
+            r#"
+            SELECT
+                a.appeal_id,
+                a.demerit_id,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = s.user_id) as student_name,
+                c.category_name,
+                dr.points,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = a.filed_by) as filed_by,
+                a.reason,
+                a.status,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = a.reviewed_by) as reviewed_by,
+                a.decision_comment,
+                a.filed_at,
+                a.decided_at
+            FROM
+                demerit_appeals a
+            JOIN
+                demerit_records dr ON a.demerit_id = dr.demerit_id
+            JOIN
+                students s ON dr.student_id = s.student_id
+            JOIN
+                demerit_categories c ON dr.category_id = c.category_id
+            WHERE
+                {}
+            ORDER BY
+                a.filed_at DESC, a.appeal_id DESC
+        "#,
+            filter
+        );
+
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&query)
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let appeals = stmt
+            .query_map(params_from_iter(id), |row| {
+                Ok(Appeal {
+                    appeal_id: row.get(0)?,
+                    demerit_id: row.get(1)?,
+                    student_name: row.get(2)?,
+                    category_name: row.get(3)?,
+                    points: row.get(4)?,
+                    filed_by: row.get(5)?,
+                    reason: row.get(6)?,
+                    status: row.get(7)?,
+                    reviewed_by: row.get(8)?,
+                    decision_comment: row.get(9)?,
+                    filed_at: row.get(10)?,
+                    decided_at: row.get(11)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        appeals.map_err(|e| StoreError::Other(format!("Failed to fetch appeals: {}", e)))
+    }
+}
+
+impl EscalationRepository for SqliteStore {
+    fn escalation_rules(&self) -> Result<Vec<EscalationRule>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT r.rule_id, r.name, r.points, r.window_days, r.action, r.assigned_to,
+                        u.first_name || ' ' || u.last_name, r.active
+                 FROM escalation_rules r
+                 JOIN users u ON r.assigned_to = u.user_id
+                 ORDER BY r.rule_id",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let rules = stmt
+            .query_map([], |row| {
+                Ok(EscalationRule {
+                    rule_id: row.get(0)?,
+                    name: row.get(1)?,
+                    points: row.get(2)?,
+                    window_days: row.get(3)?,
+                    action: row.get(4)?,
+                    assigned_to: row.get(5)?,
+                    assignee_name: row.get(6)?,
+                    active: row.get(7)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        rules.map_err(|e| StoreError::Other(format!("Failed to fetch escalation rules: {}", e)))
+    }
+
+    fn add_escalation_rule(&self, rule: &EscalationRuleValues) -> Result<i32, StoreError> {
+        self.conn()?
+            .query_row(
+                "INSERT INTO escalation_rules
+                    (name, points, window_days, action, assigned_to, active)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 RETURNING rule_id",
+                params![
+                    rule.name,
+                    rule.points,
+                    rule.window_days,
+                    rule.action.as_str(),
+                    rule.assigned_to,
+                    rule.active
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| StoreError::Other(format!("Failed to add escalation rule: {}", e)))
+    }
+
+    fn update_escalation_rule(
+        &self,
+        rule_id: i32,
+        rule: &EscalationRuleValues,
+    ) -> Result<bool, StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE escalation_rules
+                 SET name = ?1, points = ?2, window_days = ?3, action = ?4,
+                     assigned_to = ?5, active = ?6
+                 WHERE rule_id = ?7",
+                params![
+                    rule.name,
+                    rule.points,
+                    rule.window_days,
+                    rule.action.as_str(),
+                    rule.assigned_to,
+                    rule.active,
+                    rule_id
+                ],
+            )
+            .map(|updated| updated > 0)
+            .map_err(|e| StoreError::Other(format!("Failed to update escalation rule: {}", e)))
+    }
+
+    fn find_escalation(&self, escalation_id: i32) -> Result<Option<StoredEscalation>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT escalation_id, assigned_to, status = 'resolved'
+                 FROM escalations
+                 WHERE escalation_id = ?1",
+                params![escalation_id],
+                |row| {
+                    Ok(StoredEscalation {
+                        escalation_id: row.get(0)?,
+                        assigned_to: row.get(1)?,
+                        resolved: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to find escalation: {}", e)))
+    }
+
+    fn open_escalations(&self, assigned_to: Option<i32>) -> Result<Vec<Escalation>, StoreError> {
+        let query = r#"
+            SELECT
+                e.escalation_id,
+                r.name,
+                e.student_id,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = s.user_id) as student_name,
+                e.demerit_id,
+                e.points,
+                e.action,
+                e.assigned_to,
+                (SELECT first_name || ' ' || last_name FROM users WHERE user_id = e.assigned_to) as assignee_name,
+                e.created_at
+            FROM
+                escalations e
+            JOIN
+                escalation_rules r ON e.rule_id = r.rule_id
+            JOIN
+                students s ON e.student_id = s.student_id
+            WHERE
+                e.status = 'open' AND (?1 IS NULL OR e.assigned_to = ?1)
+            ORDER BY
+                e.created_at, e.escalation_id
+        "#;
+
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(query)
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let escalations = stmt
+            .query_map(params![assigned_to], |row| {
+                Ok(Escalation {
+                    escalation_id: row.get(0)?,
+                    rule_name: row.get(1)?,
+                    student_id: row.get(2)?,
+                    student_name: row.get(3)?,
+                    demerit_id: row.get(4)?,
+                    points: row.get(5)?,
+                    action: row.get(6)?,
+                    assigned_to: row.get(7)?,
+                    assignee_name: row.get(8)?,
+                    created_at: row.get(9)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        escalations.map_err(|e| StoreError::Other(format!("Failed to fetch escalations: {}", e)))
+    }
+
+    fn resolve_escalation(
+        &self,
+        escalation_id: i32,
+        resolved_by: i32,
+        resolution: &str,
+    ) -> Result<(), StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE escalations
+                 SET status = 'resolved', resolved_by = ?1, resolution = ?2,
+                     resolved_at = CURRENT_TIMESTAMP
+                 WHERE escalation_id = ?3",
+                params![resolved_by, resolution, escalation_id],
+            )
+            .map(|_| ())
+            .map_err(|e| StoreError::Other(format!("Failed to resolve escalation: {}", e)))
+    }
+}
+
+impl MeritRepository for SqliteStore {
+    fn list_merit_categories(&self) -> Result<Vec<CategoryOption>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT category_id, category_name, default_points
+                 FROM merit_categories
+                 ORDER BY category_id",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let categories = stmt
+            .query_map([], |row| {
+                Ok(CategoryOption {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    default_points: row.get(2)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        categories
+            .map_err(|e| StoreError::Other(format!("Failed to fetch merit categories: {}", e)))
+    }
+
+    fn merit_category_exists(&self, category_id: i32) -> Result<bool, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM merit_categories WHERE category_id = ?1)",
+                params![category_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| StoreError::Other(format!("Error verifying merit category: {}", e)))
+    }
+
+    fn add_merit(&self, merit: &NewMerit) -> Result<i32, StoreError> {
+        self.conn()?
+            .query_row(
+                "INSERT INTO merit_records
+                    (student_id, teacher_id, category_id, points, description)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 RETURNING merit_id",
+                params![
+                    merit.student_id,
+                    merit.teacher_id,
+                    merit.category_id,
+                    merit.points,
+                    merit.description
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| StoreError::Other(format!("Failed to add merit: {}", e)))
+    }
+
+    fn student_merits(&self, student_id: i32) -> Result<Vec<MeritRecord>, StoreError> {
+        merits_where(&*self.conn()?, "m.student_id = ?1", student_id)
+    }
+
+    fn teacher_merits(&self, teacher_id: i32) -> Result<Vec<MeritRecord>, StoreError> {
+        merits_where(&*self.conn()?, "m.teacher_id = ?1", teacher_id)
+    }
+
+    fn children_merits(&self, parent_id: i32) -> Result<Vec<MeritRecord>, StoreError> {
+        merits_where(
+            &*self.conn()?,
+            "m.student_id IN (SELECT student_id FROM parent_student WHERE parent_id = ?1)",
+            parent_id,
+        )
+    }
+}
+
+impl SessionRepository for SqliteStore {
+    fn create_session(&self, session: &NewSession) -> Result<(), StoreError> {
+        self.conn()?
+            .execute(
+                "INSERT INTO sessions (session_id, user_id, refresh_token_hash, expires_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    session.session_id,
+                    session.user_id,
+                    session.refresh_token_hash,
+                    session.expires_at
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| StoreError::Other(format!("Failed to create session: {}", e)))
+    }
+
+    fn active_session_owner(&self, session_id: &str) -> Result<Option<i32>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT user_id FROM sessions
+                 WHERE session_id = ?1 AND revoked_at IS NULL AND expires_at > CURRENT_TIMESTAMP",
+                params![session_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to load session: {}", e)))
+    }
+
+    fn rotate_refresh_token(
+        &self,
+        session_id: &str,
+        current_hash: &str,
+        new_hash: &str,
+    ) -> Result<bool, StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE sessions SET refresh_token_hash = ?1, last_refreshed_at = CURRENT_TIMESTAMP
+                 WHERE session_id = ?2 AND refresh_token_hash = ?3 AND revoked_at IS NULL",
+                params![new_hash, session_id, current_hash],
+            )
+            .map(|rotated| rotated == 1)
+            .map_err(|e| StoreError::Other(format!("Failed to rotate refresh token: {}", e)))
+    }
+
+    fn session_user(&self, session_id: &str) -> Result<Option<SessionUser>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT u.user_id, u.user_type, u.must_change_password,
+                        u.two_factor_required AND NOT EXISTS(
+                            SELECT 1 FROM user_totp t
+                            WHERE t.user_id = u.user_id AND t.enabled_at IS NOT NULL
+                        )
+                 FROM sessions s JOIN users u ON u.user_id = s.user_id
+                 WHERE s.session_id = ?1 AND s.revoked_at IS NULL
+                   AND s.expires_at > CURRENT_TIMESTAMP",
+                params![session_id],
+                |row| {
+                    Ok(SessionUser {
+                        user_id: row.get(0)?,
+                        user_type: row.get(1)?,
+                        must_change_password: row.get(2)?,
+                        must_enroll_two_factor: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to check session: {}", e)))
+    }
+
+    fn revoke_session(&self, session_id: &str) -> Result<(), StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE sessions SET revoked_at = CURRENT_TIMESTAMP
+                 WHERE session_id = ?1 AND revoked_at IS NULL",
+                params![session_id],
+            )
+            .map(|_| ())
+            .map_err(|e| StoreError::Other(format!("Failed to revoke session: {}", e)))
+    }
+
+    fn revoke_user_sessions(&self, user_id: i32) -> Result<usize, StoreError> {
+        revoke_user_sessions(&*self.conn()?, user_id)
+    }
+}
+
+fn revoke_user_sessions(conn: &Connection, user_id: i32) -> Result<usize, StoreError> {
+    conn.execute(
+        "UPDATE sessions SET revoked_at = CURRENT_TIMESTAMP
+         WHERE user_id = ?1 AND revoked_at IS NULL",
+        params![user_id],
+    )
+    .map_err(|e| StoreError::Other(format!("Failed to revoke sessions: {}", e)))
+}
+
+impl CredentialRepository for SqliteStore {
+    fn sign_in_account_by_email(&self, email: &str) -> Result<Option<User>, StoreError> {
+        sign_in_account_where(&*self.conn()?, "email", &email)
+    }
+
+    fn sign_in_account(&self, user_id: i32) -> Result<Option<User>, StoreError> {
+        sign_in_account_where(&*self.conn()?, "user_id", &user_id)
+    }
+
+    fn set_password(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        must_change: bool,
+    ) -> Result<bool, StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        if !set_password(&tx, user_id, password_hash, must_change)? {
+            return Ok(false);
+        }
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(true)
+    }
+
+    fn add_reset_token(&self, reset: &NewResetToken) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        tx.execute(
+            "UPDATE password_resets SET used_at = CURRENT_TIMESTAMP
+             WHERE user_id = ?1 AND used_at IS NULL",
+            params![reset.user_id],
+        )
+        .map_err(|e| format!("Failed to invalidate earlier reset tokens: {}", e))?;
+        tx.execute(
+            "INSERT INTO password_resets (token_hash, user_id, issued_by, expires_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                reset.token_hash,
+                reset.user_id,
+                reset.issued_by,
+                reset.expires_at
+            ],
+        )
+        .map_err(|e| format!("Failed to create reset token: {}", e))?;
+
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn redeem_reset_token(
+        &self,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> Result<Option<i32>, StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let user_id: Option<i32> = tx
+            .query_row(
+                "UPDATE password_resets SET used_at = CURRENT_TIMESTAMP
+                 WHERE token_hash = ?1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+                 RETURNING user_id",
+                params![token_hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to consume reset token: {}", e))?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+        set_password(&tx, user_id, password_hash, false)?;
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(Some(user_id))
+    }
+}
+
+/// An account with its two-factor state, where `column` equals `value`.
+fn sign_in_account_where(
+    conn: &Connection,
+    column: &str,
+    value: &dyn ToSql,
+) -> Result<Option<User>, StoreError> {
+    conn.query_row(
+        &format!(
+            "SELECT user_id, username, password_hash, email, user_type, first_name, last_name,
+                    must_change_password, two_factor_required,
+                    EXISTS(SELECT 1 FROM user_totp t
+                           WHERE t.user_id = users.user_id AND t.enabled_at IS NOT NULL),
+                    status
+             FROM users WHERE {} = ?1",
+            column
+        ),
+        params![value],
+        |row| {
+            Ok(User {
+                id: row.get(0)?,
+                username: row.get(1)?,
+                password_hash: row.get(2)?,
+                email: row.get(3)?,
+                user_type: row.get(4)?,
+                first_name: row.get(5)?,
+                last_name: row.get(6)?,
+                must_change_password: row.get(7)?,
+                two_factor_required: row.get(8)?,
+                two_factor_enabled: row.get(9)?,
+                status: row.get(10)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| StoreError::Other(format!("Failed to look up user: {}", e)))
+}
+
+/// Stores a password hash and revokes the user's sessions, inside the
+/// caller's transaction. False if there is no such user.
+fn set_password(
+    conn: &Connection,
+    user_id: i32,
+    password_hash: &str,
+    must_change: bool,
+) -> Result<bool, String> {
+    let updated = conn
+        .execute(
+            "UPDATE users SET password_hash = ?1, must_change_password = ?2 WHERE user_id = ?3",
+            params![password_hash, must_change, user_id],
+        )
+        .map_err(|e| format!("Failed to update password: {}", e))?;
+    if updated == 0 {
+        return Ok(false);
+    }
+    revoke_user_sessions(conn, user_id)?;
+    Ok(true)
+}
+
+impl LoginFailureRepository for SqliteStore {
+    fn login_throttle(
+        &self,
+        account: &str,
+        ip: &str,
+        since: &str,
+    ) -> Result<LoginThrottle, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT
+                    COALESCE(MAX(CASE WHEN last_failed_at >= ?3 THEN failed_count ELSE 0 END), 0),
+                    MAX(CASE WHEN locked_until > CURRENT_TIMESTAMP THEN locked_until END)
+                 FROM login_failures
+                 WHERE (scope = 'account' AND subject = ?1) OR (scope = 'ip' AND subject = ?2)",
+                params![account, ip, since],
+                |row| {
+                    Ok(LoginThrottle {
+                        recent_failures: row.get(0)?,
+                        locked_until: row.get(1)?,
+                    })
+                },
+            )
+            .map_err(|e| StoreError::Other(format!("Failed to check login failures: {}", e)))
+    }
+
+    fn record_login_failure(
+        &self,
+        scope: &str,
+        subject: &str,
+        since: &str,
+        locked_until: &str,
+        limit: i64,
+    ) -> Result<bool, StoreError> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO login_failures (scope, subject, failed_count, last_failed_at)
+             VALUES (?1, ?2, 1, CURRENT_TIMESTAMP)
+             ON CONFLICT (scope, subject) DO UPDATE SET
+                failed_count = CASE WHEN last_failed_at < ?3 THEN 1 ELSE failed_count + 1 END,
+                last_failed_at = CURRENT_TIMESTAMP",
+            params![scope, subject, since],
+        )
+        .map_err(|e| format!("Failed to record login failure: {}", e))?;
+
+        conn.execute(
+            "UPDATE login_failures SET locked_until = ?3
+             WHERE scope = ?1 AND subject = ?2 AND failed_count >= ?4",
+            params![scope, subject, locked_until, limit],
+        )
+        .map(|locked| locked > 0)
+        .map_err(|e| StoreError::Other(format!("Failed to lock login: {}", e)))
+    }
+
+    fn clear_login_failures(&self, scope: &str, subject: &str) -> Result<bool, StoreError> {
+        self.conn()?
+            .execute(
+                "DELETE FROM login_failures WHERE scope = ?1 AND subject = ?2",
+                params![scope, subject],
+            )
+            .map(|removed| removed > 0)
+            .map_err(|e| StoreError::Other(format!("Failed to clear login failures: {}", e)))
+    }
+
+    fn login_failures(&self, since: &str) -> Result<Vec<LockoutEntry>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT scope, subject, failed_count, last_failed_at, locked_until,
+                        COALESCE(locked_until > CURRENT_TIMESTAMP, 0)
+                 FROM login_failures
+                 WHERE last_failed_at >= ?1 OR locked_until > CURRENT_TIMESTAMP
+                 ORDER BY last_failed_at DESC",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let entries = stmt
+            .query_map(params![since], |row| {
+                Ok(LockoutEntry {
+                    scope: row.get(0)?,
+                    subject: row.get(1)?,
+                    failed_count: row.get(2)?,
+                    last_failed_at: row.get(3)?,
+                    locked_until: row.get(4)?,
+                    locked: row.get(5)?,
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        entries.map_err(|e| StoreError::Other(format!("Failed to fetch lockouts: {}", e)))
+    }
+}
+
+impl TwoFactorRepository for SqliteStore {
+    fn totp_secret(&self, user_id: i32, pending: bool) -> Result<Option<TotpSecret>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT secret, last_used_step FROM user_totp
+                 WHERE user_id = ?1 AND (enabled_at IS NULL) = ?2",
+                params![user_id, pending],
+                |row| {
+                    Ok(TotpSecret {
+                        secret: row.get(0)?,
+                        last_used_step: row.get(1)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to load TOTP secret: {}", e)))
+    }
+
+    fn save_totp_secret(&self, user_id: i32, secret: &str) -> Result<(), StoreError> {
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO user_totp (user_id, secret) VALUES (?1, ?2)",
+                params![user_id, secret],
+            )
+            .map(|_| ())
+            .map_err(|e| StoreError::Other(format!("Failed to store TOTP secret: {}", e)))
+    }
+
+    fn use_totp_step(&self, user_id: i32, step: i64) -> Result<bool, StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE user_totp SET last_used_step = ?1
+                 WHERE user_id = ?2 AND (last_used_step IS NULL OR last_used_step < ?1)",
+                params![step, user_id],
+            )
+            .map(|updated| updated > 0)
+            .map_err(|e| StoreError::Other(format!("Failed to record TOTP use: {}", e)))
+    }
+
+    fn enable_totp(&self, user_id: i32, recovery_code_hashes: &[String]) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        tx.execute(
+            "UPDATE user_totp SET enabled_at = CURRENT_TIMESTAMP WHERE user_id = ?1",
+            params![user_id],
+        )
+        .map_err(|e| format!("Failed to enable two-factor authentication: {}", e))?;
+        replace_recovery_codes(&tx, user_id, recovery_code_hashes)?;
+
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn replace_recovery_codes(
+        &self,
+        user_id: i32,
+        code_hashes: &[String],
+    ) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        replace_recovery_codes(&tx, user_id, code_hashes)?;
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn use_recovery_code(&self, user_id: i32, code_hash: &str) -> Result<bool, StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE totp_recovery_codes SET used_at = CURRENT_TIMESTAMP
+                 WHERE code_hash = ?1 AND user_id = ?2 AND used_at IS NULL",
+                params![code_hash, user_id],
+            )
+            .map(|consumed| consumed > 0)
+            .map_err(|e| StoreError::Other(format!("Failed to check recovery code: {}", e)))
+    }
+
+    fn remove_two_factor(&self, user_id: i32) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        tx.execute(
+            "DELETE FROM totp_recovery_codes WHERE user_id = ?1",
+            params![user_id],
+        )
+        .map_err(|e| format!("Failed to remove recovery codes: {}", e))?;
+        tx.execute("DELETE FROM user_totp WHERE user_id = ?1", params![user_id])
+            .map_err(|e| format!("Failed to disable two-factor authentication: {}", e))?;
+
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn set_two_factor_required(&self, user_id: i32, required: bool) -> Result<bool, StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE users SET two_factor_required = ?1 WHERE user_id = ?2",
+                params![required, user_id],
+            )
+            .map(|updated| updated > 0)
+            .map_err(|e| StoreError::Other(format!("Failed to update user: {}", e)))
+    }
+}
+
+fn replace_recovery_codes(
+    conn: &Connection,
+    user_id: i32,
+    code_hashes: &[String],
+) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM totp_recovery_codes WHERE user_id = ?1",
+        params![user_id],
+    )
+    .map_err(|e| format!("Failed to remove recovery codes: {}", e))?;
+
+    for code_hash in code_hashes {
+        conn.execute(
+            "INSERT INTO totp_recovery_codes (code_hash, user_id) VALUES (?1, ?2)",
+            params![code_hash, user_id],
+        )
+        .map_err(|e| format!("Failed to store recovery code: {}", e))?;
+    }
+    Ok(())
+}
+
+impl RegistrationRepository for SqliteStore {
+    fn create_invite(&self, invite: &NewInviteCode) -> Result<i32, StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let invite_id: i32 = tx
+            .query_row(
+                "INSERT INTO invite_codes (code_hash, created_by, email, max_uses, expires_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 RETURNING invite_id",
+                params![
+                    invite.code_hash,
+                    invite.created_by,
+                    invite.email,
+                    invite.max_uses,
+                    invite.expires_at
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to create invite: {}", e))?;
+
+        for student_id in &invite.student_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO invite_students (invite_id, student_id) VALUES (?1, ?2)",
+                params![invite_id, student_id],
+            )
+            .map_err(|e| format!("Failed to link invite to student: {}", e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(invite_id)
+    }
+
+    fn list_invites(&self) -> Result<Vec<InviteSummary>, StoreError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT i.invite_id, i.created_by, i.email, i.max_uses, i.use_count,
+                        i.created_at, i.expires_at, i.revoked_at IS NOT NULL,
+                        (SELECT GROUP_CONCAT(student_id) FROM invite_students s
+                         WHERE s.invite_id = i.invite_id)
+                 FROM invite_codes i
+                 ORDER BY i.created_at DESC, i.invite_id DESC",
+            )
+            .map_err(|e| format!("Query preparation error: {}", e))?;
+
+        let invites = stmt
+            .query_map([], |row| {
+                let student_ids: Option<String> = row.get(8)?;
+                Ok(InviteSummary {
+                    invite_id: row.get(0)?,
+                    created_by: row.get(1)?,
+                    email: row.get(2)?,
+                    max_uses: row.get(3)?,
+                    use_count: row.get(4)?,
+                    created_at: row.get(5)?,
+                    expires_at: row.get(6)?,
+                    revoked: row.get(7)?,
+                    student_ids: student_ids
+                        .unwrap_or_default()
+                        .split(',')
+                        .filter_map(|id| id.parse().ok())
+                        .collect(),
+                })
+            })
+            .and_then(|mapped| mapped.collect());
+
+        invites.map_err(|e| StoreError::Other(format!("Failed to fetch invites: {}", e)))
+    }
+
+    fn revoke_invite(&self, invite_id: i32) -> Result<bool, StoreError> {
+        self.conn()?
+            .execute(
+                "UPDATE invite_codes SET revoked_at = COALESCE(revoked_at, CURRENT_TIMESTAMP)
+                 WHERE invite_id = ?1",
+                params![invite_id],
+            )
+            .map(|updated| updated > 0)
+            .map_err(|e| StoreError::Other(format!("Failed to revoke invite: {}", e)))
+    }
+
+    fn register_parent(&self, registration: &NewRegistration) -> Result<Option<i32>, StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let student_ids = match &registration.invite_code_hash {
+            Some(code_hash) => match redeem_invite(&tx, code_hash, &registration.email)? {
+                Some(student_ids) => student_ids,
+                None => return Ok(None),
+            },
+            None => Vec::new(),
+        };
+
+        let user_id: i32 = tx
+            .query_row(
+                "INSERT INTO users
+                     (username, password_hash, email, user_type, first_name, last_name, status)
+                 VALUES (?1, ?2, ?3, 'parent', ?4, ?5, 'pending')
+                 RETURNING user_id",
+                params![
+                    registration.username,
+                    registration.password_hash,
+                    registration.email,
+                    registration.first_name,
+                    registration.last_name
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| conflict_on_unique(e, ACCOUNT_EXISTS))?;
+
+        let parent_id: i32 = tx
+            .query_row(
+                "INSERT INTO parents (user_id) VALUES (?1) RETURNING parent_id",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to create parent record: {}", e))?;
+
+        for student_id in student_ids {
+            tx.execute(
+                "INSERT INTO parent_student (parent_id, student_id) VALUES (?1, ?2)",
+                params![parent_id, student_id],
+            )
+            .map_err(|e| format!("Failed to link student: {}", e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(Some(user_id))
+    }
+
+    fn add_verification_token(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: &str,
+    ) -> Result<(), StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        tx.execute(
+            "UPDATE email_verifications SET used_at = CURRENT_TIMESTAMP
+             WHERE user_id = ?1 AND used_at IS NULL",
+            params![user_id],
+        )
+        .map_err(|e| format!("Failed to invalidate earlier verification links: {}", e))?;
+        tx.execute(
+            "INSERT INTO email_verifications (token_hash, user_id, expires_at) VALUES (?1, ?2, ?3)",
+            params![token_hash, user_id, expires_at],
+        )
+        .map_err(|e| format!("Failed to create verification token: {}", e))?;
+
+        tx.commit()
+            .map_err(|e| StoreError::Other(format!("Failed to commit transaction: {}", e)))
+    }
+
+    fn redeem_verification_token(&self, token_hash: &str) -> Result<Option<i32>, StoreError> {
+        let conn = self.conn()?;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let user_id: Option<i32> = tx
+            .query_row(
+                "UPDATE email_verifications SET used_at = CURRENT_TIMESTAMP
+                 WHERE token_hash = ?1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+                 RETURNING user_id",
+                params![token_hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to consume verification token: {}", e))?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+        tx.execute(
+            "UPDATE users SET status = 'active' WHERE user_id = ?1",
+            params![user_id],
+        )
+        .map_err(|e| format!("Failed to activate account: {}", e))?;
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        Ok(Some(user_id))
+    }
+
+    fn pending_account(&self, email: &str, sent_after: &str) -> Result<Option<i32>, StoreError> {
+        self.conn()?
+            .query_row(
+                "SELECT u.user_id FROM users u
+                 WHERE u.email = ?1 AND u.status = 'pending'
+                   AND NOT EXISTS (
+                       SELECT 1 FROM email_verifications v
+                       WHERE v.user_id = u.user_id AND v.created_at > ?2
+                   )",
+                params![email, sent_after],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StoreError::Other(format!("Failed to look up account: {}", e)))
+    }
+}
+
+/// Uses up one redemption of a valid invite for `email`, inside the caller's
+/// transaction, and returns the students it links to. `None` if the invite
+/// cannot be redeemed by that address.
+fn redeem_invite(
+    conn: &Connection,
+    code_hash: &str,
+    email: &str,
+) -> Result<Option<Vec<i32>>, String> {
+    let invite: Option<(i32, Option<String>)> = conn
+        .query_row(
+            "UPDATE invite_codes SET use_count = use_count + 1
+             WHERE code_hash = ?1 AND revoked_at IS NULL
+               AND use_count < max_uses AND expires_at > CURRENT_TIMESTAMP
+             RETURNING invite_id, email",
+            params![code_hash],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to redeem invite: {}", e))?;
+
+    let Some((invite_id, invite_email)) = invite else {
+        return Ok(None);
+    };
+    if invite_email.is_some_and(|invited| invited != email.trim().to_lowercase()) {
+        return Ok(None);
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT student_id FROM invite_students WHERE invite_id = ?1")
+        .map_err(|e| format!("Query preparation error: {}", e))?;
+    let student_ids = stmt
+        .query_map(params![invite_id], |row| row.get(0))
+        .and_then(|mapped| mapped.collect());
+
+    student_ids
+        .map(Some)
+        .map_err(|e| format!("Failed to fetch invited students: {}", e))
+}
+
+/// Turns a unique constraint violation into a conflict with `message`, and
+/// any other database error into an internal one.
+fn conflict_on_unique(e: rusqlite::Error, message: &str) -> StoreError {
+    match e.sqlite_error_code() {
+        Some(rusqlite::ErrorCode::ConstraintViolation) if e.to_string().contains("UNIQUE") => {
+            StoreError::Conflict(message.to_string())
+        }
+        _ => StoreError::Other(e.to_string()),
+    }
+}
+
+/// Merits matching `filter`, which refers to `id` as `?1`, newest first.
+fn merits_where(conn: &Connection, filter: &str, id: i32) -> Result<Vec<MeritRecord>, StoreError> {
+    let query = format!(
+        r#"
+        SELECT
+            m.merit_id,
+            m.student_id,
+            (SELECT first_name || ' ' || last_name FROM users WHERE user_id = s.user_id) as student_name,
+            c.category_name,
+            m.points,
+            (SELECT first_name || ' ' || last_name FROM users WHERE user_id = t.user_id) as teacher_name,
+            m.description,
+            m.date_awarded
+        FROM
+            merit_records m
+        JOIN
+            students s ON m.student_id = s.student_id
+        JOIN
+            teachers t ON m.teacher_id = t.teacher_id
+        JOIN
+            merit_categories c ON m.category_id = c.category_id
+        WHERE
+            {}
+        ORDER BY
+            m.date_awarded DESC, m.merit_id DESC
+    "#,
+        filter
+    );
+
+    let mut stmt = conn
+        .prepare(&query)
+        .map_err(|e| format!("Query preparation error: {}", e))?;
+
+    let merits = stmt
+        .query_map(params![id], |row| {
+            Ok(MeritRecord {
+                merit_id: row.get(0)?,
+                student_id: row.get(1)?,
+                student_name: row.get(2)?,
+                category_name: row.get(3)?,
+                points: row.get(4)?,
+                teacher_name: row.get(5)?,
+                description: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+                date_awarded: row.get(7)?,
+            })
+        })
+        .and_then(|mapped| mapped.collect());
+
+    merits.map_err(|e| StoreError::Other(format!("Failed to fetch merits: {}", e)))
+}
+
+/// Summaries of the students matching `filter`, which may refer to `id` as
+/// `?1`, highest demerit total first.
+fn summaries_where(
+    conn: &Connection,
+    filter: &str,
+    id: Option<i32>,
+) -> Result<Vec<StudentDemeritSummary>, StoreError> {
+    let query = format!(
+        r#"
+        SELECT
+            s.student_id,
+            u.first_name || ' ' || u.last_name AS student_name,
+            COALESCE(SUM(dr.points), 0) AS total_points,
+            (SELECT COALESCE(SUM(m.points), 0) FROM merit_records m
+             WHERE m.student_id = s.student_id) AS merit_points,
+            (SELECT category_name FROM demerit_categories c
+             JOIN demerit_records dr2 ON c.category_id = dr2.category_id
+             WHERE dr2.student_id = s.student_id AND dr2.voided_at IS NULL
+             ORDER BY dr2.date_issued DESC, dr2.demerit_id DESC
+             LIMIT 1) AS recent_demerit,
+            s.grade_level,
+            s.class_section
+        FROM
+            students s
+        JOIN
+            users u ON s.user_id = u.user_id
+        LEFT JOIN
+            demerit_records dr ON s.student_id = dr.student_id AND dr.voided_at IS NULL
+        WHERE
+            {}
+        GROUP BY
+            s.student_id, u.first_name, u.last_name, s.grade_level, s.class_section
+        ORDER BY
+            total_points DESC
+    "#,
+        filter
+    );
+
+    let mut stmt = conn
+        .prepare(&query)
+        .map_err(|e| format!("Query preparation error: {}", e))?;
+
+    let summaries = stmt
+        .query_map(params_from_iter(id), |row| {
+            let total_points = row.get(2)?;
+            Ok(StudentDemeritSummary {
+                student_id: row.get(0)?,
+                student_name: row.get(1)?,
+                total_points,
+                effective_points: total_points,
+                merit_points: row.get(3)?,
+                net_points: total_points,
+                recent_demerit: row.get(4)?,
+                grade_level: row.get(5)?,
+                class_section: row.get(6)?,
+            })
+        })
+        .and_then(|mapped| mapped.collect());
+
+    summaries.map_err(|e| StoreError::Other(format!("Error collecting summaries: {}", e)))
+}
+
+/// Looks up one appeal by `column`, which is `appeal_id` or `demerit_id`.
+fn find_appeal_where(
+    conn: &Connection,
+    column: &str,
+    id: i32,
+) -> Result<Option<StoredAppeal>, StoreError> {
+    let appeal = conn
+        .query_row(
+            &format!(
+                "SELECT appeal_id, demerit_id, status FROM demerit_appeals WHERE {} = ?1",
+                column
+            ),
+            params![id],
+            |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, i32>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| format!("Failed to find appeal: {}", e))?;
+
+    appeal
+        .map(|(appeal_id, demerit_id, status)| {
+            Ok(StoredAppeal {
+                appeal_id,
+                demerit_id,
+                status: status.parse()?,
+            })
+        })
+        .transpose()
+}
+
+/// Inserts a demerit with its "created" revision and returns its id.
+fn insert_demerit(conn: &Connection, demerit: &NewDemerit) -> Result<i32, String> {
+    let demerit_id: i32 = conn
+        .query_row(
+            "INSERT INTO demerit_records (student_id, teacher_id, category_id, points, description)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             RETURNING demerit_id",
+            params![
+                demerit.student_id,
+                demerit.teacher_id,
+                demerit.category_id,
+                demerit.points,
+                demerit.description
+            ],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to add demerit: {}", e))?;
+    record_revision(conn, demerit_id, "created", None, None)?;
+    Ok(demerit_id)
+}
+
+/// Copies the current values of a demerit into `demerit_revisions`. Without
+/// `changed_by` the change is attributed to the issuing teacher.
+fn record_revision(
+    conn: &Connection,
+    demerit_id: i32,
+    action: &str,
+    changed_by: Option<i32>,
+    reason: Option<&str>,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO demerit_revisions
+            (demerit_id, changed_by, action, student_id, category_id, points, description, reason)
+         SELECT dr.demerit_id, COALESCE(?1, t.user_id), ?2, dr.student_id, dr.category_id,
+                dr.points, dr.description, ?3
+         FROM demerit_records dr
+         JOIN teachers t ON dr.teacher_id = t.teacher_id
+         WHERE dr.demerit_id = ?4",
+        params![changed_by, action, reason, demerit_id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to record demerit revision: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository;
+
+    #[test]
+    fn satisfies_store_contract() {
+        repository::check_store(&memory_store());
+    }
+
+    #[test]
+    fn unique_violations_become_conflicts() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (name TEXT UNIQUE); INSERT INTO t VALUES ('a');")
+            .unwrap();
+        let e = conn.execute("INSERT INTO t VALUES ('a')", []).unwrap_err();
+
+        assert_eq!(
+            conflict_on_unique(e, "Name is taken"),
+            StoreError::Conflict("Name is taken".to_string())
+        );
+    }
+}
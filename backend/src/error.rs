@@ -0,0 +1,249 @@
+//! The error type shared by services and handlers, and how it is sent to
+//! clients.
+//!
+//! Every error answers with an [`ErrorResponse`] carrying a stable `code` for
+//! programs and a `message` for people. Internal errors are logged in full but
+//! reach the client only as a generic message, so database details never leak.
+
+use std::fmt;
+
+use actix_web::error::{BlockingError, JsonPayloadError};
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use tracing::error;
+
+use crate::database::repository::StoreError;
+use crate::models::{ErrorResponse, FieldError};
+
+const INTERNAL_MESSAGE: &str = "Something went wrong, please try again later";
+
+#[derive(Debug, PartialEq)]
+pub enum AppError {
+    /// The request cannot be understood, e.g. malformed JSON.
+    BadRequest(String),
+    /// A reset, verification or invite token that is unknown, used or expired.
+    InvalidToken(String),
+    /// No valid access or refresh token was presented.
+    Unauthenticated(String),
+    InvalidCredentials,
+    /// Wrong second-factor code at login.
+    InvalidCode,
+    InvalidChallenge,
+    EmailNotVerified,
+    PasswordChangeRequired,
+    TwoFactorEnrollmentRequired,
+    /// The registration policy does not admit this request.
+    RegistrationClosed(String),
+    /// The caller may not see or change the record.
+    Forbidden(String),
+    NotFound(String),
+    /// The record already exists.
+    Conflict(String),
+    /// One or more fields of the request are invalid.
+    Validation(Vec<FieldError>),
+    TooManyAttempts {
+        retry_after_secs: Option<i64>,
+    },
+    /// Anything the client cannot fix. The detail is only logged.
+    Internal(String),
+}
+
+impl AppError {
+    /// A validation error for a single field.
+    pub fn invalid(field: &str, message: impl Into<String>) -> Self {
+        AppError::Validation(vec![FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }])
+    }
+
+    /// Stable identifier of the error kind, for clients to branch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::InvalidToken(_) => "invalid_token",
+            AppError::Unauthenticated(_) => "unauthenticated",
+            AppError::InvalidCredentials => "invalid_credentials",
+            AppError::InvalidCode => "invalid_code",
+            AppError::InvalidChallenge => "invalid_challenge",
+            AppError::EmailNotVerified => "email_not_verified",
+            AppError::PasswordChangeRequired => "password_change_required",
+            AppError::TwoFactorEnrollmentRequired => "two_factor_enrollment_required",
+            AppError::RegistrationClosed(_) => "registration_closed",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Validation(_) => "validation_failed",
+            AppError::TooManyAttempts { .. } => "too_many_attempts",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::InvalidToken(message)
+            | AppError::Unauthenticated(message)
+            | AppError::RegistrationClosed(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::Internal(message) => f.write_str(message),
+            AppError::InvalidCredentials => f.write_str("Invalid email or password"),
+            AppError::InvalidCode => f.write_str("Invalid authentication code"),
+            AppError::InvalidChallenge => f.write_str("Login challenge is invalid or has expired"),
+            AppError::EmailNotVerified => {
+                f.write_str("Please verify your email address before logging in")
+            }
+            AppError::PasswordChangeRequired => {
+                f.write_str("You must change your password before continuing")
+            }
+            AppError::TwoFactorEnrollmentRequired => {
+                f.write_str("You must set up two-factor authentication before continuing")
+            }
+            AppError::Validation(fields) => {
+                let messages: Vec<&str> = fields.iter().map(|e| e.message.as_str()).collect();
+                f.write_str(&messages.join("; "))
+            }
+            AppError::TooManyAttempts { .. } => {
+                f.write_str("Too many failed login attempts, please try again later")
+            }
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) | AppError::InvalidToken(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthenticated(_)
+            | AppError::InvalidCredentials
+            | AppError::InvalidCode
+            | AppError::InvalidChallenge => StatusCode::UNAUTHORIZED,
+            AppError::EmailNotVerified
+            | AppError::PasswordChangeRequired
+            | AppError::TwoFactorEnrollmentRequired
+            | AppError::RegistrationClosed(_)
+            | AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyAttempts { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let AppError::TooManyAttempts {
+            retry_after_secs: Some(secs),
+        } = self
+        {
+            response.insert_header((header::RETRY_AFTER, secs.to_string()));
+        }
+
+        let message = match self {
+            AppError::Internal(detail) => {
+                error!(error = %detail, "Internal error");
+                INTERNAL_MESSAGE.to_string()
+            }
+            other => other.to_string(),
+        };
+        let fields = match self {
+            AppError::Validation(fields) => fields.clone(),
+            _ => Vec::new(),
+        };
+
+        response.json(ErrorResponse {
+            code: self.code().to_string(),
+            message,
+            fields,
+        })
+    }
+}
+
+impl From<StoreError> for AppError {
+    fn from(error: StoreError) -> Self {
+        match error {
+            StoreError::NotFound(message) => AppError::NotFound(message),
+            StoreError::Conflict(message) => AppError::Conflict(message),
+            StoreError::Other(message) => AppError::Internal(message),
+        }
+    }
+}
+
+impl From<r2d2::Error> for AppError {
+    fn from(e: r2d2::Error) -> Self {
+        AppError::Internal(format!("Database connection error: {}", e))
+    }
+}
+
+impl From<BlockingError> for AppError {
+    fn from(e: BlockingError) -> Self {
+        AppError::Internal(format!("Blocking task failed: {}", e))
+    }
+}
+
+/// For `demerit-admin`, which reports errors as plain text.
+impl From<AppError> for String {
+    fn from(error: AppError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Answers malformed JSON bodies in the same shape as every other error.
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let message = match &err {
+        JsonPayloadError::Deserialize(e) => format!("Invalid request body: {}", e),
+        JsonPayloadError::ContentType => "Expected a JSON request body".to_string(),
+        other => other.to_string(),
+    };
+    AppError::BadRequest(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    async fn body(error: AppError) -> (StatusCode, ErrorResponse) {
+        let response = error.error_response();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn internal_errors_are_not_sent_to_clients() {
+        let (status, body) = body(AppError::Internal(
+            "Failed to create user: UNIQUE constraint failed: users.email".to_string(),
+        ))
+        .await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.code, "internal_error");
+        assert!(!body.message.contains("UNIQUE"));
+    }
+
+    #[actix_web::test]
+    async fn validation_errors_list_each_field() {
+        let (status, body) = body(AppError::Validation(vec![
+            FieldError {
+                field: "email".to_string(),
+                message: "Enter a valid email address".to_string(),
+            },
+            FieldError {
+                field: "password".to_string(),
+                message: "Password must be at least 8 characters".to_string(),
+            },
+        ]))
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body.code, "validation_failed");
+        assert_eq!(body.fields.len(), 2);
+        assert_eq!(body.fields[1].field, "password");
+    }
+}
@@ -1,31 +1,22 @@
-use actix_web::{get, put, web, HttpResponse, Responder};
-use rusqlite::params;
+use std::path::Path;
+
+use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tracing::info;
 
-use crate::database::db;
-use crate::models::{AdminUserRecord, ErrorResponse};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct StudentInfo {
-    pub id: i32,
-    pub name: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AdminUserData {
-    pub user_id: i32,
-    pub username: String,
-    pub email: String,
-    pub first_name: String,
-    pub last_name: String,
-    pub user_type: String,
-    pub created_at: String,            // Make sure this field exists
-    pub grade_level: Option<i32>,      // Make sure this is Option<i32>
-    pub class_section: Option<String>, // Make sure this is Option<String>
-    pub total_demerits: i32,           // Make sure this field exists
-    pub children: Vec<StudentInfo>,
-}
+use crate::config;
+use crate::database::backup;
+use crate::database::db::DbPool;
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::handlers::util::reports;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::AdminUserRecord;
+use crate::services::lockout::{self, ClearLockoutRequest};
+use crate::services::registration::{self, NewInvite};
+use crate::services::users::UserService;
+use crate::services::{password, session, two_factor};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateUserRoleRequest {
@@ -33,339 +24,252 @@ pub struct UpdateUserRoleRequest {
     pub new_role: String,
 }
 
-#[get("/admin_data")]
-pub async fn get_admin_data() -> impl Responder {
-    let conn = match db::get_db_connection() {
-        Ok(conn) => conn,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ErrorResponse {
-                message: format!("Database connection error: {}", e),
-            })
-        }
-    };
+pub async fn update_user_role(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    req: web::Json<UpdateUserRoleRequest>,
+) -> Result<HttpResponse, AppError> {
+    info!(
+        admin_id = admin.user_id,
+        user_id = req.user_id,
+        new_role = %req.new_role,
+        "Updating user role"
+    );
+    web::block(move || UserService::new(store.get_ref()).change_role(req.user_id, &req.new_role))
+        .await??;
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "User role updated successfully"
+    })))
+}
 
-    // Create a more comprehensive query that includes all necessary fields
-    // Note that we're using LEFT JOIN to get student-specific data for students
-    let query = r#"
-        SELECT
-            u.user_id,
-            u.username,
-            u.email,
-            u.first_name,
-            u.last_name,
-            u.user_type,
-            u.created_at,
-            s.grade_level,
-            s.class_section,
-            (SELECT COALESCE(SUM(dr.points), 0) FROM demerit_records dr
-             JOIN students s2 ON dr.student_id = s2.student_id
-             WHERE s2.user_id = u.user_id) as total_demerits
-        FROM users u
-        LEFT JOIN students s ON u.user_id = s.user_id
-    "#;
+pub async fn get_admin_data(store: web::Data<dyn Store>) -> Result<HttpResponse, AppError> {
+    let accounts = web::block(move || reports(store.get_ref()).accounts()).await??;
+    Ok(HttpResponse::Ok().json(accounts))
+}
 
-    let mut stmt = match conn.prepare(query) {
-        Ok(stmt) => stmt,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ErrorResponse {
-                message: format!("Query preparation error: {}", e),
-            })
-        }
-    };
+pub async fn update_user(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    req: web::Json<AdminUserRecord>,
+) -> Result<HttpResponse, AppError> {
+    info!(
+        admin_id = admin.user_id,
+        user_id = req.user_id,
+        user_type = %req.user_type,
+        "Updating user"
+    );
+    web::block(move || UserService::new(store.get_ref()).update_account(&req)).await??;
 
-    let user_result = stmt.query_map([], |row| {
-        // Extract the grade_level and class_section values explicitly
-        let grade_level: Option<i32> = row.get(7)?;
-        let class_section: Option<String> = row.get(8)?;
-        let total_demerits: i32 = row.get(9)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "User updated successfully"
+    })))
+}
 
-        // Debug logging to see what's coming from the database
-        println!(
-            "User {} grade_level: {:?}, class_section: {:?}",
-            row.get::<_, i32>(0)?,
-            grade_level,
-            class_section
-        );
+/// Signs a user out everywhere, e.g. when a staff member leaves the school.
+pub async fn revoke_user_sessions(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = path.into_inner();
+    info!(admin_id = admin.user_id, user_id, "Revoking all sessions");
 
-        Ok(AdminUserData {
-            user_id: row.get(0)?,
-            username: row.get(1)?,
-            email: row.get(2)?,
-            first_name: row.get(3)?,
-            last_name: row.get(4)?,
-            user_type: row.get(5)?,
-            created_at: row.get::<_, String>(6)?,
-            grade_level,   // Include the grade_level
-            class_section, // Include the class_section
-            total_demerits,
-            children: Vec::new(), // Will be populated for parents later
-        })
-    });
+    let revoked =
+        web::block(move || session::revoke_all_sessions(store.get_ref(), user_id)).await??;
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": format!("Revoked {} session(s)", revoked),
+        "revoked_sessions": revoked
+    })))
+}
 
-    let mut users = match user_result {
-        Ok(mapped) => {
-            let collected: Result<Vec<AdminUserData>, _> = mapped.collect();
-            match collected {
-                Ok(users) => users,
-                Err(e) => {
-                    return HttpResponse::InternalServerError().json(ErrorResponse {
-                        message: format!("Error collecting users: {}", e),
-                    })
-                }
-            }
-        }
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(ErrorResponse {
-                message: format!("Query execution error: {}", e),
-            })
-        }
-    };
+/// Issues a single-use password reset token for a user. The admin passes the
+/// token on to the user, who redeems it at `/reset_password`.
+pub async fn issue_password_reset(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = path.into_inner();
+    info!(admin_id = admin.user_id, user_id, "Issuing password reset");
 
-    // Fetch children for parents
-    for user in &mut users {
-        if user.user_type == "parent" {
-            let children_query = r#"
-                SELECT
-                    s.student_id,
-                    u.first_name || ' ' || u.last_name as full_name
-                FROM parent_student ps
-                JOIN parents p ON ps.parent_id = p.parent_id
-                JOIN students s ON ps.student_id = s.student_id
-                JOIN users u ON s.user_id = u.user_id
-                WHERE p.user_id = ?1
-            "#;
+    let reset =
+        web::block(move || password::issue_reset_token(store.get_ref(), user_id, admin.user_id))
+            .await??;
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "reset_token": reset.token,
+        "expires_at": reset.expires_at
+    })))
+}
 
-            let mut children_stmt = match conn.prepare(children_query) {
-                Ok(stmt) => stmt,
-                Err(_) => continue, // Skip if query fails
-            };
+/// Lists accounts and client addresses with recent failed logins or an
+/// active lockout.
+pub async fn get_lockouts(store: web::Data<dyn Store>) -> Result<HttpResponse, AppError> {
+    let lockouts = web::block(move || lockout::list(store.get_ref())).await??;
+    Ok(HttpResponse::Ok().json(lockouts))
+}
 
-            let children_result = children_stmt.query_map([&user.user_id], |row| {
-                Ok(StudentInfo {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                })
-            });
+/// Lifts the lockout of an account (by email) or a client address.
+pub async fn clear_lockout(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    req: web::Json<ClearLockoutRequest>,
+) -> Result<HttpResponse, AppError> {
+    info!(
+        admin_id = admin.user_id,
+        scope = %req.scope,
+        "Clearing login lockout"
+    );
 
-            if let Ok(mapped_children) = children_result {
-                if let Ok(children) = mapped_children.collect::<Result<Vec<StudentInfo>, _>>() {
-                    user.children = children;
-                }
-            }
-        }
+    let cleared =
+        web::block(move || lockout::clear(store.get_ref(), &req.scope, &req.subject)).await??;
+    if !cleared {
+        return Err(AppError::NotFound("No lockout found".to_string()));
     }
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Lockout cleared"
+    })))
+}
 
-    HttpResponse::Ok().json(users)
+#[derive(Debug, Deserialize)]
+pub struct RequireTwoFactorRequest {
+    pub required: bool,
 }
 
-#[put("/update_user")]
-pub async fn update_user(req: web::Json<AdminUserRecord>) -> impl Responder {
-    println!(
-        "UPDATE USER HANDLER CALLED with user_id: {} and user_type: {}",
-        req.user_id, req.user_type
+/// Makes two-factor authentication mandatory for an admin or teacher account.
+/// Users without it are asked to enroll before they can use anything else.
+pub async fn require_two_factor(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    path: web::Path<i32>,
+    req: web::Json<RequireTwoFactorRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = path.into_inner();
+    let required = req.required;
+    info!(
+        admin_id = admin.user_id,
+        user_id, required, "Setting two-factor requirement"
     );
 
-    let mut conn = match db::get_db_connection() {
-        Ok(conn) => {
-            println!("Database connection successful");
-            conn
-        }
-        Err(e) => {
-            println!("Database connection failed: {}", e);
-            return HttpResponse::InternalServerError().json(ErrorResponse {
-                message: format!("Database connection error: {}", e),
-            });
-        }
-    };
-
-    // Start a transaction to ensure all operations succeed or fail together
-    let tx = match conn.transaction() {
-        Ok(tx) => {
-            println!("Transaction started successfully");
-            tx
-        }
-        Err(e) => {
-            println!("Failed to start transaction: {}", e);
-            return HttpResponse::InternalServerError().json(ErrorResponse {
-                message: format!("Failed to start transaction: {}", e),
-            });
-        }
-    };
-
-    // Step 1: Get the current user data to check if role is changing
-    let current_user_type: String = match tx.query_row(
-        "SELECT user_type FROM users WHERE user_id = ?1",
-        params![req.user_id],
-        |row| row.get(0),
-    ) {
-        Ok(user_type) => {
-            println!("Current user_type: {}", user_type);
-            user_type
-        }
-        Err(e) => {
-            println!("User not found: {}", e);
-            return HttpResponse::NotFound().json(ErrorResponse {
-                message: format!("User not found: {}", e),
-            });
-        }
-    };
-
-    // Step 2: Update the users table
-    match tx.execute(
-        "UPDATE users SET first_name = ?1, last_name = ?2, email = ?3, username = ?4, user_type = ?5 WHERE user_id = ?6",
-        params![
-            req.first_name,
-            req.last_name,
-            req.email,
-            req.username,
-            req.user_type,
-            req.user_id
-        ],
-    ) {
-        Ok(updated) => {
-                    println!("Updated {} rows in users table", updated);
-                    if updated == 0 {
-                        return HttpResponse::NotFound().json(ErrorResponse {
-                            message: "User not found".to_string(),
-                        });
-                    }
-                }
-                Err(e) => {
-                    println!("Failed to update user: {}", e);
-                    return HttpResponse::InternalServerError().json(ErrorResponse {
-                        message: format!("Failed to update user: {}", e),
-                    });
-                }
-        _ => {}
-    }
+    web::block(move || two_factor::set_required(store.get_ref(), user_id, required)).await??;
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "two_factor_required": required
+    })))
+}
 
-    // Step 3: Handle role-specific changes if role has changed
-    if current_user_type != req.user_type {
-        println!(
-            "Role changed from {} to {}",
-            current_user_type, req.user_type
-        );
+/// Removes a user's authenticator and recovery codes, e.g. after a lost
+/// phone, and signs them out everywhere.
+pub async fn reset_two_factor(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = path.into_inner();
+    info!(
+        admin_id = admin.user_id,
+        user_id, "Resetting two-factor authentication"
+    );
 
-        // Remove from previous role-specific table
-        match current_user_type.as_str() {
-            "teacher" => {
-                if let Err(e) = tx.execute(
-                    "DELETE FROM teachers WHERE user_id = ?1",
-                    params![req.user_id],
-                ) {
-                    return HttpResponse::InternalServerError().json(ErrorResponse {
-                        message: format!("Failed to remove user from teachers table: {}", e),
-                    });
-                }
-            }
-            "student" => {
-                if let Err(e) = tx.execute(
-                    "DELETE FROM students WHERE user_id = ?1",
-                    params![req.user_id],
-                ) {
-                    return HttpResponse::InternalServerError().json(ErrorResponse {
-                        message: format!("Failed to remove user from students table: {}", e),
-                    });
-                }
-            }
-            "parent" => {
-                // Get parent_id first
-                let parent_id: Result<i32, rusqlite::Error> = tx.query_row(
-                    "SELECT parent_id FROM parents WHERE user_id = ?1",
-                    params![req.user_id],
-                    |row| row.get(0),
-                );
+    web::block(move || {
+        two_factor::disable(store.get_ref(), user_id)?;
+        session::revoke_all_sessions(store.get_ref(), user_id)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Two-factor authentication has been reset"
+    })))
+}
 
-                if let Ok(parent_id) = parent_id {
-                    // Delete parent-student relationships
-                    if let Err(e) = tx.execute(
-                        "DELETE FROM parent_student WHERE parent_id = ?1",
-                        params![parent_id],
-                    ) {
-                        return HttpResponse::InternalServerError().json(ErrorResponse {
-                            message: format!(
-                                "Failed to remove parent-student relationships: {}",
-                                e
-                            ),
-                        });
-                    }
-                }
+/// Creates a registration invite code, optionally restricted to one email
+/// address and pre-linked to students. The code is returned only once.
+pub async fn create_invite(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    req: web::Json<NewInvite>,
+) -> Result<HttpResponse, AppError> {
+    let students = req.student_ids.len();
+    let invite =
+        web::block(move || registration::create_invite(store.get_ref(), admin.user_id, &req))
+            .await??;
 
-                // Delete from parents table
-                if let Err(e) = tx.execute(
-                    "DELETE FROM parents WHERE user_id = ?1",
-                    params![req.user_id],
-                ) {
-                    return HttpResponse::InternalServerError().json(ErrorResponse {
-                        message: format!("Failed to remove user from parents table: {}", e),
-                    });
-                }
-            }
-            _ => {}
-        }
+    info!(
+        admin_id = admin.user_id,
+        invite_id = invite.invite_id,
+        students,
+        "Created registration invite"
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "invite_id": invite.invite_id,
+        "invite_code": invite.code,
+        "expires_at": invite.expires_at
+    })))
+}
 
-        // Add to new role-specific table
-        match req.user_type.as_str() {
-            "teacher" => {
-                if let Err(e) = tx.execute(
-                    "INSERT INTO teachers (user_id, subject, department) VALUES (?1, ?2, ?3)",
-                    params![req.user_id, "Not Set", "Not Set"],
-                ) {
-                    return HttpResponse::InternalServerError().json(ErrorResponse {
-                        message: format!("Failed to add user to teachers table: {}", e),
-                    });
-                }
-            }
-            "student" => {
-                let grade_level = req.grade_level.unwrap_or(0);
-                let class_section = req.class_section.as_deref().unwrap_or("Not Set");
+pub async fn get_invites(store: web::Data<dyn Store>) -> Result<HttpResponse, AppError> {
+    let invites = web::block(move || registration::list_invites(store.get_ref())).await??;
+    Ok(HttpResponse::Ok().json(invites))
+}
 
-                if let Err(e) = tx.execute(
-                    "INSERT INTO students (user_id, grade_level, class_section) VALUES (?1, ?2, ?3)",
-                    params![req.user_id, grade_level, class_section],
-                ) {
-                    return HttpResponse::InternalServerError().json(ErrorResponse {
-                        message: format!("Failed to add user to students table: {}", e),
-                    });
-                }
-            }
-            "parent" => {
-                if let Err(e) = tx.execute(
-                    "INSERT INTO parents (user_id) VALUES (?1)",
-                    params![req.user_id],
-                ) {
-                    return HttpResponse::InternalServerError().json(ErrorResponse {
-                        message: format!("Failed to add user to parents table: {}", e),
-                    });
-                }
-            }
-            _ => {}
-        }
+pub async fn revoke_invite(
+    store: web::Data<dyn Store>,
+    admin: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let invite_id = path.into_inner();
+    let revoked =
+        web::block(move || registration::revoke_invite(store.get_ref(), invite_id)).await??;
+    if !revoked {
+        return Err(AppError::NotFound("Invite not found".to_string()));
     }
-    // Handle student info update if still a student
-    else if req.user_type == "student" {
-        let grade_level = req.grade_level.unwrap_or(0);
-        let class_section = req.class_section.as_deref().unwrap_or("Not Set");
 
-        if let Err(e) = tx.execute(
-            "UPDATE students SET grade_level = ?1, class_section = ?2 WHERE user_id = ?3",
-            params![grade_level, class_section, req.user_id],
-        ) {
-            return HttpResponse::InternalServerError().json(ErrorResponse {
-                message: format!("Failed to update student information: {}", e),
-            });
-        }
-    }
+    info!(
+        admin_id = admin.user_id,
+        invite_id, "Revoked registration invite"
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Invite revoked"
+    })))
+}
 
-    // Commit the transaction
-    if let Err(e) = tx.commit() {
-        return HttpResponse::InternalServerError().json(ErrorResponse {
-            message: format!("Failed to commit transaction: {}", e),
-        });
-    }
+/// Takes a snapshot of the live database into the backup directory. The
+/// copy and its integrity check run on the blocking thread pool. Only the
+/// sqlite backend registers a pool to back up.
+pub async fn create_backup(
+    pool: Option<web::Data<DbPool>>,
+    admin: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let pool = pool.ok_or_else(|| {
+        AppError::BadRequest("Backups are only available with the sqlite backend".to_string())
+    })?;
+    let path = web::block(move || {
+        let conn = pool.get()?;
+        backup::snapshot_with_retention(&conn, &config::get().backup)
+            .map_err(|e| AppError::Internal(format!("Backup failed: {}", e)))
+    })
+    .await??;
 
-    HttpResponse::Ok().json(json!({
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    info!(admin_id = admin.user_id, snapshot = %name, "Wrote backup");
+    Ok(HttpResponse::Ok().json(json!({
         "status": "success",
-        "message": "User updated successfully"
-    }))
+        "name": name
+    })))
+}
+
+pub async fn get_backups() -> Result<HttpResponse, AppError> {
+    let snapshots = web::block(|| backup::list(Path::new(&config::get().backup.dir)))
+        .await?
+        .map_err(AppError::Internal)?;
+    Ok(HttpResponse::Ok().json(snapshots))
 }
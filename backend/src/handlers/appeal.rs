@@ -0,0 +1,79 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use tracing::info;
+
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::{AppealDecisionRequest, FileAppealRequest};
+use crate::services::appeals::AppealService;
+
+pub async fn file_appeal(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    req: web::Json<FileAppealRequest>,
+) -> Result<HttpResponse, AppError> {
+    let demerit_id = path.into_inner();
+    let appeal_id = web::block(move || {
+        AppealService::new(store.get_ref()).file(user.user_id, user.role, demerit_id, &req.reason)
+    })
+    .await??;
+    info!(
+        appeal_id,
+        demerit_id,
+        user_id = user.user_id,
+        "Appeal filed"
+    );
+    Ok(HttpResponse::Created().json(json!({
+        "status": "success",
+        "message": "Appeal filed",
+        "appeal_id": appeal_id
+    })))
+}
+
+pub async fn get_appeals(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let appeals =
+        web::block(move || AppealService::new(store.get_ref()).list(user.user_id, user.role))
+            .await??;
+    Ok(HttpResponse::Ok().json(appeals))
+}
+
+pub async fn review_appeal(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let appeal_id = path.into_inner();
+    web::block(move || {
+        AppealService::new(store.get_ref()).start_review(user.user_id, user.role, appeal_id)
+    })
+    .await??;
+    info!(appeal_id, user_id = user.user_id, "Appeal under review");
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Appeal under review"
+    })))
+}
+
+pub async fn decide_appeal(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    req: web::Json<AppealDecisionRequest>,
+) -> Result<HttpResponse, AppError> {
+    let appeal_id = path.into_inner();
+    let outcome = req.outcome.as_str();
+    web::block(move || {
+        AppealService::new(store.get_ref()).decide(user.user_id, user.role, appeal_id, &req)
+    })
+    .await??;
+    info!(appeal_id, user_id = user.user_id, outcome, "Appeal decided");
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": format!("Appeal {}", outcome)
+    })))
+}
@@ -1,53 +1,299 @@
-use actix_web::{cookie::time::Duration, cookie::Cookie, post, web, HttpResponse, Responder};
+use actix_web::{
+    cookie::time::Duration, cookie::Cookie, web, HttpRequest, HttpResponse, HttpResponseBuilder,
+    Responder,
+};
+use serde::Deserialize;
 use serde_json::json;
+use tracing::{error, info, warn};
 
-use crate::models::{AuthResponse, ErrorResponse, LoginRequest, RegisterRequest};
-use crate::services::auth;
+use crate::config;
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::logging::redacted_debug;
+use crate::middleware::auth::{AuthenticatedUser, AUTH_COOKIE};
+use crate::models::{
+    AuthResponse, ChangePasswordRequest, LoginRequest, RegisterRequest, ResendVerificationRequest,
+    ResetPasswordRequest, TwoFactorLoginRequest, VerifyEmailRequest,
+};
+use crate::services::auth::LoginOutcome;
+use crate::services::lockout::Throttle;
+use crate::services::registration::RegistrationMode;
+use crate::services::{auth, lockout, password, session, verification};
 
-#[post("/login")]
-pub async fn login(req: web::Json<LoginRequest>) -> impl Responder {
-    println!("Login attempt for email: {}", req.email);
+/// Name of the cookie holding the refresh token. It is only sent to
+/// [`REFRESH_PATH`].
+const REFRESH_COOKIE: &str = "refresh_token";
 
-    match auth::auth_request(req.into_inner()) {
-        Ok(response) => {
-            // Create a cookie with the user email and proper configuration
-            // Using the correct way to set max age with seconds
-            let email_cookie = Cookie::build("user_email", response.user.email.clone())
-                .path("/")
-                .http_only(true)
-                .same_site(actix_web::cookie::SameSite::Lax) // Allow cookies in same-site requests
-                .max_age(Duration::hours(24)) // 24 hours in seconds: 24*60*60
-                .finish();
-
-            // Also create a cookie for user type for permission checks
-            let user_type_cookie = Cookie::build("user_type", response.user.permissions.clone())
-                .path("/")
-                .http_only(true)
-                .same_site(actix_web::cookie::SameSite::Lax)
-                .max_age(Duration::hours(24)) // 24 hours in seconds
-                .finish();
-
-            // Print that we're setting these cookies
-            println!(
-                "Setting cookies for user: {} type: {}",
-                response.user.email, response.user.permissions
+/// Full path of the `refresh` route, see `handlers::routes`.
+const REFRESH_PATH: &str = "/api/v1/auth/refresh";
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: Option<String>,
+}
+
+redacted_debug!(RefreshRequest {} secret { refresh_token });
+
+fn auth_cookie(token: &str) -> Cookie<'static> {
+    Cookie::build(AUTH_COOKIE, token.to_string())
+        .path("/")
+        .http_only(true)
+        .same_site(actix_web::cookie::SameSite::Lax)
+        .max_age(Duration::hours(config::get().auth.jwt_expiry_hours))
+        .finish()
+}
+
+fn refresh_cookie(token: &str) -> Cookie<'static> {
+    Cookie::build(REFRESH_COOKIE, token.to_string())
+        .path(REFRESH_PATH)
+        .http_only(true)
+        .same_site(actix_web::cookie::SameSite::Strict)
+        .max_age(Duration::days(config::get().auth.session_days))
+        .finish()
+}
+
+/// Starts a successful response that sets both tokens as cookies.
+pub(crate) fn with_auth_cookies(response: &AuthResponse) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::Ok();
+    builder
+        .cookie(auth_cookie(&response.token))
+        .cookie(refresh_cookie(&response.refresh_token));
+    builder
+}
+
+/// Returns the auth response with both tokens also set as cookies.
+pub(crate) fn signed_in(response: AuthResponse) -> HttpResponse {
+    with_auth_cookies(&response).json(response)
+}
+
+/// Clears both auth cookies on the client.
+fn signed_out(body: serde_json::Value) -> HttpResponse {
+    let mut response = HttpResponse::Ok().json(body);
+    let _ = response.add_removal_cookie(&auth_cookie(""));
+    let _ = response.add_removal_cookie(&refresh_cookie(""));
+    response
+}
+
+/// Address of the client, used to count failed logins per origin.
+fn client_ip(req: &HttpRequest) -> String {
+    req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+pub async fn login(
+    store: web::Data<dyn Store>,
+    http_req: HttpRequest,
+    req: web::Json<LoginRequest>,
+) -> Result<HttpResponse, AppError> {
+    let ip = client_ip(&http_req);
+
+    // The connection is released before stalling so slow attempts cannot
+    // starve the pool.
+    let req = req.into_inner();
+    let throttle = {
+        let (store, email, ip) = (store.clone(), req.email.clone(), ip.clone());
+        web::block(move || lockout::check(store.get_ref(), &email, &ip)).await??
+    };
+    match throttle {
+        Throttle::Allowed { delay } => {
+            if !delay.is_zero() {
+                actix_web::rt::time::sleep(delay).await;
+            }
+        }
+        Throttle::LockedOut { retry_after_secs } => {
+            warn!(client_ip = %ip, "Login refused while locked out");
+            return Err(AppError::TooManyAttempts {
+                retry_after_secs: Some(retry_after_secs),
+            });
+        }
+    }
+
+    // Password hashing is slow, so it stays off the async workers as well
+    let outcome = {
+        let ip = ip.clone();
+        web::block(move || auth::auth_request(store.get_ref(), req, &ip)).await?
+    };
+    match outcome {
+        Ok(LoginOutcome::SignedIn(response)) => {
+            info!(
+                user_id = %response.user.id,
+                role = %response.user.permissions,
+                "User signed in"
             );
+            Ok(signed_in(response))
+        }
+        Ok(LoginOutcome::TwoFactorRequired(challenge)) => Ok(HttpResponse::Ok().json(challenge)),
+        Err(AppError::InvalidCredentials) => {
+            warn!(client_ip = %ip, "Failed login attempt");
+            Err(AppError::InvalidCredentials)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Second login step for accounts with two-factor authentication: exchanges
+/// the challenge from `/login` and a TOTP or recovery code for a session.
+pub async fn login_two_factor(
+    store: web::Data<dyn Store>,
+    http_req: HttpRequest,
+    req: web::Json<TwoFactorLoginRequest>,
+) -> Result<HttpResponse, AppError> {
+    let ip = client_ip(&http_req);
+    let outcome = {
+        let ip = ip.clone();
+        web::block(move || auth::complete_two_factor(store.get_ref(), req.into_inner(), &ip))
+            .await?
+    };
 
-            // Return the response with cookies
-            HttpResponse::Ok()
-                .cookie(email_cookie)
-                .cookie(user_type_cookie)
-                .json(response)
+    match outcome {
+        Ok(response) => {
+            info!(
+                user_id = %response.user.id,
+                role = %response.user.permissions,
+                "User signed in with two-factor authentication"
+            );
+            Ok(signed_in(response))
+        }
+        Err(e @ AppError::TooManyAttempts { .. }) => {
+            warn!(client_ip = %ip, "Two-factor login refused while locked out");
+            Err(e)
+        }
+        Err(e @ (AppError::InvalidCode | AppError::InvalidChallenge)) => {
+            warn!(client_ip = %ip, "Failed two-factor login attempt");
+            Err(e)
         }
-        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { message: e }),
+        Err(e) => Err(e),
     }
 }
 
-#[post("/register")]
-pub async fn register(req: web::Json<RegisterRequest>) -> impl Responder {
-    println!("Login attempt for email: {}", req.email);
-    match auth::register(req.into_inner()) {
-        Ok(response) => HttpResponse::Ok().json(response),
-        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { message: e }),
+pub async fn register(
+    store: web::Data<dyn Store>,
+    req: web::Json<RegisterRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = web::block(move || auth::register(store.get_ref(), req.into_inner())).await??;
+
+    info!(
+        user_id,
+        "Parent account registered, awaiting email verification"
+    );
+    Ok(HttpResponse::Created().json(json!({
+        "status": "pending",
+        "message": "Check your email for a link to activate your account"
+    })))
+}
+
+/// Tells the registration form whether an invite code is needed.
+pub async fn registration_policy() -> impl Responder {
+    let policy = &config::get().registration;
+    HttpResponse::Ok().json(json!({
+        "mode": policy.mode,
+        "invite_required": policy.mode == RegistrationMode::Invite
+    }))
+}
+
+/// Swaps a refresh token (from the body or cookie) for a new token pair.
+pub async fn refresh(
+    store: web::Data<dyn Store>,
+    http_req: HttpRequest,
+    req: Option<web::Json<RefreshRequest>>,
+) -> Result<HttpResponse, AppError> {
+    let token = req
+        .and_then(|body| body.into_inner().refresh_token)
+        .or_else(|| {
+            http_req
+                .cookie(REFRESH_COOKIE)
+                .map(|c| c.value().to_string())
+        })
+        .ok_or_else(|| AppError::Unauthenticated("Refresh token required".to_string()))?;
+
+    let response = web::block(move || auth::refresh(store.get_ref(), &token)).await??;
+    Ok(signed_in(response))
+}
+
+/// Ends the session the request was made with.
+pub async fn logout(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    web::block(move || session::revoke_session(store.get_ref(), &user.session_id)).await??;
+
+    Ok(signed_out(json!({
+        "status": "success",
+        "message": "Logged out"
+    })))
+}
+
+/// Ends every session of the signed-in user, on all devices.
+pub async fn logout_all(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let revoked =
+        web::block(move || session::revoke_all_sessions(store.get_ref(), user.user_id)).await??;
+
+    Ok(signed_out(json!({
+        "status": "success",
+        "message": "Logged out on all devices",
+        "revoked_sessions": revoked
+    })))
+}
+
+/// Lets a signed-in user pick a new password. This is the only endpoint open
+/// to users who still have to replace a generated or seeded password.
+pub async fn change_password(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    req: web::Json<ChangePasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    let response =
+        web::block(move || auth::change_password(store.get_ref(), user.user_id, req.into_inner()))
+            .await??;
+    Ok(signed_in(response))
+}
+
+/// Sets a new password using a reset token issued by an admin.
+pub async fn reset_password(
+    store: web::Data<dyn Store>,
+    req: web::Json<ResetPasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    web::block(move || password::reset_password(store.get_ref(), &req.token, &req.new_password))
+        .await??;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Password has been reset, please log in"
+    })))
+}
+
+/// Activates a pending account with the token from its verification email.
+pub async fn verify_email(
+    store: web::Data<dyn Store>,
+    req: web::Json<VerifyEmailRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id =
+        web::block(move || verification::verify_email(store.get_ref(), &req.token)).await??;
+
+    info!(user_id, "Email address verified");
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Email address verified, please log in"
+    })))
+}
+
+/// Emails a new verification link. Answers the same whether or not the
+/// address has a pending account.
+pub async fn resend_verification(
+    store: web::Data<dyn Store>,
+    req: web::Json<ResendVerificationRequest>,
+) -> Result<HttpResponse, AppError> {
+    let resent = web::block(move || verification::resend(store.get_ref(), &req.email)).await?;
+    if let Err(e) = resent {
+        error!(error = %e, "Failed to resend verification email");
     }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "If the account is awaiting verification, a new link has been sent"
+    })))
 }
@@ -0,0 +1,75 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use tracing::info;
+
+use crate::config;
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::{EscalationRuleRequest, ResolveEscalationRequest};
+use crate::services::escalations::EscalationService;
+
+fn service(store: &dyn Store) -> EscalationService<'_> {
+    EscalationService::new(store, &config::get().school)
+}
+
+pub async fn get_escalation_rules(store: web::Data<dyn Store>) -> Result<HttpResponse, AppError> {
+    let rules = web::block(move || service(store.get_ref()).rules()).await??;
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+pub async fn create_escalation_rule(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    req: web::Json<EscalationRuleRequest>,
+) -> Result<HttpResponse, AppError> {
+    let rule_id = web::block(move || service(store.get_ref()).create_rule(&req)).await??;
+    info!(rule_id, user_id = user.user_id, "Escalation rule created");
+    Ok(HttpResponse::Created().json(json!({
+        "status": "success",
+        "message": "Escalation rule created",
+        "rule_id": rule_id
+    })))
+}
+
+pub async fn update_escalation_rule(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    req: web::Json<EscalationRuleRequest>,
+) -> Result<HttpResponse, AppError> {
+    let rule_id = path.into_inner();
+    web::block(move || service(store.get_ref()).update_rule(rule_id, &req)).await??;
+    info!(rule_id, user_id = user.user_id, "Escalation rule updated");
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Escalation rule updated"
+    })))
+}
+
+pub async fn get_open_escalations(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let escalations =
+        web::block(move || service(store.get_ref()).open(user.user_id, user.role)).await??;
+    Ok(HttpResponse::Ok().json(escalations))
+}
+
+pub async fn resolve_escalation(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    req: web::Json<ResolveEscalationRequest>,
+) -> Result<HttpResponse, AppError> {
+    let escalation_id = path.into_inner();
+    web::block(move || {
+        service(store.get_ref()).resolve(user.user_id, user.role, escalation_id, &req.resolution)
+    })
+    .await??;
+    info!(escalation_id, user_id = user.user_id, "Escalation resolved");
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Escalation resolved"
+    })))
+}
@@ -0,0 +1,56 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use tracing::info;
+
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::NewMeritRecord;
+use crate::services::merits::MeritService;
+
+pub async fn get_merit_categories(store: web::Data<dyn Store>) -> Result<HttpResponse, AppError> {
+    let categories = web::block(move || MeritService::new(store.get_ref()).categories()).await??;
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+pub async fn award_merit(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    req: web::Json<NewMeritRecord>,
+) -> Result<HttpResponse, AppError> {
+    let (student_id, points) = (req.student_id, req.points);
+    let merit_id =
+        web::block(move || MeritService::new(store.get_ref()).award(user.user_id, &req)).await??;
+    info!(merit_id, student_id, points, "Merit awarded");
+    Ok(HttpResponse::Created().json(json!({
+        "status": "success",
+        "message": "Merit awarded",
+        "merit_id": merit_id
+    })))
+}
+
+pub async fn get_teacher_merits(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let merits =
+        web::block(move || MeritService::new(store.get_ref()).awarded_by(user.user_id)).await??;
+    Ok(HttpResponse::Ok().json(merits))
+}
+
+pub async fn get_my_merits(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let merits = web::block(move || MeritService::new(store.get_ref()).own(user.user_id)).await??;
+    Ok(HttpResponse::Ok().json(merits))
+}
+
+pub async fn get_children_merits(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let merits =
+        web::block(move || MeritService::new(store.get_ref()).of_children(user.user_id)).await??;
+    Ok(HttpResponse::Ok().json(merits))
+}
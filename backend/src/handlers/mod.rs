@@ -1,9 +1,14 @@
 pub mod admin;
+pub mod appeal;
 pub mod auth;
 pub mod demerit;
+pub mod escalation;
+pub mod merit;
 pub mod parent;
+pub mod routes;
 pub mod student;
 pub mod teacher;
 pub mod time;
+pub mod two_factor;
 pub mod upload;
 pub mod util;
@@ -0,0 +1,240 @@
+//! The route table of the HTTP API.
+//!
+//! Everything lives under [`API_PREFIX`], with one scope per audience. The
+//! role scopes are guarded by [`RequireRole`] as a whole, so a handler placed
+//! in `/admin` can never be reached by a teacher. Routes outside the role
+//! scopes check the caller themselves.
+
+use actix_web::web;
+
+use super::{
+    admin, appeal, auth, demerit, escalation, merit, parent, student, teacher, time, two_factor,
+    upload,
+};
+use crate::middleware::auth::RequireRole;
+
+/// Prefix of the current API version.
+pub const API_PREFIX: &str = "/api/v1";
+
+/// Registers every route of the API.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope(API_PREFIX)
+            .route("/time", web::get().to(time::get_time))
+            .service(web::scope("/auth").configure(auth_routes))
+            .service(
+                web::scope("/admin")
+                    .wrap(RequireRole::admin())
+                    .configure(admin_routes),
+            )
+            .service(
+                web::scope("/staff")
+                    .wrap(RequireRole::staff())
+                    .configure(staff_routes),
+            )
+            .service(
+                web::scope("/teacher")
+                    .wrap(RequireRole::teacher())
+                    .configure(teacher_routes),
+            )
+            .service(
+                web::scope("/student")
+                    .wrap(RequireRole::student())
+                    .configure(student_routes),
+            )
+            .service(
+                web::scope("/parent")
+                    .wrap(RequireRole::parent())
+                    .configure(parent_routes),
+            ),
+    );
+}
+
+/// Signing in and out, registration and account security. Open to anyone;
+/// handlers that need a session take an `AuthenticatedUser`.
+fn auth_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/login", web::post().to(auth::login))
+        .route("/login/two_factor", web::post().to(auth::login_two_factor))
+        .route("/register", web::post().to(auth::register))
+        .route(
+            "/registration_policy",
+            web::get().to(auth::registration_policy),
+        )
+        .route("/verify_email", web::post().to(auth::verify_email))
+        .route(
+            "/resend_verification",
+            web::post().to(auth::resend_verification),
+        )
+        .route("/refresh", web::post().to(auth::refresh))
+        .route("/reset_password", web::post().to(auth::reset_password))
+        .route("/logout", web::post().to(auth::logout))
+        .route("/logout_all", web::post().to(auth::logout_all))
+        .route("/change_password", web::post().to(auth::change_password))
+        .route("/two_factor/setup", web::post().to(two_factor::setup))
+        .route("/two_factor/enable", web::post().to(two_factor::enable))
+        .route(
+            "/two_factor/recovery_codes",
+            web::post().to(two_factor::regenerate_recovery_codes),
+        )
+        .route("/two_factor/disable", web::post().to(two_factor::disable));
+}
+
+fn admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/users")
+            .route(web::get().to(admin::get_admin_data))
+            .route(web::put().to(admin::update_user)),
+    )
+    .route("/users/role", web::put().to(admin::update_user_role))
+    .route(
+        "/users/{user_id}/revoke_sessions",
+        web::post().to(admin::revoke_user_sessions),
+    )
+    .route(
+        "/users/{user_id}/password_reset",
+        web::post().to(admin::issue_password_reset),
+    )
+    .route(
+        "/users/{user_id}/require_two_factor",
+        web::post().to(admin::require_two_factor),
+    )
+    .route(
+        "/users/{user_id}/reset_two_factor",
+        web::post().to(admin::reset_two_factor),
+    )
+    .route("/lockouts", web::get().to(admin::get_lockouts))
+    .route("/lockouts/clear", web::post().to(admin::clear_lockout))
+    .service(
+        web::resource("/invites")
+            .route(web::get().to(admin::get_invites))
+            .route(web::post().to(admin::create_invite)),
+    )
+    .route(
+        "/invites/{invite_id}/revoke",
+        web::post().to(admin::revoke_invite),
+    )
+    .service(
+        web::resource("/backups")
+            .route(web::get().to(admin::get_backups))
+            .route(web::post().to(admin::create_backup)),
+    )
+    .route("/parents", web::get().to(parent::get_parents))
+    .service(
+        web::resource("/parents/students")
+            .route(web::post().to(parent::add_parent_student))
+            .route(web::put().to(parent::update_parent_students)),
+    )
+    .route("/students/import", web::post().to(upload::upload_csv))
+    .service(
+        web::resource("/escalation_rules")
+            .route(web::get().to(escalation::get_escalation_rules))
+            .route(web::post().to(escalation::create_escalation_rule)),
+    )
+    .route(
+        "/escalation_rules/{rule_id}",
+        web::put().to(escalation::update_escalation_rule),
+    );
+}
+
+/// Shared by admins and teachers; teachers may only change the demerits they
+/// issued, decide appeals against them and see escalations assigned to them.
+fn staff_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/students", web::get().to(student::get_students))
+        .route(
+            "/students/{student_id}/demerits",
+            web::get().to(student::get_student_demerits),
+        )
+        .route(
+            "/demerit_categories",
+            web::get().to(demerit::get_demerit_categories),
+        )
+        .route(
+            "/merit_categories",
+            web::get().to(merit::get_merit_categories),
+        )
+        .route(
+            "/demerits/{demerit_id}",
+            web::patch().to(demerit::edit_demerit),
+        )
+        .route(
+            "/demerits/{demerit_id}/void",
+            web::post().to(demerit::void_demerit),
+        )
+        .route(
+            "/demerits/{demerit_id}/revisions",
+            web::get().to(demerit::get_demerit_revisions),
+        )
+        .route("/appeals", web::get().to(appeal::get_appeals))
+        .route(
+            "/appeals/{appeal_id}/review",
+            web::post().to(appeal::review_appeal),
+        )
+        .route(
+            "/appeals/{appeal_id}/decide",
+            web::post().to(appeal::decide_appeal),
+        )
+        .route(
+            "/escalations",
+            web::get().to(escalation::get_open_escalations),
+        )
+        .route(
+            "/escalations/{escalation_id}/resolve",
+            web::post().to(escalation::resolve_escalation),
+        )
+        .route(
+            "/reports/history",
+            web::get().to(demerit::get_demerit_history),
+        )
+        .route(
+            "/reports/distribution",
+            web::get().to(demerit::get_demerit_distribution),
+        )
+        .route("/reports/trend", web::get().to(demerit::get_demerit_trend))
+        .route(
+            "/reports/students",
+            web::get().to(teacher::get_student_demerit_summary),
+        );
+}
+
+fn teacher_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/demerits")
+            .route(web::get().to(teacher::get_teacher_data))
+            .route(web::post().to(teacher::add_demerit)),
+    )
+    .service(
+        web::resource("/merits")
+            .route(web::get().to(merit::get_teacher_merits))
+            .route(web::post().to(merit::award_merit)),
+    );
+}
+
+fn student_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/demerits", web::get().to(student::get_my_demerits))
+        .route(
+            "/demerits/{demerit_id}/appeal",
+            web::post().to(appeal::file_appeal),
+        )
+        .route("/appeals", web::get().to(appeal::get_appeals))
+        .route("/merits", web::get().to(merit::get_my_merits))
+        .route("/summary", web::get().to(student::get_my_summary))
+        .route("/profile", web::get().to(student::get_my_student_info));
+}
+
+fn parent_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/demerits", web::get().to(parent::get_children_demerits))
+        .route(
+            "/demerits/{demerit_id}/appeal",
+            web::post().to(appeal::file_appeal),
+        )
+        .route("/appeals", web::get().to(appeal::get_appeals))
+        .route("/merits", web::get().to(merit::get_children_merits))
+        .route(
+            "/children",
+            web::get().to(parent::get_parent_children_summary),
+        )
+        .route(
+            "/children/{student_id}/demerits",
+            web::get().to(student::get_student_demerits),
+        );
+}
@@ -1,4 +1,4 @@
-use actix_web::{get, web, Responder};
+use actix_web::{web, Responder};
 use chrono::Local;
 use serde::Serialize;
 
@@ -7,7 +7,6 @@ pub struct TimeResponse {
     current_time: String,
 }
 
-#[get("/time")]
 pub async fn get_time() -> impl Responder {
     let current_time = Local::now().to_string();
     web::Json(TimeResponse { current_time })
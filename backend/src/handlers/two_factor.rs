@@ -0,0 +1,107 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::handlers::auth::with_auth_cookies;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::{Role, TwoFactorCodeRequest};
+use crate::services::{auth, two_factor};
+
+/// Enrollment is open to admins and teachers only. These endpoints skip
+/// `RequireRole` because users who are required to enroll must reach them.
+fn require_supported_role(user: &AuthenticatedUser) -> Result<(), AppError> {
+    if matches!(user.role, Role::Admin | Role::Teacher) {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden(
+            "Two-factor authentication is only available to admin and teacher accounts".to_string(),
+        ))
+    }
+}
+
+/// Checks a current TOTP or recovery code sent in the `code` field.
+fn require_code(store: &dyn Store, user_id: i32, code: &str) -> Result<(), AppError> {
+    if two_factor::verify_code(store, user_id, code)? {
+        Ok(())
+    } else {
+        Err(AppError::invalid("code", AppError::InvalidCode.to_string()))
+    }
+}
+
+/// Generates a new TOTP secret and returns it with an `otpauth://` URI to
+/// render as a QR code. Nothing changes for logins until `/two_factor/enable`.
+pub async fn setup(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    require_supported_role(&user)?;
+
+    let enrollment =
+        web::block(move || two_factor::begin_enrollment(store.get_ref(), user.user_id)).await??;
+    Ok(HttpResponse::Ok().json(json!({
+        "secret": enrollment.secret,
+        "otpauth_uri": enrollment.otpauth_uri
+    })))
+}
+
+/// Confirms enrollment with a code from the authenticator app. Returns the
+/// recovery codes, which are shown only this once, and a fresh session.
+pub async fn enable(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    req: web::Json<TwoFactorCodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    require_supported_role(&user)?;
+
+    let (recovery_codes, response) = web::block(move || {
+        auth::enable_two_factor(store.get_ref(), user.user_id, &user.session_id, &req.code)
+    })
+    .await??;
+    Ok(with_auth_cookies(&response).json(json!({
+        "recovery_codes": recovery_codes,
+        "token": response.token,
+        "refresh_token": response.refresh_token,
+        "user": response.user
+    })))
+}
+
+/// Replaces the recovery codes. Requires a current code.
+pub async fn regenerate_recovery_codes(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    req: web::Json<TwoFactorCodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    let recovery_codes = web::block(move || {
+        require_code(store.get_ref(), user.user_id, &req.code)?;
+        two_factor::regenerate_recovery_codes(store.get_ref(), user.user_id)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(json!({
+        "recovery_codes": recovery_codes
+    })))
+}
+
+/// Turns two-factor authentication off. Requires a current code, and is
+/// refused while an admin has made it mandatory for the account.
+pub async fn disable(
+    store: web::Data<dyn Store>,
+    user: AuthenticatedUser,
+    req: web::Json<TwoFactorCodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    web::block(move || {
+        if two_factor::is_required(store.get_ref(), user.user_id)? {
+            return Err(AppError::Forbidden(
+                "Two-factor authentication is required for this account".to_string(),
+            ));
+        }
+
+        require_code(store.get_ref(), user.user_id, &req.code)?;
+        two_factor::disable(store.get_ref(), user.user_id)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Two-factor authentication disabled"
+    })))
+}
@@ -1 +1,9 @@
+use crate::config;
+use crate::database::repository::Store;
+use crate::services::reports::ReportService;
 
+/// Reports under the configured decay and merit policies.
+pub fn reports(store: &dyn Store) -> ReportService<'_> {
+    let config = config::get();
+    ReportService::new(store, &config.points, &config.school)
+}
@@ -0,0 +1,11 @@
+//! Demerit System backend: the HTTP server (`demerit-backend`) and the
+//! operator tool (`demerit-admin`) are both built on the modules below.
+
+pub mod config;
+pub mod database;
+pub mod error;
+pub mod handlers;
+pub mod logging;
+pub mod middleware;
+pub mod models;
+pub mod services;
@@ -0,0 +1,107 @@
+//! Structured logging for the backend.
+//!
+//! Events go through `tracing`; every HTTP request runs in a span carrying a
+//! request id, which is also returned to the client in `X-Request-Id`.
+//!
+//! Redaction policy: passwords, password hashes, access/refresh/reset tokens,
+//! TOTP secrets and codes are never logged, not even at `debug` level. Types
+//! that hold them implement `Debug` through [`redacted_debug!`], so logging the
+//! whole value with `?value` is safe. Log users by id rather than by email.
+
+use std::fmt;
+
+use tracing_subscriber::EnvFilter;
+
+/// Response header echoing the id of the request span.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Installs the global subscriber with a filter such as `info` or
+/// `demerit_backend=debug,actix_web=info`. Call once, before anything logs.
+pub fn init(filter: &str) {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(filter))
+        .init();
+}
+
+/// Like [`init`], but writes to stderr so command output on stdout stays
+/// clean. Used by `demerit-admin`.
+pub fn init_cli(filter: &str) {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(filter))
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+/// Formats as `[REDACTED]` whatever it wraps.
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+/// Implements `Debug` for a struct, printing the fields listed under `secret`
+/// as `[REDACTED]`.
+///
+/// ```ignore
+/// redacted_debug!(LoginRequest { email, username } secret { password });
+/// ```
+macro_rules! redacted_debug {
+    ($ty:ident { $($field:ident),* $(,)? } secret { $($secret:ident),* $(,)? }) => {
+        impl std::fmt::Debug for $ty {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!($ty))
+                    $(.field(stringify!($field), &self.$field))*
+                    $(.field(stringify!($secret), &$crate::logging::Redacted(&self.$secret)))*
+                    .finish()
+            }
+        }
+    };
+}
+
+pub(crate) use redacted_debug;
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{AuthResponse, LoginRequest, UserResponse};
+
+    #[test]
+    fn debug_output_hides_credentials() {
+        let login = LoginRequest {
+            email: "teacher@edu.my".to_string(),
+            password: "hunter22".to_string(),
+            username: None,
+            first_name: None,
+            last_name: None,
+        };
+        let output = format!("{:?}", login);
+        assert!(output.contains("teacher@edu.my"));
+        assert!(!output.contains("hunter22"));
+
+        let response = AuthResponse {
+            token: "access-token".to_string(),
+            refresh_token: "refresh-token".to_string(),
+            user: UserResponse {
+                id: "1".to_string(),
+                email: "teacher@edu.my".to_string(),
+                username: None,
+                first_name: None,
+                last_name: None,
+                permissions: "teacher".to_string(),
+                must_change_password: false,
+                two_factor_enabled: false,
+                must_enroll_two_factor: false,
+            },
+        };
+        let output = format!("{:?}", response);
+        assert!(!output.contains("access-token"));
+        assert!(!output.contains("refresh-token"));
+    }
+}
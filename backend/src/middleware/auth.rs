@@ -1 +1,182 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
 
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest, ResponseError};
+use futures::future::LocalBoxFuture;
+
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::models::Role;
+use crate::services::{auth, session};
+
+/// Name of the cookie holding the access token for browser clients.
+pub const AUTH_COOKIE: &str = "auth_token";
+
+/// The caller identified by a valid access token for an active session.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: i32,
+    pub role: Role,
+    pub session_id: String,
+    pub must_change_password: bool,
+    pub must_enroll_two_factor: bool,
+}
+
+/// Reads the access token from the `Authorization: Bearer` header, falling
+/// back to the auth cookie set at login.
+fn token_from_request(req: &HttpRequest) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    bearer.or_else(|| req.cookie(AUTH_COOKIE).map(|c| c.value().to_string()))
+}
+
+async fn authenticate(req: HttpRequest) -> Result<AuthenticatedUser, AppError> {
+    if let Some(user) = req.extensions().get::<AuthenticatedUser>() {
+        return Ok(user.clone());
+    }
+
+    let token = token_from_request(&req)
+        .ok_or_else(|| AppError::Unauthenticated("Authentication required".to_string()))?;
+    let claims = auth::verify_token(&token)?;
+
+    let store = req
+        .app_data::<web::Data<dyn Store>>()
+        .cloned()
+        .ok_or_else(|| AppError::Internal("Store is not configured".to_string()))?;
+    let session_id = claims.sid.clone();
+    let account =
+        web::block(move || session::active_session_user(store.get_ref(), &session_id)).await??;
+    // Role and requirements come from the database, not the token, so that
+    // changes by an admin apply to tokens that are already out there
+    let account = account
+        .filter(|account| account.user_id == claims.sub)
+        .ok_or_else(|| {
+            AppError::Unauthenticated("Session has ended, please log in again".to_string())
+        })?;
+    let role = account
+        .user_type
+        .parse::<Role>()
+        .map_err(AppError::Unauthenticated)?;
+
+    let user = AuthenticatedUser {
+        user_id: account.user_id,
+        role,
+        session_id: claims.sid,
+        must_change_password: account.must_change_password,
+        must_enroll_two_factor: account.must_enroll_two_factor,
+    };
+    req.extensions_mut().insert(user.clone());
+    Ok(user)
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        Box::pin(authenticate(req.clone()))
+    }
+}
+
+/// Middleware that rejects callers who are not signed in (401), whose role is
+/// not in the allowed set, or who still have to replace their password or set
+/// up two-factor authentication (403).
+pub struct RequireRole {
+    roles: &'static [Role],
+}
+
+impl RequireRole {
+    pub fn new(roles: &'static [Role]) -> Self {
+        RequireRole { roles }
+    }
+
+    pub fn admin() -> Self {
+        Self::new(&[Role::Admin])
+    }
+
+    pub fn staff() -> Self {
+        Self::new(&[Role::Admin, Role::Teacher])
+    }
+
+    pub fn teacher() -> Self {
+        Self::new(&[Role::Teacher])
+    }
+
+    pub fn student() -> Self {
+        Self::new(&[Role::Student])
+    }
+
+    pub fn parent() -> Self {
+        Self::new(&[Role::Parent])
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireRoleMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware {
+            service: Rc::new(service),
+            roles: self.roles,
+        }))
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<S>,
+    roles: &'static [Role],
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let roles = self.roles;
+        Box::pin(async move {
+            let checked = authenticate(req.request().clone()).await.and_then(|user| {
+                if user.must_change_password {
+                    Err(AppError::PasswordChangeRequired)
+                } else if user.must_enroll_two_factor {
+                    Err(AppError::TwoFactorEnrollmentRequired)
+                } else if roles.contains(&user.role) {
+                    Ok(user)
+                } else {
+                    Err(AppError::Forbidden(
+                        "You do not have permission to access this resource".to_string(),
+                    ))
+                }
+            });
+
+            if let Err(e) = checked {
+                let response = e.error_response().map_into_right_body();
+                return Ok(req.into_response(response));
+            }
+            service.call(req).await.map(|res| res.map_into_left_body())
+        })
+    }
+}
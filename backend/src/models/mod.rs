@@ -1,13 +1,133 @@
-// Either re-export existing model types or add missing ones
+use crate::logging::redacted_debug;
 use serde::{Deserialize, Serialize};
-use crate::StudentInfo;
+use std::str::FromStr;
 
+/// Body of every error response. See `crate::error::AppError`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
+    pub code: String,
     pub message: String,
+    /// The invalid fields of a request that failed validation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Teacher,
+    Student,
+    Parent,
+}
+
+impl Role {
+    /// The value stored in `users.user_type`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Teacher => "teacher",
+            Role::Student => "student",
+            Role::Parent => "parent",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "teacher" => Ok(Role::Teacher),
+            "student" => Ok(Role::Student),
+            "parent" => Ok(Role::Parent),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+}
+
+/// Where an appeal against a demerit stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppealStatus {
+    Open,
+    UnderReview,
+    /// The demerit stands.
+    Upheld,
+    /// The demerit was voided or its points reduced.
+    Overturned,
+}
+
+impl AppealStatus {
+    /// The value stored in `demerit_appeals.status`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AppealStatus::Open => "open",
+            AppealStatus::UnderReview => "under_review",
+            AppealStatus::Upheld => "upheld",
+            AppealStatus::Overturned => "overturned",
+        }
+    }
+}
+
+impl FromStr for AppealStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(AppealStatus::Open),
+            "under_review" => Ok(AppealStatus::UnderReview),
+            "upheld" => Ok(AppealStatus::Upheld),
+            "overturned" => Ok(AppealStatus::Overturned),
+            other => Err(format!("Unknown appeal status: {}", other)),
+        }
+    }
+}
+
+/// What a crossed escalation rule asks staff to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EscalationAction {
+    WarningLetter,
+    Detention,
+    ParentMeeting,
+    SuspensionReview,
+}
+
+impl EscalationAction {
+    /// The value stored in `escalation_rules.action`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EscalationAction::WarningLetter => "warning_letter",
+            EscalationAction::Detention => "detention",
+            EscalationAction::ParentMeeting => "parent_meeting",
+            EscalationAction::SuspensionReview => "suspension_review",
+        }
+    }
+}
+
+impl FromStr for EscalationAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warning_letter" => Ok(EscalationAction::WarningLetter),
+            "detention" => Ok(EscalationAction::Detention),
+            "parent_meeting" => Ok(EscalationAction::ParentMeeting),
+            "suspension_review" => Ok(EscalationAction::SuspensionReview),
+            other => Err(format!("Unknown escalation action: {}", other)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
@@ -16,19 +136,60 @@ pub struct LoginRequest {
     pub last_name: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+redacted_debug!(LoginRequest { email, username, first_name, last_name } secret { password });
+
+#[derive(Serialize, Deserialize)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
     pub user: UserResponse,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+redacted_debug!(AuthResponse { user } secret { token, refresh_token });
+
+#[derive(Serialize, Deserialize)]
 pub struct RegisterRequest {
     pub email: String,
     pub password: String,
     pub username: Option<String>,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
+    /// Required when registration is invite-only.
+    #[serde(default)]
+    pub invite_code: Option<String>,
+}
+
+redacted_debug!(RegisterRequest { email, username, first_name, last_name } secret {
+    password,
+    invite_code,
+});
+
+#[derive(Serialize, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+redacted_debug!(ChangePasswordRequest {} secret { current_password, new_password });
+
+#[derive(Serialize, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+redacted_debug!(ResetPasswordRequest {} secret { token, new_password });
+
+#[derive(Serialize, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+redacted_debug!(VerifyEmailRequest {} secret { token });
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,8 +200,37 @@ pub struct UserResponse {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub permissions: String,
+    pub must_change_password: bool,
+    pub two_factor_enabled: bool,
+    pub must_enroll_two_factor: bool,
 }
 
+/// Returned by `/login` instead of tokens when the account has two-factor
+/// authentication enabled. The challenge token is redeemed, together with a
+/// code, at `/login/two_factor`.
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+}
+
+redacted_debug!(TwoFactorChallenge { two_factor_required } secret { challenge_token });
+
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    pub challenge_token: String,
+    pub code: String,
+}
+
+redacted_debug!(TwoFactorLoginRequest {} secret { challenge_token, code });
+
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+redacted_debug!(TwoFactorCodeRequest {} secret { code });
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TeacherRecord {
     pub id: i32,
@@ -48,25 +238,49 @@ pub struct TeacherRecord {
     pub category: String,
     pub points: i32,
     pub date_issued: String,
+    pub voided_at: Option<String>,
+    pub void_reason: Option<String>,
+    pub appeal_status: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct StudentRecord {
+pub struct ParentRecord {
     pub id: i32,
+    pub student_name: String,
     pub category: String,
     pub points: i32,
     pub teacher_name: String,
     pub date_issued: String,
+    pub voided_at: Option<String>,
+    pub void_reason: Option<String>,
+    pub appeal_status: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ParentRecord {
+pub struct StudentInfo {
     pub id: i32,
-    pub student_name: String,
-    pub category: String,
-    pub points: i32,
-    pub teacher_name: String,
-    pub date_issued: String,
+    pub name: String,
+}
+
+/// An account in the admin user list. Students carry the same point totals
+/// as their own summaries; other accounts have zeros.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUserData {
+    pub user_id: i32,
+    pub username: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub user_type: String,
+    pub created_at: String,
+    pub grade_level: Option<i32>,
+    pub class_section: Option<String>,
+    pub total_demerits: i32,
+    pub effective_points: i32,
+    pub merit_points: i32,
+    pub net_points: i32,
+    /// Linked students, for parents.
+    pub children: Vec<StudentInfo>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -81,7 +295,7 @@ pub struct AdminUserRecord {
     pub created_at: String,
     pub grade_level: Option<i32>,
     pub class_section: Option<String>,
-    pub children: Option<Vec<StudentInfo>>
+    pub children: Option<Vec<StudentInfo>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -93,14 +307,67 @@ pub struct NewDemeritRecord {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct DemeritRecord {
-    pub id: i32,
-    pub category: String,
+pub struct NewMeritRecord {
+    pub student_id: i32,
+    pub category_id: i32,
     pub points: i32,
-    pub date_issued: String,
+    pub description: String,
+}
+
+/// Changes to a demerit; omitted fields keep their current value.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EditDemeritRecord {
+    pub student_id: Option<i32>,
+    pub category_id: Option<i32>,
+    pub points: Option<i32>,
+    pub description: Option<String>,
+    /// Why the demerit was changed, kept with the revision.
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VoidDemeritRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileAppealRequest {
+    pub reason: String,
 }
 
+/// An escalation rule as created or replaced by an admin. Without
+/// `window_days` the rule counts points in the current term.
 #[derive(Debug, Serialize, Deserialize)]
+pub struct EscalationRuleRequest {
+    pub name: String,
+    pub points: i32,
+    pub window_days: Option<i32>,
+    pub action: EscalationAction,
+    /// Staff member who handles the escalations the rule creates.
+    pub assigned_to: i32,
+    #[serde(default = "active_by_default")]
+    pub active: bool,
+}
+
+fn active_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResolveEscalationRequest {
+    pub resolution: String,
+}
+
+/// Closes an appeal under review. Overturning voids the demerit unless
+/// `points` gives a reduced value.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppealDecisionRequest {
+    pub outcome: AppealStatus,
+    pub comment: Option<String>,
+    pub points: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct User {
     pub id: i32,
     pub username: String,
@@ -109,5 +376,24 @@ pub struct User {
     pub user_type: String,
     pub first_name: String,
     pub last_name: String,
+    pub must_change_password: bool,
+    pub two_factor_required: bool,
+    pub two_factor_enabled: bool,
+    pub status: String,
 }
+
+redacted_debug!(User {
+    id,
+    username,
+    email,
+    user_type,
+    first_name,
+    last_name,
+    must_change_password,
+    two_factor_required,
+    two_factor_enabled,
+    status,
+} secret {
+    password_hash,
+});
 // Add any other models that exist in your application but aren't shown in the provided code
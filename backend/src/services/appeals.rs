@@ -0,0 +1,375 @@
+//! Appeals against demerits: filed by the student or a linked parent,
+//! decided by the issuing teacher or an admin.
+
+use crate::database::repository::{
+    Appeal, AppealDecision, AppealOutcome, AppealScope, NewAppeal, Store, StoredAppeal,
+};
+use crate::error::AppError;
+use crate::models::{AppealDecisionRequest, AppealStatus, Role};
+use crate::services::demerits::DemeritService;
+
+pub struct AppealService<'a> {
+    store: &'a dyn Store,
+}
+
+impl<'a> AppealService<'a> {
+    pub fn new(store: &'a dyn Store) -> Self {
+        AppealService { store }
+    }
+
+    /// Files an appeal against a demerit of the student signed in as
+    /// `user_id`, or of one of their children, and returns its id.
+    pub fn file(
+        &self,
+        user_id: i32,
+        role: Role,
+        demerit_id: i32,
+        reason: &str,
+    ) -> Result<i32, AppError> {
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(AppError::invalid("reason", "Give a reason for the appeal"));
+        }
+        let demerit = self
+            .store
+            .find_demerit(demerit_id)?
+            .ok_or_else(|| AppError::NotFound("Demerit not found".to_string()))?;
+
+        let allowed = match role {
+            Role::Student => self.store.find_student_id(user_id)? == Some(demerit.student_id),
+            Role::Parent => match self.store.find_parent_id(user_id)? {
+                Some(parent_id) => self.store.is_linked(parent_id, demerit.student_id)?,
+                None => false,
+            },
+            Role::Admin | Role::Teacher => false,
+        };
+        if !allowed {
+            return Err(AppError::Forbidden(
+                "Only the student or their parents can appeal this demerit".to_string(),
+            ));
+        }
+        if demerit.voided {
+            return Err(AppError::Conflict(
+                "A voided demerit cannot be appealed".to_string(),
+            ));
+        }
+        if self.store.demerit_appeal(demerit_id)?.is_some() {
+            return Err(AppError::Conflict(
+                "This demerit has already been appealed".to_string(),
+            ));
+        }
+
+        Ok(self.store.file_appeal(&NewAppeal {
+            demerit_id,
+            filed_by: user_id,
+            reason: reason.to_string(),
+        })?)
+    }
+
+    /// Appeals `user_id` may see: admins see all, teachers those against
+    /// demerits they issued, students their own and parents their children's.
+    pub fn list(&self, user_id: i32, role: Role) -> Result<Vec<Appeal>, AppError> {
+        let scope = match role {
+            Role::Admin => AppealScope::All,
+            Role::Teacher => {
+                AppealScope::IssuedBy(self.store.find_teacher_id(user_id)?.ok_or_else(|| {
+                    AppError::Forbidden("No teacher record for this account".to_string())
+                })?)
+            }
+            Role::Student => AppealScope::Student(
+                self.store
+                    .find_student_id(user_id)?
+                    .ok_or_else(|| AppError::NotFound("Student not found".to_string()))?,
+            ),
+            Role::Parent => AppealScope::Children(
+                self.store
+                    .find_parent_id(user_id)?
+                    .ok_or_else(|| AppError::NotFound("Parent not found".to_string()))?,
+            ),
+        };
+        Ok(self.store.appeals(scope)?)
+    }
+
+    /// Loads an appeal whose demerit `user_id` may change.
+    fn decidable(
+        &self,
+        user_id: i32,
+        role: Role,
+        appeal_id: i32,
+    ) -> Result<StoredAppeal, AppError> {
+        let appeal = self
+            .store
+            .find_appeal(appeal_id)?
+            .ok_or_else(|| AppError::NotFound("Appeal not found".to_string()))?;
+        DemeritService::new(self.store).changeable(user_id, role, appeal.demerit_id)?;
+        Ok(appeal)
+    }
+
+    /// Moves an open appeal under review.
+    pub fn start_review(&self, user_id: i32, role: Role, appeal_id: i32) -> Result<(), AppError> {
+        let appeal = self.decidable(user_id, role, appeal_id)?;
+        if appeal.status != AppealStatus::Open {
+            return Err(AppError::Conflict(
+                "Only an open appeal can be taken under review".to_string(),
+            ));
+        }
+        Ok(self.store.start_appeal_review(appeal_id, user_id)?)
+    }
+
+    /// Closes an appeal under review. Upholding needs a comment; overturning
+    /// voids the demerit, or lowers its points when `points` is given.
+    pub fn decide(
+        &self,
+        user_id: i32,
+        role: Role,
+        appeal_id: i32,
+        decision: &AppealDecisionRequest,
+    ) -> Result<(), AppError> {
+        let appeal = self.decidable(user_id, role, appeal_id)?;
+        if appeal.status != AppealStatus::UnderReview {
+            return Err(AppError::Conflict(
+                "Only an appeal under review can be decided".to_string(),
+            ));
+        }
+        let comment = decision
+            .comment
+            .as_deref()
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(str::to_string);
+        let reason = match &comment {
+            Some(comment) => format!("Appeal overturned: {}", comment),
+            None => "Appeal overturned".to_string(),
+        };
+
+        let outcome = match (decision.outcome, decision.points) {
+            (AppealStatus::Upheld, _) if comment.is_none() => {
+                return Err(AppError::invalid(
+                    "comment",
+                    "Explain why the demerit stands",
+                ));
+            }
+            (AppealStatus::Upheld, _) => AppealOutcome::Upheld,
+            (AppealStatus::Overturned, points) => {
+                let demerit = self
+                    .store
+                    .find_demerit(appeal.demerit_id)?
+                    .ok_or_else(|| AppError::NotFound("Demerit not found".to_string()))?;
+                if demerit.voided {
+                    return Err(AppError::Conflict(
+                        "The demerit is already voided".to_string(),
+                    ));
+                }
+                match points {
+                    Some(points) if points < 1 || points >= demerit.points => {
+                        return Err(AppError::invalid(
+                            "points",
+                            format!(
+                                "Reduced points must be at least 1 and less than {}",
+                                demerit.points
+                            ),
+                        ));
+                    }
+                    Some(points) => AppealOutcome::ReducePoints { points, reason },
+                    None => AppealOutcome::Void { reason },
+                }
+            }
+            (AppealStatus::Open | AppealStatus::UnderReview, _) => {
+                return Err(AppError::invalid(
+                    "outcome",
+                    "An appeal is decided as upheld or overturned",
+                ));
+            }
+        };
+
+        Ok(self.store.decide_appeal(
+            appeal_id,
+            &AppealDecision {
+                decided_by: user_id,
+                outcome,
+                comment,
+            },
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::{DemeritRepository, RoleDetails};
+    use crate::database::sqlite::fixtures::{parent, student, teacher, user};
+    use crate::database::sqlite::{self, fixtures};
+
+    /// Records a 3-point demerit issued by `teacher_user`.
+    fn demerit(store: &sqlite::SqliteStore, teacher_user: i32, student_id: i32) -> i32 {
+        fixtures::demerit(store, teacher_user, student_id, 3)
+    }
+
+    fn decision(
+        outcome: AppealStatus,
+        comment: Option<&str>,
+        points: Option<i32>,
+    ) -> AppealDecisionRequest {
+        AppealDecisionRequest {
+            outcome,
+            comment: comment.map(str::to_string),
+            points,
+        }
+    }
+
+    #[test]
+    fn students_and_linked_parents_file_appeals() {
+        let store = sqlite::memory_store();
+        let service = AppealService::new(&store);
+        let issuer = teacher(&store, "teacher");
+        let (jane_user, jane) = student(&store, "jane");
+        let (bob_user, _) = student(&store, "bob");
+        let (parent_user, _) = parent(&store, "parent", &[jane]);
+        let first = demerit(&store, issuer, jane);
+        let second = demerit(&store, issuer, jane);
+
+        assert!(matches!(
+            service.file(jane_user, Role::Student, first, " "),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            service.file(bob_user, Role::Student, first, "Not me"),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            service.file(issuer, Role::Teacher, first, "Not me"),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            service.file(jane_user, Role::Student, first + 100, "Not me"),
+            Err(AppError::NotFound(_))
+        ));
+        service
+            .file(jane_user, Role::Student, first, "Not me")
+            .unwrap();
+        assert!(matches!(
+            service.file(parent_user, Role::Parent, first, "Not her"),
+            Err(AppError::Conflict(_))
+        ));
+        service
+            .file(parent_user, Role::Parent, second, "Not her")
+            .unwrap();
+
+        assert_eq!(service.list(jane_user, Role::Student).unwrap().len(), 2);
+        assert_eq!(service.list(parent_user, Role::Parent).unwrap().len(), 2);
+        assert_eq!(service.list(issuer, Role::Teacher).unwrap().len(), 2);
+        assert!(service.list(bob_user, Role::Student).unwrap().is_empty());
+    }
+
+    #[test]
+    fn appeals_are_reviewed_before_they_are_decided() {
+        let store = sqlite::memory_store();
+        let service = AppealService::new(&store);
+        let issuer = teacher(&store, "teacher");
+        let other = teacher(&store, "other");
+        let admin = user(&store, "admin", RoleDetails::Admin);
+        let (jane_user, jane) = student(&store, "jane");
+        let upheld = demerit(&store, issuer, jane);
+        let reduced = demerit(&store, issuer, jane);
+        let voided = demerit(&store, issuer, jane);
+        let file = |demerit_id| {
+            service
+                .file(jane_user, Role::Student, demerit_id, "Not me")
+                .unwrap()
+        };
+        let (upheld, reduced, voided) = (file(upheld), file(reduced), file(voided));
+        let overturn = decision(AppealStatus::Overturned, None, None);
+
+        assert!(matches!(
+            service.decide(issuer, Role::Teacher, upheld, &overturn),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            service.start_review(other, Role::Teacher, upheld),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            service.start_review(jane_user, Role::Student, upheld),
+            Err(AppError::Forbidden(_))
+        ));
+        for appeal_id in [upheld, reduced, voided] {
+            service
+                .start_review(issuer, Role::Teacher, appeal_id)
+                .unwrap();
+        }
+        assert!(matches!(
+            service.start_review(issuer, Role::Teacher, upheld),
+            Err(AppError::Conflict(_))
+        ));
+
+        assert_eq!(
+            service.decide(
+                issuer,
+                Role::Teacher,
+                upheld,
+                &decision(AppealStatus::Upheld, Some(" "), None)
+            ),
+            Err(AppError::invalid(
+                "comment",
+                "Explain why the demerit stands"
+            ))
+        );
+        assert!(matches!(
+            service.decide(
+                issuer,
+                Role::Teacher,
+                upheld,
+                &decision(AppealStatus::Open, None, None)
+            ),
+            Err(AppError::Validation(_))
+        ));
+        service
+            .decide(
+                issuer,
+                Role::Teacher,
+                upheld,
+                &decision(AppealStatus::Upheld, Some("Seen on camera"), None),
+            )
+            .unwrap();
+        assert!(matches!(
+            service.decide(issuer, Role::Teacher, upheld, &overturn),
+            Err(AppError::Conflict(_))
+        ));
+
+        assert!(matches!(
+            service.decide(
+                admin,
+                Role::Admin,
+                reduced,
+                &decision(AppealStatus::Overturned, None, Some(3))
+            ),
+            Err(AppError::Validation(_))
+        ));
+        service
+            .decide(
+                admin,
+                Role::Admin,
+                reduced,
+                &decision(AppealStatus::Overturned, Some("Partly late"), Some(1)),
+            )
+            .unwrap();
+        service
+            .decide(issuer, Role::Teacher, voided, &overturn)
+            .unwrap();
+
+        let demerits = store.student_demerits(jane).unwrap();
+        let points: Vec<i32> = demerits.iter().map(|d| d.points).collect();
+        assert_eq!(points, [3, 1, 3]);
+        assert_eq!(
+            demerits[0].void_reason.as_deref(),
+            Some("Appeal overturned")
+        );
+        assert_eq!(demerits[2].appeal_status.as_deref(), Some("upheld"));
+        assert_eq!(
+            store.demerit_revisions(demerits[1].demerit_id).unwrap()[1]
+                .reason
+                .as_deref(),
+            Some("Appeal overturned: Partly late")
+        );
+    }
+}
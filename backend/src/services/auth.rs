@@ -1,94 +1,403 @@
-use crate::database::db;
-use crate::models;
+use crate::config;
+use crate::database::repository::{NewRegistration, Store};
+use crate::error::AppError;
+use crate::models::{self, FieldError};
+use crate::services::registration::{self, RegistrationPolicy};
+use crate::services::session::{self, IssuedSession};
+use crate::services::{lockout, password, secret, two_factor, verification};
 use bcrypt::verify;
 use bcrypt::{hash, DEFAULT_COST};
-use rusqlite::params;
-
-pub fn auth_request(req: models::LoginRequest) -> Result<models::AuthResponse, String> {
-    // Get DB connection
-    let conn = db::get_db_connection().map_err(|e| format!("Database connection error: {}", e))?;
-
-    // Query to fetch user details
-    let user = conn
-        .query_row(
-            "SELECT user_id, username, password_hash, email, user_type, first_name, last_name
-             FROM users WHERE email = ?1",
-            params![req.email],
-            |row| {
-                Ok(models::User {
-                    id: row.get(0)?,
-                    username: row.get(1)?,
-                    password_hash: row.get(2)?,
-                    email: row.get(3)?,
-                    user_type: row.get(4)?,
-                    first_name: row.get(5)?,
-                    last_name: row.get(6)?,
-                })
-            },
-        )
-        .map_err(|_| "User not found".to_string())?;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use tracing::error;
 
-    println!("Found user with hash: {}", user.password_hash); // Debug line
-    println!("Comparing with password: {}", req.password); // Debug line
+/// Claims carried by every access token issued by the backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: i32,
+    pub user_type: String,
+    pub sid: String,
+    #[serde(default)]
+    pub must_change_password: bool,
+    #[serde(default)]
+    pub must_enroll_two_factor: bool,
+    pub iat: i64,
+    pub exp: i64,
+}
 
-    // Verify password
-    let is_valid = verify(&req.password, &user.password_hash)
-        .map_err(|e| format!("Password verification error: {}", e))?;
+/// The secret access tokens are signed with.
+pub fn jwt_secret() -> &'static [u8] {
+    config::get().auth.jwt_secret.as_bytes()
+}
 
-    println!("Password validation result: {}", is_valid); // Debug line
+/// Signs a token identifying `user_id` with the given role within a session.
+pub fn issue_token(
+    user_id: i32,
+    user_type: &str,
+    session_id: &str,
+    must_change_password: bool,
+    must_enroll_two_factor: bool,
+) -> Result<String, AppError> {
+    let now = Utc::now();
+    let claims = Claims {
+        sub: user_id,
+        user_type: user_type.to_string(),
+        sid: session_id.to_string(),
+        must_change_password,
+        must_enroll_two_factor,
+        iat: now.timestamp(),
+        exp: (now + Duration::hours(config::get().auth.jwt_expiry_hours)).timestamp(),
+    };
 
-    if !is_valid {
-        return Err("Invalid password".to_string());
-    }
+    encode_claims(&claims, jwt_secret())
+}
+
+/// Checks the signature and expiry of `token` and returns its claims.
+pub fn verify_token(token: &str) -> Result<Claims, AppError> {
+    decode_claims(token, jwt_secret())
+}
+
+fn encode_claims(claims: &Claims, secret: &[u8]) -> Result<String, AppError> {
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(secret),
+    )
+    .map_err(|e| AppError::Internal(format!("Token signing error: {}", e)))
+}
+
+fn decode_claims(token: &str, secret: &[u8]) -> Result<Claims, AppError> {
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret),
+        &Validation::default(),
+    )
+    .map(|data| data.claims)
+    .map_err(|_| AppError::Unauthenticated("Access token is invalid or has expired".to_string()))
+}
+
+fn auth_response(
+    user: models::User,
+    session: IssuedSession,
+) -> Result<models::AuthResponse, AppError> {
+    let must_enroll_two_factor = user.two_factor_required && !user.two_factor_enabled;
+    let token = issue_token(
+        user.id,
+        &user.user_type,
+        &session.session_id,
+        user.must_change_password,
+        must_enroll_two_factor,
+    )?;
 
     Ok(models::AuthResponse {
-        token: "10281".to_string(),
+        token,
+        refresh_token: session.refresh_token,
         user: models::UserResponse {
             id: user.id.to_string(),
-            email: req.email,
-            username: req.username,
-            first_name: req.first_name,
-            last_name: req.last_name,
+            email: user.email,
+            username: Some(user.username),
+            first_name: Some(user.first_name),
+            last_name: Some(user.last_name),
             permissions: user.user_type,
+            must_change_password: user.must_change_password,
+            two_factor_enabled: user.two_factor_enabled,
+            must_enroll_two_factor,
         },
     })
 }
 
-pub fn register(req: models::RegisterRequest) -> Result<models::AuthResponse, String> {
-    let conn = db::get_db_connection().map_err(|e| format!("Database connection error: {}", e))?;
-
-    let password_hash =
-        hash(req.password, DEFAULT_COST).map_err(|e| format!("Password hashing error: {}", e))?;
-
-    let sql = "INSERT INTO users
-                   (username, password_hash, email, user_type, first_name, last_name)
-                   VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-                   RETURNING user_id"; // This will return the newly created user_id
-
-    let user_id: i32 = conn
-        .query_row(
-            sql,
-            params![
-                req.username,
-                password_hash,
-                req.email,
-                "parent".to_string(), // Assuming it is parent register
-                req.first_name,
-                req.last_name,
-            ],
-            |row| row.get(0),
-        )
-        .map_err(|e| format!("Failed to create user: {}", e))?;
+/// Loads a user who is known to exist, e.g. from a session or challenge.
+fn load_user(store: &dyn Store, user_id: i32) -> Result<models::User, AppError> {
+    store
+        .sign_in_account(user_id)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))
+}
 
-    Ok(models::AuthResponse {
-        token: "10281".to_string(),
-        user: models::UserResponse {
-            id: user_id.to_string(),
-            email: req.email,
-            username: req.username,
-            first_name: req.first_name,
-            last_name: req.last_name,
-            permissions: "parent".to_string(),
-        },
-    })
+/// A hash to check passwords against when no account matches, so that
+/// unknown addresses take as long to reject as wrong passwords.
+fn dummy_password_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| hash("not-a-real-password", DEFAULT_COST).unwrap_or_default())
+}
+
+/// Result of a correct password: either a signed-in session, or a challenge
+/// to complete with a second factor.
+pub enum LoginOutcome {
+    SignedIn(models::AuthResponse),
+    TwoFactorRequired(models::TwoFactorChallenge),
+}
+
+/// Checks credentials and starts a session, unless the account also needs a
+/// second factor. Failures are counted towards the lockout of both the account
+/// and the client address, and all answer with the same error so callers
+/// cannot tell whether an email address has an account.
+pub fn auth_request(
+    store: &dyn Store,
+    req: models::LoginRequest,
+    ip: &str,
+) -> Result<LoginOutcome, AppError> {
+    let user = store.sign_in_account_by_email(&req.email)?;
+    let is_valid = match &user {
+        Some(user) => verify(&req.password, &user.password_hash).unwrap_or(false),
+        None => {
+            let _ = verify(&req.password, dummy_password_hash());
+            false
+        }
+    };
+
+    let user = match user {
+        Some(user) if is_valid => user,
+        _ => {
+            lockout::record_failure(store, &req.email, ip)?;
+            return Err(AppError::InvalidCredentials);
+        }
+    };
+
+    lockout::record_success(store, &req.email)?;
+
+    if user.status == verification::STATUS_PENDING {
+        return Err(AppError::EmailNotVerified);
+    }
+
+    if user.two_factor_enabled {
+        return Ok(LoginOutcome::TwoFactorRequired(
+            models::TwoFactorChallenge {
+                two_factor_required: true,
+                challenge_token: two_factor::issue_challenge(user.id)?,
+            },
+        ));
+    }
+
+    let session = session::create_session(store, user.id)?;
+    auth_response(user, session).map(LoginOutcome::SignedIn)
+}
+
+/// Second login step: redeems a challenge from `auth_request` with a TOTP or
+/// recovery code. Wrong codes count towards the lockout like wrong passwords.
+pub fn complete_two_factor(
+    store: &dyn Store,
+    req: models::TwoFactorLoginRequest,
+    ip: &str,
+) -> Result<models::AuthResponse, AppError> {
+    let user_id = two_factor::verify_challenge(&req.challenge_token)?;
+    let user = load_user(store, user_id)?;
+
+    if let lockout::Throttle::LockedOut { retry_after_secs } =
+        lockout::check(store, &user.email, ip)?
+    {
+        return Err(AppError::TooManyAttempts {
+            retry_after_secs: Some(retry_after_secs),
+        });
+    }
+
+    if !two_factor::verify_code(store, user.id, &req.code)? {
+        lockout::record_failure(store, &user.email, ip)?;
+        return Err(AppError::InvalidCode);
+    }
+
+    let session = session::create_session(store, user.id)?;
+    auth_response(user, session)
+}
+
+/// Confirms two-factor enrollment. The caller's session is replaced so the
+/// new access token no longer asks for enrollment.
+pub fn enable_two_factor(
+    store: &dyn Store,
+    user_id: i32,
+    session_id: &str,
+    code: &str,
+) -> Result<(Vec<String>, models::AuthResponse), AppError> {
+    let recovery_codes = two_factor::confirm_enrollment(store, user_id, code)?;
+    session::revoke_session(store, session_id)?;
+
+    let user = load_user(store, user_id)?;
+    let session = session::create_session(store, user.id)?;
+    Ok((recovery_codes, auth_response(user, session)?))
+}
+
+/// Rotates a refresh token and issues a new access token for its session.
+/// The user is re-read so role changes take effect on refresh.
+pub fn refresh(store: &dyn Store, refresh_token: &str) -> Result<models::AuthResponse, AppError> {
+    let session = session::rotate_refresh_token(store, refresh_token)?;
+    let user = load_user(store, session.user_id)?;
+
+    auth_response(user, session)
+}
+
+/// Changes the password of a signed-in user. All existing sessions end, so a
+/// fresh session is started and returned for the caller.
+pub fn change_password(
+    store: &dyn Store,
+    user_id: i32,
+    req: models::ChangePasswordRequest,
+) -> Result<models::AuthResponse, AppError> {
+    password::change_password(store, user_id, &req.current_password, &req.new_password)?;
+
+    let user = load_user(store, user_id)?;
+    let session = session::create_session(store, user.id)?;
+    auth_response(user, session)
+}
+
+/// Checks every field of a registration, so the form can show all problems
+/// at once.
+fn validate_registration(req: &models::RegisterRequest) -> Result<(), AppError> {
+    let mut invalid = Vec::new();
+    let mut require = |field: &str, value: Option<&str>, message: &str| {
+        if value.is_none_or(|value| value.trim().is_empty()) {
+            invalid.push(FieldError {
+                field: field.to_string(),
+                message: message.to_string(),
+            });
+        }
+    };
+    require("username", req.username.as_deref(), "Username is required");
+    require(
+        "first_name",
+        req.first_name.as_deref(),
+        "First name is required",
+    );
+    require(
+        "last_name",
+        req.last_name.as_deref(),
+        "Last name is required",
+    );
+
+    let well_formed = req
+        .email
+        .trim()
+        .split_once('@')
+        .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'));
+    if !well_formed {
+        invalid.push(FieldError {
+            field: "email".to_string(),
+            message: "Enter a valid email address".to_string(),
+        });
+    }
+    if let Err(AppError::Validation(mut errors)) =
+        password::validate_new_password("password", &req.password)
+    {
+        invalid.append(&mut errors);
+    }
+
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Validation(invalid))
+    }
+}
+
+const ACCOUNT_EXISTS: &str = "Username or email already exists";
+
+/// Creates a parent account if the registration policy allows it. An invite
+/// code, when given, is redeemed and links the parent to the invited students.
+/// The account starts out pending and cannot sign in until the emailed
+/// verification link has been opened.
+pub fn register(store: &dyn Store, req: models::RegisterRequest) -> Result<i32, AppError> {
+    let user_id = create_parent_account(store, &config::get().registration, &req)?;
+
+    if let Err(e) = verification::send_verification(store, user_id, &req.email) {
+        // The account exists now; the parent can ask for another link.
+        error!(user_id, error = %e, "Failed to send verification email");
+    }
+
+    Ok(user_id)
+}
+
+/// The pending account and invite links of [`register`], without the email.
+pub(crate) fn create_parent_account(
+    store: &dyn Store,
+    policy: &RegistrationPolicy,
+    req: &models::RegisterRequest,
+) -> Result<i32, AppError> {
+    if req.invite_code.is_none() {
+        policy.check_uninvited(&req.email)?;
+    }
+    validate_registration(req)?;
+
+    let username = req.username.as_deref().unwrap_or_default();
+    if store.find_user_id(username)?.is_some() || store.find_user_id(&req.email)?.is_some() {
+        return Err(AppError::Conflict(ACCOUNT_EXISTS.to_string()));
+    }
+
+    let password_hash = hash(&req.password, DEFAULT_COST)
+        .map_err(|e| AppError::Internal(format!("Password hashing error: {}", e)))?;
+
+    store
+        .register_parent(&NewRegistration {
+            username: username.to_string(),
+            email: req.email.clone(),
+            password_hash,
+            first_name: req.first_name.clone().unwrap_or_default(),
+            last_name: req.last_name.clone().unwrap_or_default(),
+            invite_code_hash: req
+                .invite_code
+                .as_ref()
+                .map(|code| secret::hash(code.trim())),
+        })?
+        .ok_or_else(|| AppError::InvalidToken(registration::INVALID_INVITE.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn claims(user_id: i32, user_type: &str, expires_in: Duration) -> Claims {
+        let now = Utc::now();
+        Claims {
+            sub: user_id,
+            user_type: user_type.to_string(),
+            sid: "session".to_string(),
+            must_change_password: false,
+            must_enroll_two_factor: false,
+            iat: now.timestamp(),
+            exp: (now + expires_in).timestamp(),
+        }
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let token = encode_claims(&claims(7, "student", Duration::hours(1)), SECRET).unwrap();
+        let verified = decode_claims(&token, SECRET).unwrap();
+        assert_eq!((verified.sub, verified.user_type.as_str()), (7, "student"));
+        assert!(decode_claims(&token, b"another-secret").is_err());
+
+        let (signed, signature) = token.rsplit_once('.').unwrap();
+        let flipped = if signature.starts_with('A') { "B" } else { "A" };
+        let bad_signature = format!("{}.{}{}", signed, flipped, &signature[1..]);
+        assert!(decode_claims(&bad_signature, SECRET).is_err());
+
+        // An admin payload does not verify under the student's signature
+        let admin = encode_claims(&claims(1, "admin", Duration::hours(1)), SECRET).unwrap();
+        let (admin_signed, _) = admin.rsplit_once('.').unwrap();
+        let forged = format!("{}.{}", admin_signed, signature);
+        assert!(decode_claims(&forged, SECRET).is_err());
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let token = encode_claims(&claims(7, "student", Duration::hours(-2)), SECRET).unwrap();
+        assert_eq!(
+            decode_claims(&token, SECRET).err(),
+            Some(AppError::Unauthenticated(
+                "Access token is invalid or has expired".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn challenge_tokens_are_not_access_tokens() {
+        let challenge = two_factor::challenge_token(7, SECRET).unwrap();
+        assert!(decode_claims(&challenge, SECRET).is_err());
+        assert_eq!(two_factor::challenge_user(&challenge, SECRET), Ok(7));
+
+        let access = encode_claims(&claims(7, "admin", Duration::hours(1)), SECRET).unwrap();
+        assert_eq!(
+            two_factor::challenge_user(&access, SECRET),
+            Err(AppError::InvalidChallenge)
+        );
+    }
 }
@@ -0,0 +1,389 @@
+//! Issuing demerits and deciding who may read them.
+
+use tracing::info;
+
+use crate::database::repository::{
+    CategoryOption, DemeritChange, DemeritRevision, EscalationCheck, NewDemerit, Store,
+    StoredDemerit, StudentDemeritDetail,
+};
+use crate::error::AppError;
+use crate::models::{EditDemeritRecord, NewDemeritRecord, ParentRecord, Role, TeacherRecord};
+
+pub struct DemeritService<'a> {
+    store: &'a dyn Store,
+}
+
+impl<'a> DemeritService<'a> {
+    pub fn new(store: &'a dyn Store) -> Self {
+        DemeritService { store }
+    }
+
+    pub fn categories(&self) -> Result<Vec<CategoryOption>, AppError> {
+        Ok(self.store.list_categories()?)
+    }
+
+    fn teacher_id(&self, user_id: i32) -> Result<i32, AppError> {
+        self.store
+            .find_teacher_id(user_id)?
+            .ok_or_else(|| AppError::Forbidden("No teacher record for this account".to_string()))
+    }
+
+    fn validate(&self, student_id: i32, category_id: i32, points: i32) -> Result<(), AppError> {
+        if points < 1 {
+            return Err(AppError::invalid("points", "Points must be at least 1"));
+        }
+        if !self.store.student_exists(student_id)? {
+            return Err(AppError::invalid("student_id", "Student not found"));
+        }
+        if !self.store.category_exists(category_id)? {
+            return Err(AppError::invalid("category_id", "Category not found"));
+        }
+        Ok(())
+    }
+
+    /// Records a demerit issued by the teacher signed in as `user_id`, with
+    /// an escalation for every check whose threshold it crosses. Returns the
+    /// demerit id and the ids of the escalations raised.
+    pub fn record(
+        &self,
+        user_id: i32,
+        demerit: &NewDemeritRecord,
+        checks: &[EscalationCheck],
+    ) -> Result<(i32, Vec<i32>), AppError> {
+        let teacher_id = self.teacher_id(user_id)?;
+        self.validate(demerit.student_id, demerit.category_id, demerit.points)?;
+
+        let (demerit_id, escalations) = self.store.record_demerit(
+            &NewDemerit {
+                student_id: demerit.student_id,
+                teacher_id,
+                category_id: demerit.category_id,
+                points: demerit.points,
+                description: demerit.description.clone(),
+            },
+            checks,
+        )?;
+        for escalation_id in &escalations {
+            info!(
+                escalation_id,
+                demerit_id,
+                student_id = demerit.student_id,
+                "Escalation raised"
+            );
+        }
+        Ok((demerit_id, escalations))
+    }
+
+    /// Loads a demerit that `user_id` may change: admins may change any, a
+    /// teacher only the ones they issued.
+    pub(crate) fn changeable(
+        &self,
+        user_id: i32,
+        role: Role,
+        demerit_id: i32,
+    ) -> Result<StoredDemerit, AppError> {
+        let demerit = self
+            .store
+            .find_demerit(demerit_id)?
+            .ok_or_else(|| AppError::NotFound("Demerit not found".to_string()))?;
+        let allowed = match role {
+            Role::Admin => true,
+            Role::Teacher => self.store.find_teacher_id(user_id)? == Some(demerit.teacher_id),
+            Role::Student | Role::Parent => false,
+        };
+        if !allowed {
+            return Err(AppError::Forbidden(
+                "Only the issuing teacher or an admin can change this demerit".to_string(),
+            ));
+        }
+        Ok(demerit)
+    }
+
+    /// Applies `edit` to a demerit that is not voided.
+    pub fn edit(
+        &self,
+        user_id: i32,
+        role: Role,
+        demerit_id: i32,
+        edit: &EditDemeritRecord,
+    ) -> Result<(), AppError> {
+        let current = self.changeable(user_id, role, demerit_id)?;
+        if current.voided {
+            return Err(AppError::Conflict(
+                "A voided demerit cannot be edited".to_string(),
+            ));
+        }
+
+        let change = DemeritChange {
+            student_id: edit.student_id.unwrap_or(current.student_id),
+            category_id: edit.category_id.unwrap_or(current.category_id),
+            points: edit.points.unwrap_or(current.points),
+            description: edit
+                .description
+                .clone()
+                .unwrap_or_else(|| current.description.clone()),
+            changed_by: user_id,
+            reason: edit.reason.clone(),
+        };
+        if change.student_id == current.student_id
+            && change.category_id == current.category_id
+            && change.points == current.points
+            && change.description == current.description
+        {
+            return Err(AppError::BadRequest("Nothing to change".to_string()));
+        }
+        self.validate(change.student_id, change.category_id, change.points)?;
+
+        Ok(self.store.update_demerit(demerit_id, &change)?)
+    }
+
+    /// Voids a demerit: it stays on record but no longer counts.
+    pub fn void(
+        &self,
+        user_id: i32,
+        role: Role,
+        demerit_id: i32,
+        reason: &str,
+    ) -> Result<(), AppError> {
+        let current = self.changeable(user_id, role, demerit_id)?;
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(AppError::invalid(
+                "reason",
+                "Give a reason for voiding the demerit",
+            ));
+        }
+        if current.voided {
+            return Err(AppError::Conflict(
+                "The demerit is already voided".to_string(),
+            ));
+        }
+
+        Ok(self.store.void_demerit(demerit_id, user_id, reason)?)
+    }
+
+    /// Every revision of a demerit, for those who may change it.
+    pub fn revisions(
+        &self,
+        user_id: i32,
+        role: Role,
+        demerit_id: i32,
+    ) -> Result<Vec<DemeritRevision>, AppError> {
+        self.changeable(user_id, role, demerit_id)?;
+        Ok(self.store.demerit_revisions(demerit_id)?)
+    }
+
+    /// Demerits of `student_id` as seen by `user_id`. Staff see every
+    /// student, parents only their children and students only themselves.
+    pub fn for_student(
+        &self,
+        user_id: i32,
+        role: Role,
+        student_id: i32,
+    ) -> Result<Vec<StudentDemeritDetail>, AppError> {
+        match role {
+            Role::Admin | Role::Teacher => {}
+            Role::Parent => {
+                let linked = match self.store.find_parent_id(user_id)? {
+                    Some(parent_id) => self.store.is_linked(parent_id, student_id)?,
+                    None => false,
+                };
+                if !linked {
+                    return Err(AppError::Forbidden(
+                        "You can only view records of your own children".to_string(),
+                    ));
+                }
+            }
+            Role::Student => {
+                let own = self.store.student_info(user_id)?;
+                if own.map(|info| info.student_id) != Some(student_id) {
+                    return Err(AppError::Forbidden(
+                        "You can only view your own records".to_string(),
+                    ));
+                }
+            }
+        }
+
+        if !self.store.student_exists(student_id)? {
+            return Err(AppError::NotFound("Student not found".to_string()));
+        }
+        Ok(self.store.student_demerits(student_id)?)
+    }
+
+    /// Demerits of the student signed in as `user_id`.
+    pub fn own(&self, user_id: i32) -> Result<Vec<StudentDemeritDetail>, AppError> {
+        let student = self
+            .store
+            .student_info(user_id)?
+            .ok_or_else(|| AppError::NotFound("Student not found".to_string()))?;
+        Ok(self.store.student_demerits(student.student_id)?)
+    }
+
+    /// Demerits of every child of the parent signed in as `user_id`.
+    pub fn of_children(&self, user_id: i32) -> Result<Vec<ParentRecord>, AppError> {
+        let parent_id = self
+            .store
+            .find_parent_id(user_id)?
+            .ok_or_else(|| AppError::NotFound("Parent not found".to_string()))?;
+        Ok(self.store.children_demerits(parent_id)?)
+    }
+
+    /// Demerits issued by the teacher signed in as `user_id`.
+    pub fn issued_by(&self, user_id: i32) -> Result<Vec<TeacherRecord>, AppError> {
+        let teacher_id = self.teacher_id(user_id)?;
+        Ok(self.store.teacher_demerits(teacher_id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::RoleDetails;
+    use crate::database::sqlite;
+    use crate::database::sqlite::fixtures::{parent, student, teacher, user};
+
+    fn demerit(student_id: i32, category_id: i32, points: i32) -> NewDemeritRecord {
+        NewDemeritRecord {
+            student_id,
+            category_id,
+            points,
+            description: "Late".to_string(),
+        }
+    }
+
+    #[test]
+    fn record_checks_teacher_student_category_and_points() {
+        let store = sqlite::memory_store();
+        let service = DemeritService::new(&store);
+        let teacher = teacher(&store, "teacher");
+        let (student_user, student_id) = student(&store, "jane");
+
+        assert!(service
+            .record(teacher, &demerit(student_id, 1, 2), &[])
+            .is_ok());
+        assert!(matches!(
+            service.record(student_user, &demerit(student_id, 1, 2), &[]),
+            Err(AppError::Forbidden(_))
+        ));
+        assert_eq!(
+            service.record(teacher, &demerit(student_id + 100, 1, 2), &[]),
+            Err(AppError::invalid("student_id", "Student not found"))
+        );
+        assert_eq!(
+            service.record(teacher, &demerit(student_id, 100, 2), &[]),
+            Err(AppError::invalid("category_id", "Category not found"))
+        );
+        assert!(matches!(
+            service.record(teacher, &demerit(student_id, 1, 0), &[]),
+            Err(AppError::Validation(_))
+        ));
+        assert_eq!(service.issued_by(teacher).unwrap().len(), 1);
+        assert_eq!(service.own(student_user).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parents_and_students_only_see_their_own_records() {
+        let store = sqlite::memory_store();
+        let service = DemeritService::new(&store);
+        let teacher = teacher(&store, "teacher");
+        let (jane_user, jane) = student(&store, "jane");
+        let (_, bob) = student(&store, "bob");
+        let (parent_user, _) = parent(&store, "parent", &[jane]);
+        service.record(teacher, &demerit(jane, 1, 1), &[]).unwrap();
+        service.record(teacher, &demerit(bob, 1, 1), &[]).unwrap();
+
+        let children = service.of_children(parent_user).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].student_name, "jane Test");
+        assert!(matches!(
+            service.of_children(jane_user),
+            Err(AppError::NotFound(_))
+        ));
+
+        assert_eq!(
+            service
+                .for_student(parent_user, Role::Parent, jane)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(matches!(
+            service.for_student(parent_user, Role::Parent, bob),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(service.for_student(jane_user, Role::Student, jane).is_ok());
+        assert!(matches!(
+            service.for_student(jane_user, Role::Student, bob),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            service.for_student(teacher, Role::Teacher, bob + 100),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn only_the_issuing_teacher_or_an_admin_can_change_a_demerit() {
+        let store = sqlite::memory_store();
+        let service = DemeritService::new(&store);
+        let issuer = teacher(&store, "teacher");
+        let other = teacher(&store, "other");
+        let admin = user(&store, "admin", RoleDetails::Admin);
+        let (_, jane) = student(&store, "jane");
+        let (demerit_id, _) = service.record(issuer, &demerit(jane, 1, 2), &[]).unwrap();
+        let edit = EditDemeritRecord {
+            points: Some(3),
+            ..EditDemeritRecord::default()
+        };
+
+        assert!(matches!(
+            service.edit(other, Role::Teacher, demerit_id, &edit),
+            Err(AppError::Forbidden(_))
+        ));
+        service
+            .edit(issuer, Role::Teacher, demerit_id, &edit)
+            .unwrap();
+        assert_eq!(
+            service.edit(issuer, Role::Teacher, demerit_id, &edit),
+            Err(AppError::BadRequest("Nothing to change".to_string()))
+        );
+        assert!(matches!(
+            service.edit(
+                admin,
+                Role::Admin,
+                demerit_id,
+                &EditDemeritRecord {
+                    points: Some(0),
+                    ..EditDemeritRecord::default()
+                }
+            ),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            service.void(issuer, Role::Teacher, demerit_id, "  "),
+            Err(AppError::Validation(_))
+        ));
+        service
+            .void(admin, Role::Admin, demerit_id, "Wrong student")
+            .unwrap();
+        assert!(matches!(
+            service.void(admin, Role::Admin, demerit_id, "Again"),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            service.edit(issuer, Role::Teacher, demerit_id, &edit),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            service.edit(admin, Role::Admin, demerit_id + 100, &edit),
+            Err(AppError::NotFound(_))
+        ));
+
+        let revisions = service
+            .revisions(issuer, Role::Teacher, demerit_id)
+            .unwrap();
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[2].changed_by, "admin Test");
+        assert!(service.revisions(other, Role::Teacher, demerit_id).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+//! Escalation rules set by admins, and the escalations they raise when a new
+//! demerit takes a student's points over a threshold.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::SchoolConfig;
+use crate::database::repository::{
+    Escalation, EscalationCheck, EscalationRule, EscalationRuleValues, Store,
+};
+use crate::error::AppError;
+use crate::models::{EscalationRuleRequest, FieldError, Role};
+
+pub struct EscalationService<'a> {
+    store: &'a dyn Store,
+    school: &'a SchoolConfig,
+}
+
+impl<'a> EscalationService<'a> {
+    pub fn new(store: &'a dyn Store, school: &'a SchoolConfig) -> Self {
+        EscalationService { store, school }
+    }
+
+    pub fn rules(&self) -> Result<Vec<EscalationRule>, AppError> {
+        Ok(self.store.escalation_rules()?)
+    }
+
+    pub fn create_rule(&self, rule: &EscalationRuleRequest) -> Result<i32, AppError> {
+        let values = self.rule_values(rule)?;
+        Ok(self.store.add_escalation_rule(&values)?)
+    }
+
+    pub fn update_rule(&self, rule_id: i32, rule: &EscalationRuleRequest) -> Result<(), AppError> {
+        let values = self.rule_values(rule)?;
+        if !self.store.update_escalation_rule(rule_id, &values)? {
+            return Err(AppError::NotFound("Escalation rule not found".to_string()));
+        }
+        Ok(())
+    }
+
+    fn rule_values(&self, rule: &EscalationRuleRequest) -> Result<EscalationRuleValues, AppError> {
+        let mut invalid = Vec::new();
+        let mut check = |ok: bool, field: &str, message: &str| {
+            if !ok {
+                invalid.push(FieldError {
+                    field: field.to_string(),
+                    message: message.to_string(),
+                });
+            }
+        };
+        check(!rule.name.trim().is_empty(), "name", "Name is required");
+        check(rule.points >= 1, "points", "Points must be at least 1");
+        match rule.window_days {
+            Some(days) => check(days >= 1, "window_days", "Window must be at least 1 day"),
+            None => check(
+                !self.school.term_starts.is_empty(),
+                "window_days",
+                "Give window_days, or set school.term_starts to count points per term",
+            ),
+        }
+        let staff = matches!(
+            self.store.find_role(rule.assigned_to)?,
+            Some(Role::Admin | Role::Teacher)
+        );
+        check(
+            staff,
+            "assigned_to",
+            "Escalations can only be assigned to a teacher or an admin",
+        );
+        if !invalid.is_empty() {
+            return Err(AppError::Validation(invalid));
+        }
+
+        Ok(EscalationRuleValues {
+            name: rule.name.trim().to_string(),
+            points: rule.points,
+            window_days: rule.window_days,
+            action: rule.action,
+            assigned_to: rule.assigned_to,
+            active: rule.active,
+        })
+    }
+
+    /// Start of a rule's window at `now`: `window_days` back, or the start of
+    /// the current term. `None` before the first term has started.
+    fn window_start(&self, window_days: Option<i32>, now: DateTime<Utc>) -> Option<String> {
+        let start = match window_days {
+            Some(days) => now - Duration::days(days.into()),
+            None => self
+                .school
+                .term_start(now.date_naive())?
+                .and_hms_opt(0, 0, 0)?
+                .and_utc(),
+        };
+        Some(start.format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+
+    /// The active rules, each with its window as of now, for
+    /// [`DemeritService::record`](crate::services::demerits::DemeritService::record) to check a new demerit against. A rule
+    /// fires once per crossing: not again while the student stays above it.
+    pub fn checks(&self) -> Result<Vec<EscalationCheck>, AppError> {
+        let now = Utc::now();
+        let mut checks = Vec::new();
+        for rule in self.store.escalation_rules()? {
+            if !rule.active {
+                continue;
+            }
+            let Some(window_start) = self.window_start(rule.window_days, now) else {
+                continue;
+            };
+            checks.push(EscalationCheck {
+                rule_id: rule.rule_id,
+                points: rule.points,
+                window_start,
+                action: rule.action.parse().map_err(AppError::Internal)?,
+                assigned_to: rule.assigned_to,
+            });
+        }
+        Ok(checks)
+    }
+
+    /// Open escalations: all of them for admins, a teacher's own otherwise.
+    pub fn open(&self, user_id: i32, role: Role) -> Result<Vec<Escalation>, AppError> {
+        let assigned_to = match role {
+            Role::Admin => None,
+            _ => Some(user_id),
+        };
+        Ok(self.store.open_escalations(assigned_to)?)
+    }
+
+    /// Closes an escalation; only its assignee or an admin may.
+    pub fn resolve(
+        &self,
+        user_id: i32,
+        role: Role,
+        escalation_id: i32,
+        resolution: &str,
+    ) -> Result<(), AppError> {
+        let escalation = self
+            .store
+            .find_escalation(escalation_id)?
+            .ok_or_else(|| AppError::NotFound("Escalation not found".to_string()))?;
+        if role != Role::Admin && escalation.assigned_to != user_id {
+            return Err(AppError::Forbidden(
+                "Only the assigned staff member or an admin can resolve this escalation"
+                    .to_string(),
+            ));
+        }
+        if escalation.resolved {
+            return Err(AppError::Conflict(
+                "The escalation is already resolved".to_string(),
+            ));
+        }
+        let resolution = resolution.trim();
+        if resolution.is_empty() {
+            return Err(AppError::invalid(
+                "resolution",
+                "Describe how the escalation was resolved",
+            ));
+        }
+
+        Ok(self
+            .store
+            .resolve_escalation(escalation_id, user_id, resolution)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::RoleDetails;
+    use crate::database::sqlite::fixtures::{student, teacher, user};
+    use crate::database::sqlite::{self, SqliteStore};
+    use crate::models::{EscalationAction, NewDemeritRecord};
+    use crate::services::demerits::DemeritService;
+
+    fn rule(points: i32, window_days: Option<i32>, assigned_to: i32) -> EscalationRuleRequest {
+        EscalationRuleRequest {
+            name: format!("Detention at {}", points),
+            points,
+            window_days,
+            action: EscalationAction::Detention,
+            assigned_to,
+            active: true,
+        }
+    }
+
+    /// Records a demerit against the rules, as the handler does.
+    fn demerit(
+        service: &EscalationService,
+        store: &SqliteStore,
+        teacher_user: i32,
+        student_id: i32,
+        points: i32,
+    ) -> Vec<i32> {
+        let demerit = NewDemeritRecord {
+            student_id,
+            category_id: 1,
+            points,
+            description: "Late".to_string(),
+        };
+        DemeritService::new(store)
+            .record(teacher_user, &demerit, &service.checks().unwrap())
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn rules_need_a_window_and_a_staff_assignee() {
+        let store = sqlite::memory_store();
+        let no_terms = SchoolConfig::default();
+        let service = EscalationService::new(&store, &no_terms);
+        let teacher = teacher(&store, "teacher");
+        let (student_user, _) = student(&store, "jane");
+
+        let Err(AppError::Validation(fields)) = service.create_rule(&rule(0, None, student_user))
+        else {
+            panic!("expected a validation error");
+        };
+        let fields: Vec<&str> = fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(fields, ["points", "window_days", "assigned_to"]);
+
+        let rule_id = service.create_rule(&rule(5, Some(30), teacher)).unwrap();
+        let mut inactive = rule(5, Some(30), teacher);
+        inactive.active = false;
+        service.update_rule(rule_id, &inactive).unwrap();
+        assert!(!service.rules().unwrap()[0].active);
+        assert!(matches!(
+            service.update_rule(rule_id + 100, &inactive),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn a_rule_fires_once_when_its_threshold_is_crossed() {
+        let store = sqlite::memory_store();
+        let school = SchoolConfig {
+            term_starts: vec![Utc::now().format("%Y-%m-%d").to_string()],
+        };
+        let service = EscalationService::new(&store, &school);
+        let teacher = teacher(&store, "teacher");
+        let (_, student_id) = student(&store, "jane");
+        service.create_rule(&rule(5, Some(30), teacher)).unwrap();
+        service.create_rule(&rule(6, None, teacher)).unwrap();
+        let mut inactive = rule(1, Some(30), teacher);
+        inactive.active = false;
+        service.create_rule(&inactive).unwrap();
+
+        assert!(demerit(&service, &store, teacher, student_id, 3).is_empty());
+        assert_eq!(demerit(&service, &store, teacher, student_id, 2).len(), 1);
+        assert_eq!(demerit(&service, &store, teacher, student_id, 1).len(), 1);
+        assert!(demerit(&service, &store, teacher, student_id, 4).is_empty());
+
+        let open = service.open(teacher, Role::Teacher).unwrap();
+        assert_eq!(open.len(), 2);
+        assert_eq!((open[0].points, open[1].points), (5, 6));
+        assert_eq!(open[0].action, "detention");
+    }
+
+    #[test]
+    fn only_the_assignee_or_an_admin_resolves_an_escalation() {
+        let store = sqlite::memory_store();
+        let school = SchoolConfig::default();
+        let service = EscalationService::new(&store, &school);
+        let teacher = teacher(&store, "teacher");
+        let admin = user(&store, "admin", RoleDetails::Admin);
+        let (student_user, student_id) = student(&store, "jane");
+        service.create_rule(&rule(1, Some(7), admin)).unwrap();
+        let escalation_id = demerit(&service, &store, teacher, student_id, 1)[0];
+
+        assert!(service.open(teacher, Role::Teacher).unwrap().is_empty());
+        assert!(matches!(
+            service.resolve(teacher, Role::Teacher, escalation_id, "Done"),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            service.resolve(student_user, Role::Student, escalation_id, "Done"),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            service.resolve(admin, Role::Admin, escalation_id, " "),
+            Err(AppError::Validation(_))
+        ));
+        service
+            .resolve(admin, Role::Admin, escalation_id, "Detention served")
+            .unwrap();
+        assert!(matches!(
+            service.resolve(admin, Role::Admin, escalation_id, "Again"),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(service.open(admin, Role::Admin).unwrap().is_empty());
+    }
+}
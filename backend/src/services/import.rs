@@ -0,0 +1,150 @@
+//! Bulk import of students from CSV, used by the upload endpoint and by
+//! `demerit-admin import-csv`.
+
+use std::io::Read;
+
+use bcrypt::{hash, DEFAULT_COST};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::database::repository::{NewAccount, NewDemerit, RoleDetails, Store};
+use crate::error::AppError;
+use crate::services::secret;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct StudentCsvRecord {
+    name: String,
+    grade: i32,
+    class: String,
+    demerits: i32,
+}
+
+/// Outcome of an import. Rows that fail are reported in `errors` and skipped;
+/// the rest are committed.
+#[derive(Debug, Serialize)]
+pub struct ImportResult {
+    pub status: String,
+    pub message: String,
+    pub success_count: usize,
+    pub failure_count: usize,
+    pub errors: Vec<String>,
+    /// `name: password` for every newly created student, to hand out once.
+    pub generated_passwords: Vec<String>,
+}
+
+/// Imports students from a CSV file with `name,grade,class,demerits` columns.
+/// Existing students (matched by generated username or email) have their
+/// grade and class updated; new ones get an account with a random password
+/// that must be changed at first login. A positive `demerits` value is
+/// recorded as a "Data migration" demerit. Each row is stored on its own, so a
+/// failing row leaves the others in place.
+pub fn import_students(store: &dyn Store, reader: impl Read) -> Result<ImportResult, AppError> {
+    let mut rdr = csv::Reader::from_reader(reader);
+
+    let mut success_count = 0;
+    let mut failure_count = 0;
+    let mut errors = Vec::new();
+    let mut generated_passwords = Vec::new();
+
+    // Find the teacher and category for "Migration" demerits
+    let teacher_id = store.any_teacher_id()?.ok_or_else(|| {
+        AppError::Conflict("Importing needs at least one teacher account".to_string())
+    })?;
+    let category_id = store.find_category_id("Late to Class")?.ok_or_else(|| {
+        AppError::Conflict("Importing needs the demerit category Late to Class".to_string())
+    })?;
+
+    // Process each record
+    for result in rdr.deserialize() {
+        let record: StudentCsvRecord = match result {
+            Ok(record) => record,
+            Err(e) => {
+                failure_count += 1;
+                errors.push(format!("Error parsing CSV record: {}", e));
+                continue;
+            }
+        };
+
+        match import_record(store, &record, teacher_id, category_id) {
+            Ok(Some(password)) => {
+                // Returned to the uploading admin only; never logged.
+                generated_passwords.push(format!("{}: {}", record.name, password));
+                success_count += 1;
+            }
+            Ok(None) => success_count += 1,
+            Err(e) => {
+                // The detail may quote the database, so it is only logged.
+                warn!(student = %record.name, error = %e, "Failed to import student");
+                failure_count += 1;
+                errors.push(format!("{}: could not be stored", record.name));
+            }
+        }
+    }
+
+    Ok(ImportResult {
+        status: "success".to_string(),
+        message: "CSV processing completed".to_string(),
+        success_count,
+        failure_count,
+        errors,
+        generated_passwords,
+    })
+}
+
+/// Stores one row, returning the generated password if the student is new.
+fn import_record(
+    store: &dyn Store,
+    record: &StudentCsvRecord,
+    teacher_id: i32,
+    category_id: i32,
+) -> Result<Option<String>, String> {
+    // Format the student name for username and email
+    let name_parts: Vec<&str> = record.name.split_whitespace().collect();
+    let username = record.name.replace(" ", "_").to_lowercase();
+    let email = format!("{}@school.edu", username);
+
+    let existing = match store.find_user_id(&username)? {
+        Some(user_id) => Some(user_id),
+        None => store.find_user_id(&email)?,
+    };
+
+    let (student_id, password) = match existing {
+        Some(user_id) => (
+            store.save_student(user_id, record.grade, &record.class)?,
+            None,
+        ),
+        None => {
+            let password = secret::generate(12); // 12 characters is reasonably secure
+            let password_hash = hash(&password, DEFAULT_COST)
+                .map_err(|e| format!("Failed to hash password: {}", e))?;
+            let user_id = store.create_user(&NewAccount {
+                username,
+                email,
+                password_hash,
+                first_name: name_parts.first().unwrap_or(&"").to_string(),
+                last_name: name_parts.get(1).unwrap_or(&"").to_string(),
+                must_change_password: true,
+                details: RoleDetails::Student {
+                    grade_level: record.grade,
+                    class_section: record.class.clone(),
+                },
+            })?;
+            let student_id = store
+                .find_student_id(user_id)?
+                .ok_or_else(|| "Student record was not created".to_string())?;
+            (student_id, Some(password))
+        }
+    };
+
+    if record.demerits > 0 {
+        store.add_demerit(&NewDemerit {
+            student_id,
+            teacher_id,
+            category_id,
+            points: record.demerits,
+            description: "Data migration".to_string(),
+        })?;
+    }
+
+    Ok(password)
+}
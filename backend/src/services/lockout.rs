@@ -0,0 +1,242 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::database::repository::{LockoutEntry, Store};
+use crate::error::AppError;
+use crate::services::secret;
+
+/// Failed logins for one email address before it is locked.
+const MAX_ACCOUNT_FAILURES: i64 = 5;
+/// Failed logins from one client address before it is locked. Higher than the
+/// account limit because a school network may share a single address.
+const MAX_IP_FAILURES: i64 = 20;
+/// How long a lock lasts, and how long failures are remembered.
+const LOCKOUT_WINDOW: chrono::Duration = chrono::Duration::minutes(15);
+/// Delay before answering the n-th attempt is BASE_DELAY_MS * 2^(n-1), capped.
+const BASE_DELAY_MS: u64 = 250;
+const MAX_DELAY_MS: u64 = 5_000;
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub const SCOPE_ACCOUNT: &str = "account";
+pub const SCOPE_IP: &str = "ip";
+
+#[derive(Debug, PartialEq)]
+pub enum Throttle {
+    Allowed { delay: Duration },
+    LockedOut { retry_after_secs: i64 },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClearLockoutRequest {
+    pub scope: String,
+    pub subject: String,
+}
+
+fn account_subject(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// `now` shifted by `offset`, as stored in the database.
+fn timestamp(offset: chrono::Duration) -> String {
+    (Utc::now() + offset).format(TIMESTAMP_FORMAT).to_string()
+}
+
+/// Decides whether a login attempt may proceed and how long to stall it.
+pub fn check(store: &dyn Store, email: &str, ip: &str) -> Result<Throttle, AppError> {
+    let throttle =
+        store.login_throttle(&account_subject(email), ip, &timestamp(-LOCKOUT_WINDOW))?;
+
+    if let Some(locked_until) = throttle.locked_until {
+        let locked_until =
+            NaiveDateTime::parse_from_str(&locked_until, TIMESTAMP_FORMAT).map_err(|e| {
+                AppError::Internal(format!("Invalid lock time {}: {}", locked_until, e))
+            })?;
+        let remaining = locked_until - Utc::now().naive_utc();
+        return Ok(Throttle::LockedOut {
+            retry_after_secs: remaining.num_seconds().max(0) + 1,
+        });
+    }
+
+    let failures = throttle.recent_failures;
+    let delay = if failures == 0 {
+        Duration::ZERO
+    } else {
+        let factor = 1u64 << (failures - 1).min(16);
+        Duration::from_millis((BASE_DELAY_MS * factor).min(MAX_DELAY_MS))
+    };
+    Ok(Throttle::Allowed { delay })
+}
+
+fn bump(store: &dyn Store, scope: &str, subject: &str, limit: i64) -> Result<(), AppError> {
+    let locked = store.record_login_failure(
+        scope,
+        subject,
+        &timestamp(-LOCKOUT_WINDOW),
+        &timestamp(LOCKOUT_WINDOW),
+        limit,
+    )?;
+    if locked {
+        log_lock(store, scope, subject);
+    }
+    Ok(())
+}
+
+/// The account behind an account-scope subject, for logging by id.
+fn subject_user_id(store: &dyn Store, subject: &str) -> Option<i32> {
+    store.find_user_id(subject).unwrap_or(None)
+}
+
+/// Short hash of a subject that matches no account, so it can be logged.
+fn subject_hash(subject: &str) -> String {
+    secret::hash(subject)[..12].to_string()
+}
+
+/// Logs a new lock without the email address: accounts are named by user id,
+/// or by a digest of the address when no account has it.
+fn log_lock(store: &dyn Store, scope: &str, subject: &str) {
+    if scope != SCOPE_ACCOUNT {
+        warn!(scope, ip = subject, "Login locked after repeated failures");
+        return;
+    }
+    match subject_user_id(store, subject) {
+        Some(user_id) => warn!(scope, user_id, "Login locked after repeated failures"),
+        None => warn!(
+            scope,
+            subject_hash = %subject_hash(subject),
+            "Login locked after repeated failures"
+        ),
+    }
+}
+
+/// Counts a failed attempt against both the account and the client address.
+pub fn record_failure(store: &dyn Store, email: &str, ip: &str) -> Result<(), AppError> {
+    bump(
+        store,
+        SCOPE_ACCOUNT,
+        &account_subject(email),
+        MAX_ACCOUNT_FAILURES,
+    )?;
+    bump(store, SCOPE_IP, ip, MAX_IP_FAILURES)
+}
+
+/// Forgets earlier failures for an account after a successful login. Address
+/// failures are kept so one valid account cannot be used to reset them.
+pub fn record_success(store: &dyn Store, email: &str) -> Result<(), AppError> {
+    store.clear_login_failures(SCOPE_ACCOUNT, &account_subject(email))?;
+    Ok(())
+}
+
+/// Accounts and addresses that are locked or have recent failures.
+pub fn list(store: &dyn Store) -> Result<Vec<LockoutEntry>, AppError> {
+    Ok(store.login_failures(&timestamp(-LOCKOUT_WINDOW))?)
+}
+
+/// Removes the failure record for an account or address. Returns whether a
+/// record existed.
+pub fn clear(store: &dyn Store, scope: &str, subject: &str) -> Result<bool, AppError> {
+    let subject = if scope == SCOPE_ACCOUNT {
+        account_subject(subject)
+    } else if scope == SCOPE_IP {
+        subject.to_string()
+    } else {
+        return Err(AppError::invalid(
+            "scope",
+            format!("Unknown lockout scope: {}", scope),
+        ));
+    };
+
+    let removed = store.clear_login_failures(scope, &subject)?;
+    if removed {
+        if scope == SCOPE_IP {
+            info!(scope, ip = %subject, "Login lockout cleared");
+        } else if let Some(user_id) = subject_user_id(store, &subject) {
+            info!(scope, user_id, "Login lockout cleared");
+        } else {
+            info!(scope, subject_hash = %subject_hash(&subject), "Login lockout cleared");
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite;
+
+    const IP: &str = "10.0.0.1";
+
+    fn delay_ms(store: &dyn Store, email: &str, ip: &str) -> u128 {
+        match check(store, email, ip).unwrap() {
+            Throttle::Allowed { delay } => delay.as_millis(),
+            Throttle::LockedOut { .. } => panic!("{} from {} is locked", email, ip),
+        }
+    }
+
+    fn is_locked(store: &dyn Store, email: &str, ip: &str) -> bool {
+        matches!(
+            check(store, email, ip).unwrap(),
+            Throttle::LockedOut { retry_after_secs } if retry_after_secs > 0
+        )
+    }
+
+    #[test]
+    fn accounts_lock_after_repeated_failures_with_growing_delays() {
+        let store = sqlite::memory_store();
+
+        assert_eq!(delay_ms(&store, "ada@school.edu", IP), 0);
+        let mut delays = Vec::new();
+        for _ in 1..MAX_ACCOUNT_FAILURES {
+            record_failure(&store, "Ada@School.edu", IP).unwrap();
+            delays.push(delay_ms(&store, "ada@school.edu", "10.0.0.2"));
+        }
+        assert_eq!(delays, [250, 500, 1000, 2000]);
+
+        record_failure(&store, "ada@school.edu", IP).unwrap();
+        assert!(is_locked(&store, "ada@school.edu", "10.0.0.2"));
+        assert!(!is_locked(&store, "bob@school.edu", "10.0.0.2"));
+    }
+
+    #[test]
+    fn addresses_lock_across_accounts_and_survive_a_success() {
+        let store = sqlite::memory_store();
+
+        for n in 1..MAX_IP_FAILURES {
+            record_failure(&store, &format!("user{}@school.edu", n), IP).unwrap();
+        }
+        assert_eq!(delay_ms(&store, "new@school.edu", IP), MAX_DELAY_MS as u128);
+        assert_eq!(delay_ms(&store, "new@school.edu", "10.0.0.2"), 0);
+
+        record_success(&store, "user1@school.edu").unwrap();
+        record_failure(&store, "user1@school.edu", IP).unwrap();
+        assert!(is_locked(&store, "new@school.edu", IP));
+        assert_eq!(delay_ms(&store, "user1@school.edu", "10.0.0.2"), 250);
+    }
+
+    #[test]
+    fn clear_lifts_a_lock_for_its_scope_only() {
+        let store = sqlite::memory_store();
+        for _ in 0..MAX_ACCOUNT_FAILURES {
+            record_failure(&store, "ada@school.edu", IP).unwrap();
+        }
+        let entries = list(&store).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries
+            .iter()
+            .any(|e| e.scope == SCOPE_ACCOUNT && e.subject == "ada@school.edu" && e.locked));
+
+        assert!(matches!(
+            clear(&store, "user", "ada@school.edu"),
+            Err(AppError::Validation(_))
+        ));
+        assert!(clear(&store, SCOPE_ACCOUNT, " ADA@school.edu ").unwrap());
+        assert!(!clear(&store, SCOPE_ACCOUNT, "ada@school.edu").unwrap());
+        assert!(!is_locked(&store, "ada@school.edu", "10.0.0.2"));
+        assert_eq!(delay_ms(&store, "ada@school.edu", IP), 4000);
+
+        assert!(clear(&store, SCOPE_IP, IP).unwrap());
+        assert_eq!(delay_ms(&store, "ada@school.edu", IP), 0);
+        assert!(list(&store).unwrap().is_empty());
+    }
+}
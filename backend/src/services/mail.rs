@@ -0,0 +1,127 @@
+//! Outgoing email, delivered by the transport chosen in the `[mail]`
+//! section of the configuration.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use uuid::Uuid;
+
+use crate::config::{self, MailConfig, MailTransportKind};
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait MailTransport {
+    fn send(&self, email: &Email) -> Result<(), String>;
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message, String> {
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|e| format!("Invalid recipient address: {}", e))?;
+
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(email.subject.clone())
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())
+        .map_err(|e| format!("Failed to build email: {}", e))
+}
+
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: SmtpTransport,
+}
+
+impl SmtpMailer {
+    fn new(from: Mailbox, config: &MailConfig) -> Result<Self, String> {
+        let host = config
+            .smtp_host
+            .as_deref()
+            .ok_or_else(|| "mail.smtp_host is not set".to_string())?;
+        let mut builder =
+            SmtpTransport::relay(host).map_err(|e| format!("Invalid SMTP host: {}", e))?;
+
+        if let Some(port) = config.smtp_port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(SmtpMailer {
+            from,
+            transport: builder.build(),
+        })
+    }
+}
+
+impl MailTransport for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        let message = build_message(&self.from, email)?;
+        self.transport
+            .send(&message)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send email: {}", e))
+    }
+}
+
+pub struct FileMailer {
+    from: Mailbox,
+    dir: PathBuf,
+}
+
+impl MailTransport for FileMailer {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        let message = build_message(&self.from, email)?;
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create mail directory: {}", e))?;
+
+        let path = self.dir.join(format!("{}.eml", Uuid::new_v4()));
+        fs::write(&path, message.formatted())
+            .map_err(|e| format!("Failed to write email to {}: {}", path.display(), e))
+    }
+}
+
+pub struct ConsoleMailer {
+    from: Mailbox,
+}
+
+impl MailTransport for ConsoleMailer {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        let message = build_message(&self.from, email)?;
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(&message.formatted())
+            .and_then(|()| stdout.write_all(b"\n"))
+            .map_err(|e| format!("Failed to print email: {}", e))
+    }
+}
+
+/// Builds the configured transport.
+pub fn transport() -> Result<Box<dyn MailTransport>, String> {
+    let config = &config::get().mail;
+    let from: Mailbox = config
+        .from
+        .parse()
+        .map_err(|e| format!("Invalid mail.from: {}", e))?;
+
+    Ok(match config.transport {
+        MailTransportKind::Smtp => Box::new(SmtpMailer::new(from, config)?),
+        MailTransportKind::File => Box::new(FileMailer {
+            from,
+            dir: PathBuf::from(&config.dir),
+        }),
+        MailTransportKind::Console => Box::new(ConsoleMailer { from }),
+    })
+}
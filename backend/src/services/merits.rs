@@ -0,0 +1,119 @@
+//! Awarding merits. Their points offset demerits in the net totals of
+//! [`ReportService`](super::reports::ReportService).
+
+use crate::database::repository::{CategoryOption, MeritRecord, NewMerit, Store};
+use crate::error::AppError;
+use crate::models::NewMeritRecord;
+
+pub struct MeritService<'a> {
+    store: &'a dyn Store,
+}
+
+impl<'a> MeritService<'a> {
+    pub fn new(store: &'a dyn Store) -> Self {
+        MeritService { store }
+    }
+
+    pub fn categories(&self) -> Result<Vec<CategoryOption>, AppError> {
+        Ok(self.store.list_merit_categories()?)
+    }
+
+    fn teacher_id(&self, user_id: i32) -> Result<i32, AppError> {
+        self.store
+            .find_teacher_id(user_id)?
+            .ok_or_else(|| AppError::Forbidden("No teacher record for this account".to_string()))
+    }
+
+    /// Records a merit awarded by the teacher signed in as `user_id` and
+    /// returns its id.
+    pub fn award(&self, user_id: i32, merit: &NewMeritRecord) -> Result<i32, AppError> {
+        let teacher_id = self.teacher_id(user_id)?;
+        if merit.points < 1 {
+            return Err(AppError::invalid("points", "Points must be at least 1"));
+        }
+        if !self.store.student_exists(merit.student_id)? {
+            return Err(AppError::invalid("student_id", "Student not found"));
+        }
+        if !self.store.merit_category_exists(merit.category_id)? {
+            return Err(AppError::invalid("category_id", "Category not found"));
+        }
+
+        Ok(self.store.add_merit(&NewMerit {
+            student_id: merit.student_id,
+            teacher_id,
+            category_id: merit.category_id,
+            points: merit.points,
+            description: merit.description.clone(),
+        })?)
+    }
+
+    /// Merits awarded by the teacher signed in as `user_id`.
+    pub fn awarded_by(&self, user_id: i32) -> Result<Vec<MeritRecord>, AppError> {
+        let teacher_id = self.teacher_id(user_id)?;
+        Ok(self.store.teacher_merits(teacher_id)?)
+    }
+
+    /// Merits of the student signed in as `user_id`.
+    pub fn own(&self, user_id: i32) -> Result<Vec<MeritRecord>, AppError> {
+        let student = self
+            .store
+            .student_info(user_id)?
+            .ok_or_else(|| AppError::NotFound("Student not found".to_string()))?;
+        Ok(self.store.student_merits(student.student_id)?)
+    }
+
+    /// Merits of every child of the parent signed in as `user_id`.
+    pub fn of_children(&self, user_id: i32) -> Result<Vec<MeritRecord>, AppError> {
+        let parent_id = self
+            .store
+            .find_parent_id(user_id)?
+            .ok_or_else(|| AppError::NotFound("Parent not found".to_string()))?;
+        Ok(self.store.children_merits(parent_id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite;
+    use crate::database::sqlite::fixtures::{parent, student, teacher};
+
+    fn merit(student_id: i32, category_id: i32, points: i32) -> NewMeritRecord {
+        NewMeritRecord {
+            student_id,
+            category_id,
+            points,
+            description: "Helped a classmate".to_string(),
+        }
+    }
+
+    #[test]
+    fn teachers_award_merits_that_students_and_parents_see() {
+        let store = sqlite::memory_store();
+        let merits = MeritService::new(&store);
+        let teacher = teacher(&store, "teacher");
+        let (student_user, student_id) = student(&store, "jane");
+        let (parent_user, _) = parent(&store, "pat", &[student_id]);
+
+        assert!(matches!(
+            merits.award(student_user, &merit(student_id, 1, 1)),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            merits.award(teacher, &merit(student_id, 1, 0)),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            merits.award(teacher, &merit(student_id, 99, 1)),
+            Err(AppError::Validation(_))
+        ));
+
+        let merit_id = merits.award(teacher, &merit(student_id, 2, 2)).unwrap();
+        let own = merits.own(student_user).unwrap();
+        assert_eq!(own.len(), 1);
+        assert_eq!(own[0].merit_id, merit_id);
+        assert_eq!(own[0].category_name, "Academic Excellence");
+        assert_eq!(merits.of_children(parent_user).unwrap().len(), 1);
+        assert_eq!(merits.awarded_by(teacher).unwrap().len(), 1);
+    }
+}
@@ -1 +1,16 @@
+pub mod appeals;
 pub mod auth;
+pub mod demerits;
+pub mod escalations;
+pub mod import;
+pub mod lockout;
+pub mod mail;
+pub mod merits;
+pub mod password;
+pub mod registration;
+pub mod reports;
+pub mod secret;
+pub mod session;
+pub mod two_factor;
+pub mod users;
+pub mod verification;
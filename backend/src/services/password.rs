@@ -0,0 +1,225 @@
+use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::{Duration, Utc};
+
+use crate::database::repository::{NewResetToken, Store};
+use crate::error::AppError;
+use crate::services::secret;
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const RESET_TOKEN_LIFETIME_HOURS: i64 = 24;
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A reset token to hand to the user out of band. Only its hash is stored.
+pub struct IssuedResetToken {
+    pub token: String,
+    pub expires_at: String,
+}
+
+/// Checks the strength of a new password sent in the request field `field`.
+pub fn validate_new_password(field: &str, password: &str) -> Result<(), AppError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::invalid(
+            field,
+            format!(
+                "Password must be at least {} characters",
+                MIN_PASSWORD_LENGTH
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn hash_password(password: &str) -> Result<String, AppError> {
+    hash(password, DEFAULT_COST)
+        .map_err(|e| AppError::Internal(format!("Password hashing error: {}", e)))
+}
+
+/// Stores a new password, sets or clears the forced-change flag and signs the
+/// user out of every session.
+fn set_password(
+    store: &dyn Store,
+    user_id: i32,
+    new_password: &str,
+    must_change: bool,
+) -> Result<(), AppError> {
+    if !store.set_password(user_id, &hash_password(new_password)?, must_change)? {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+    Ok(())
+}
+
+pub fn change_password(
+    store: &dyn Store,
+    user_id: i32,
+    current_password: &str,
+    new_password: &str,
+) -> Result<(), AppError> {
+    let account = store
+        .sign_in_account(user_id)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let is_valid = verify(current_password, &account.password_hash)
+        .map_err(|e| AppError::Internal(format!("Password verification error: {}", e)))?;
+    if !is_valid {
+        return Err(AppError::invalid(
+            "current_password",
+            "Current password is incorrect",
+        ));
+    }
+
+    validate_new_password("new_password", new_password)?;
+    if current_password == new_password {
+        return Err(AppError::invalid(
+            "new_password",
+            "New password must differ from the current one",
+        ));
+    }
+
+    set_password(store, user_id, new_password, false)
+}
+
+/// Replaces a user's password with one chosen by an administrator, which
+/// must be changed at the next login, and signs the user out everywhere.
+pub fn set_temporary_password(
+    store: &dyn Store,
+    user_id: i32,
+    new_password: &str,
+) -> Result<(), AppError> {
+    validate_new_password("password", new_password)?;
+    set_password(store, user_id, new_password, true)
+}
+
+/// Creates a single-use reset token for `user_id`, replacing any earlier
+/// token that has not been used yet.
+pub fn issue_reset_token(
+    store: &dyn Store,
+    user_id: i32,
+    issued_by: i32,
+) -> Result<IssuedResetToken, AppError> {
+    if store.find_role(user_id)?.is_none() {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    let token = secret::generate(32);
+    let expires_at = (Utc::now() + Duration::hours(RESET_TOKEN_LIFETIME_HOURS))
+        .format(TIMESTAMP_FORMAT)
+        .to_string();
+
+    store.add_reset_token(&NewResetToken {
+        token_hash: secret::hash(&token),
+        user_id,
+        issued_by,
+        expires_at: expires_at.clone(),
+    })?;
+
+    Ok(IssuedResetToken { token, expires_at })
+}
+
+/// Consumes a reset token and sets the new password.
+pub fn reset_password(store: &dyn Store, token: &str, new_password: &str) -> Result<(), AppError> {
+    validate_new_password("new_password", new_password)?;
+
+    store
+        .redeem_reset_token(&secret::hash(token), &hash_password(new_password)?)?
+        .map(|_| ())
+        .ok_or_else(|| AppError::InvalidToken("Reset token is invalid or has expired".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::{CredentialRepository, RoleDetails};
+    use crate::database::sqlite::{self, fixtures};
+    use crate::services::session;
+
+    fn invalid_token() -> AppError {
+        AppError::InvalidToken("Reset token is invalid or has expired".to_string())
+    }
+
+    fn password_hash(store: &dyn Store, user_id: i32) -> String {
+        store
+            .sign_in_account(user_id)
+            .unwrap()
+            .unwrap()
+            .password_hash
+    }
+
+    #[test]
+    fn reset_tokens_work_once_and_replace_earlier_ones() {
+        let store = sqlite::memory_store();
+        let user_id = fixtures::teacher(&store, "ada");
+        let admin = fixtures::user(&store, "admin", RoleDetails::Admin);
+        let session = session::open_session(&store, user_id, Duration::days(1))
+            .unwrap()
+            .session_id;
+
+        let replaced = issue_reset_token(&store, user_id, admin).unwrap();
+        let issued = issue_reset_token(&store, user_id, admin).unwrap();
+        assert_eq!(
+            reset_password(&store, &replaced.token, "new password").err(),
+            Some(invalid_token())
+        );
+        assert!(matches!(
+            reset_password(&store, &issued.token, "short"),
+            Err(AppError::Validation(_))
+        ));
+
+        reset_password(&store, &issued.token, "new password").unwrap();
+        assert!(verify("new password", &password_hash(&store, user_id)).unwrap());
+        assert!(session::active_session_user(&store, &session)
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            reset_password(&store, &issued.token, "other password").err(),
+            Some(invalid_token())
+        );
+        assert!(matches!(
+            issue_reset_token(&store, user_id + 100, admin),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn expired_reset_tokens_are_rejected() {
+        let store = sqlite::memory_store();
+        let user_id = fixtures::teacher(&store, "ada");
+        let issued = issue_reset_token(&store, user_id, user_id).unwrap();
+        store
+            .connection()
+            .execute(
+                "UPDATE password_resets SET expires_at = datetime('now', '-1 minute')",
+                [],
+            )
+            .unwrap();
+
+        assert_eq!(
+            reset_password(&store, &issued.token, "new password").err(),
+            Some(invalid_token())
+        );
+        assert_eq!(password_hash(&store, user_id), "hash");
+    }
+
+    #[test]
+    fn changing_a_password_needs_the_current_one() {
+        let store = sqlite::memory_store();
+        let user_id = fixtures::teacher(&store, "ada");
+        store
+            .set_password(user_id, &hash("old password", 4).unwrap(), true)
+            .unwrap();
+
+        assert_eq!(
+            change_password(&store, user_id, "wrong password", "new password"),
+            Err(AppError::invalid(
+                "current_password",
+                "Current password is incorrect"
+            ))
+        );
+        assert!(matches!(
+            change_password(&store, user_id, "old password", "old password"),
+            Err(AppError::Validation(_))
+        ));
+        change_password(&store, user_id, "old password", "new password").unwrap();
+        let account = store.sign_in_account(user_id).unwrap().unwrap();
+        assert!(!account.must_change_password);
+    }
+}
@@ -0,0 +1,343 @@
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::database::repository::{InviteSummary, NewInviteCode, Store};
+use crate::error::AppError;
+use crate::models::FieldError;
+use crate::services::secret;
+
+const DEFAULT_INVITE_LIFETIME_DAYS: i64 = 14;
+const INVITE_CODE_LENGTH: usize = 12;
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const INVITE_REQUIRED: &str = "Registration is by invitation only";
+const DOMAIN_NOT_ALLOWED: &str = "Registration is not open to this email domain";
+pub(crate) const INVALID_INVITE: &str = "Invite code is invalid or has expired";
+
+/// Who may create a parent account through `/register`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    /// Anyone may register.
+    Open,
+    /// Only addresses in the allowed domains, or holders of an invite code.
+    Domain,
+    /// Only holders of an invite code.
+    Invite,
+}
+
+impl FromStr for RegistrationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "open" => Ok(RegistrationMode::Open),
+            "domain" => Ok(RegistrationMode::Domain),
+            "invite" => Ok(RegistrationMode::Invite),
+            other => Err(format!("Unknown registration mode: {}", other)),
+        }
+    }
+}
+
+/// The `[registration]` section of the configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistrationPolicy {
+    pub mode: RegistrationMode,
+    /// Email domains that may register in domain mode, e.g. `school.edu`.
+    pub allowed_domains: Vec<String>,
+}
+
+impl Default for RegistrationPolicy {
+    fn default() -> Self {
+        RegistrationPolicy {
+            mode: RegistrationMode::Open,
+            allowed_domains: Vec::new(),
+        }
+    }
+}
+
+impl RegistrationPolicy {
+    fn allows_domain_of(&self, email: &str) -> bool {
+        email
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_lowercase())
+            .is_some_and(|domain| self.allowed_domains.contains(&domain))
+    }
+
+    /// Checks a registration that comes without an invite code.
+    pub fn check_uninvited(&self, email: &str) -> Result<(), AppError> {
+        match self.mode {
+            RegistrationMode::Open => Ok(()),
+            RegistrationMode::Domain if self.allows_domain_of(email) => Ok(()),
+            RegistrationMode::Domain => {
+                Err(AppError::RegistrationClosed(DOMAIN_NOT_ALLOWED.to_string()))
+            }
+            RegistrationMode::Invite => {
+                Err(AppError::RegistrationClosed(INVITE_REQUIRED.to_string()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewInvite {
+    /// Restricts the invite to one email address.
+    pub email: Option<String>,
+    /// Students the new parent is linked to on registration.
+    #[serde(default)]
+    pub student_ids: Vec<i32>,
+    pub max_uses: Option<i32>,
+    pub expires_in_days: Option<i64>,
+}
+
+/// A freshly created invite. The code is only returned here; the database
+/// keeps its hash.
+pub struct IssuedInvite {
+    pub invite_id: i32,
+    pub code: String,
+    pub expires_at: String,
+}
+
+pub fn create_invite(
+    store: &dyn Store,
+    created_by: i32,
+    invite: &NewInvite,
+) -> Result<IssuedInvite, AppError> {
+    let mut invalid = Vec::new();
+    let max_uses = invite.max_uses.unwrap_or(1);
+    if max_uses < 1 {
+        invalid.push(FieldError {
+            field: "max_uses".to_string(),
+            message: "max_uses must be at least 1".to_string(),
+        });
+    }
+    let lifetime_days = invite
+        .expires_in_days
+        .unwrap_or(DEFAULT_INVITE_LIFETIME_DAYS);
+    if lifetime_days < 1 {
+        invalid.push(FieldError {
+            field: "expires_in_days".to_string(),
+            message: "expires_in_days must be at least 1".to_string(),
+        });
+    }
+    if !invalid.is_empty() {
+        return Err(AppError::Validation(invalid));
+    }
+
+    for &student_id in &invite.student_ids {
+        if !store.student_exists(student_id)? {
+            return Err(AppError::invalid(
+                "student_ids",
+                format!("Student {} not found", student_id),
+            ));
+        }
+    }
+
+    let code = secret::generate(INVITE_CODE_LENGTH);
+    let expires_at = (Utc::now() + Duration::days(lifetime_days))
+        .format(TIMESTAMP_FORMAT)
+        .to_string();
+
+    let invite_id = store.create_invite(&NewInviteCode {
+        code_hash: secret::hash(&code),
+        created_by,
+        email: invite
+            .email
+            .as_ref()
+            .map(|email| email.trim().to_lowercase()),
+        max_uses,
+        expires_at: expires_at.clone(),
+        student_ids: invite.student_ids.clone(),
+    })?;
+
+    Ok(IssuedInvite {
+        invite_id,
+        code,
+        expires_at,
+    })
+}
+
+pub fn list_invites(store: &dyn Store) -> Result<Vec<InviteSummary>, AppError> {
+    Ok(store.list_invites()?)
+}
+
+/// Stops an invite from being used again. Returns whether it existed.
+pub fn revoke_invite(store: &dyn Store, invite_id: i32) -> Result<bool, AppError> {
+    Ok(store.revoke_invite(invite_id)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::{CredentialRepository, ParentRepository, RoleDetails};
+    use crate::database::sqlite::{self, fixtures};
+    use crate::models::RegisterRequest;
+    use crate::services::auth;
+
+    fn policy(mode: RegistrationMode) -> RegistrationPolicy {
+        RegistrationPolicy {
+            mode,
+            allowed_domains: vec!["school.edu".to_string()],
+        }
+    }
+
+    fn request(email: &str, invite_code: Option<&str>) -> RegisterRequest {
+        let username = email.split('@').next().unwrap();
+        RegisterRequest {
+            email: email.to_string(),
+            password: "long enough".to_string(),
+            username: Some(username.to_string()),
+            first_name: Some("Pat".to_string()),
+            last_name: Some("Doe".to_string()),
+            invite_code: invite_code.map(str::to_string),
+        }
+    }
+
+    fn invite(email: Option<&str>, student_ids: Vec<i32>, max_uses: Option<i32>) -> NewInvite {
+        NewInvite {
+            email: email.map(str::to_string),
+            student_ids,
+            max_uses,
+            expires_in_days: None,
+        }
+    }
+
+    fn invalid_invite() -> AppError {
+        AppError::InvalidToken(INVALID_INVITE.to_string())
+    }
+
+    /// Registers a parent with an invite code, under open registration.
+    fn register(store: &dyn Store, email: &str, code: &str) -> Result<i32, AppError> {
+        auth::create_parent_account(
+            store,
+            &policy(RegistrationMode::Open),
+            &request(email, Some(code)),
+        )
+    }
+
+    #[test]
+    fn uninvited_registrations_follow_the_mode() {
+        let closed = |e| e == AppError::RegistrationClosed(DOMAIN_NOT_ALLOWED.to_string());
+
+        assert!(policy(RegistrationMode::Open)
+            .check_uninvited("pat@gmail.com")
+            .is_ok());
+        assert!(policy(RegistrationMode::Domain)
+            .check_uninvited("pat@School.EDU")
+            .is_ok());
+        assert!(policy(RegistrationMode::Domain)
+            .check_uninvited("pat@gmail.com")
+            .is_err_and(closed));
+        assert!(policy(RegistrationMode::Domain)
+            .check_uninvited("pat@mail.school.edu")
+            .is_err_and(closed));
+        assert_eq!(
+            policy(RegistrationMode::Invite).check_uninvited("pat@school.edu"),
+            Err(AppError::RegistrationClosed(INVITE_REQUIRED.to_string()))
+        );
+        assert_eq!(
+            "Domain".parse::<RegistrationMode>(),
+            Ok(RegistrationMode::Domain)
+        );
+    }
+
+    #[test]
+    fn invites_bypass_the_policy_and_link_the_invited_students() {
+        let store = sqlite::memory_store();
+        let admin = fixtures::user(&store, "admin", RoleDetails::Admin);
+        let (_, jane) = fixtures::student(&store, "jane");
+        let (_, bob) = fixtures::student(&store, "bob");
+        let invite_only = policy(RegistrationMode::Invite);
+
+        let Err(AppError::Validation(fields)) = create_invite(
+            &store,
+            admin,
+            &NewInvite {
+                expires_in_days: Some(0),
+                ..invite(None, vec![], Some(0))
+            },
+        ) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(fields.len(), 2);
+        assert!(matches!(
+            create_invite(&store, admin, &invite(None, vec![jane + 100], None)),
+            Err(AppError::Validation(_))
+        ));
+
+        let issued = create_invite(
+            &store,
+            admin,
+            &invite(Some(" Pat@Gmail.com"), vec![jane, bob], None),
+        )
+        .unwrap();
+        assert_eq!(
+            auth::create_parent_account(
+                &store,
+                &invite_only,
+                &request("eve@gmail.com", Some(&issued.code))
+            ),
+            Err(invalid_invite())
+        );
+        assert!(matches!(
+            auth::create_parent_account(&store, &invite_only, &request("pat@gmail.com", None)),
+            Err(AppError::RegistrationClosed(_))
+        ));
+
+        let user_id = auth::create_parent_account(
+            &store,
+            &invite_only,
+            &request("pat@gmail.com", Some(&issued.code)),
+        )
+        .unwrap();
+        let parent_id = store.find_parent_id(user_id).unwrap().unwrap();
+        assert!(store.is_linked(parent_id, jane).unwrap());
+        assert!(store.is_linked(parent_id, bob).unwrap());
+
+        let account = store.sign_in_account(user_id).unwrap().unwrap();
+        assert_eq!(account.status, "pending");
+        assert_eq!(list_invites(&store).unwrap()[0].use_count, 1);
+        assert_eq!(
+            register(&store, "pat2@gmail.com", &issued.code),
+            Err(invalid_invite())
+        );
+    }
+
+    #[test]
+    fn revoked_and_expired_invites_are_rejected() {
+        let store = sqlite::memory_store();
+        let admin = fixtures::user(&store, "admin", RoleDetails::Admin);
+        let open = create_invite(&store, admin, &invite(None, vec![], Some(2))).unwrap();
+        let revoked = create_invite(&store, admin, &invite(None, vec![], None)).unwrap();
+        let expired = create_invite(&store, admin, &invite(None, vec![], None)).unwrap();
+
+        assert!(register(&store, "a@gmail.com", &format!(" {} ", open.code)).is_ok());
+        assert!(register(&store, "b@gmail.com", &open.code).is_ok());
+        assert_eq!(
+            register(&store, "c@gmail.com", &open.code),
+            Err(invalid_invite())
+        );
+
+        assert!(revoke_invite(&store, revoked.invite_id).unwrap());
+        assert!(!revoke_invite(&store, revoked.invite_id + 100).unwrap());
+        assert_eq!(
+            register(&store, "d@gmail.com", &revoked.code),
+            Err(invalid_invite())
+        );
+
+        store
+            .connection()
+            .execute(
+                "UPDATE invite_codes SET expires_at = datetime('now', '-1 minute')
+                 WHERE invite_id = ?1",
+                [expired.invite_id],
+            )
+            .unwrap();
+        assert_eq!(
+            register(&store, "e@gmail.com", &expired.code),
+            Err(invalid_invite())
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! Read-only reports, served over HTTP and exported by `demerit-admin`.
+
+use std::collections::HashMap;
+
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+
+use crate::config::{PointDecay, PointsConfig, SchoolConfig};
+use crate::database::repository::{
+    DemeritCategoryCount, DemeritHistoryRecord, DemeritTimePoint, GradeDemeritCount, Store,
+    StudentDemeritSummary, StudentScope,
+};
+use crate::error::AppError;
+use crate::models::{AdminUserData, StudentInfo};
+
+#[derive(Serialize)]
+pub struct DemeritDistribution {
+    pub categories: Vec<DemeritCategoryCount>,
+    pub grades: Vec<GradeDemeritCount>,
+}
+
+pub struct ReportService<'a> {
+    store: &'a dyn Store,
+    points: &'a PointsConfig,
+    school: &'a SchoolConfig,
+}
+
+impl<'a> ReportService<'a> {
+    pub fn new(store: &'a dyn Store, points: &'a PointsConfig, school: &'a SchoolConfig) -> Self {
+        ReportService {
+            store,
+            points,
+            school,
+        }
+    }
+
+    /// Demerit points of the students in `scope` that still count now under
+    /// the decay policy, rounded down. Students with none are left out.
+    pub fn effective_points(&self, scope: StudentScope) -> Result<HashMap<i32, i32>, AppError> {
+        let now = Utc::now().naive_utc();
+        let mut totals: HashMap<i32, f64> = HashMap::new();
+        for demerit in self.store.demerit_points(scope)? {
+            // A date that cannot be read is treated as new rather than dropped
+            let weight =
+                match NaiveDateTime::parse_from_str(&demerit.date_issued, "%Y-%m-%d %H:%M:%S") {
+                    Ok(issued) => self.points.weight(self.school, issued, now),
+                    Err(_) => 1.0,
+                };
+            *totals.entry(demerit.student_id).or_default() += f64::from(demerit.points) * weight;
+        }
+        Ok(totals
+            .into_iter()
+            .map(|(student_id, total)| (student_id, total.floor() as i32))
+            .collect())
+    }
+
+    /// Fills in effective and net points of the summaries of the students
+    /// in `scope`, so every summary applies the decay and merit policies the
+    /// same way.
+    fn with_policies(
+        &self,
+        scope: StudentScope,
+        mut summaries: Vec<StudentDemeritSummary>,
+    ) -> Result<Vec<StudentDemeritSummary>, AppError> {
+        if self.points.decay != PointDecay::None {
+            let effective = self.effective_points(scope)?;
+            for summary in &mut summaries {
+                summary.effective_points = effective.get(&summary.student_id).copied().unwrap_or(0);
+            }
+        }
+        for summary in &mut summaries {
+            summary.net_points = self
+                .points
+                .net_points(summary.effective_points, summary.merit_points);
+        }
+        Ok(summaries)
+    }
+
+    /// Every account for the admin user list, with the point totals of
+    /// students and the children of parents.
+    pub fn accounts(&self) -> Result<Vec<AdminUserData>, AppError> {
+        let mut summaries: HashMap<i32, StudentDemeritSummary> = self
+            .student_summaries()?
+            .into_iter()
+            .map(|summary| (summary.student_id, summary))
+            .collect();
+        let mut children: HashMap<i32, Vec<StudentInfo>> = HashMap::new();
+        for child in self.store.linked_children()? {
+            children
+                .entry(child.parent_user_id)
+                .or_default()
+                .push(StudentInfo {
+                    id: child.student_id,
+                    name: child.student_name,
+                });
+        }
+
+        Ok(self
+            .store
+            .list_accounts()?
+            .into_iter()
+            .map(|account| {
+                let summary = account.student_id.and_then(|id| summaries.remove(&id));
+                let points =
+                    |field: fn(&StudentDemeritSummary) -> i32| summary.as_ref().map_or(0, field);
+                AdminUserData {
+                    total_demerits: points(|s| s.total_points),
+                    effective_points: points(|s| s.effective_points),
+                    merit_points: points(|s| s.merit_points),
+                    net_points: points(|s| s.net_points),
+                    children: children.remove(&account.user_id).unwrap_or_default(),
+                    user_id: account.user_id,
+                    username: account.username,
+                    email: account.email,
+                    first_name: account.first_name,
+                    last_name: account.last_name,
+                    user_type: account.user_type,
+                    created_at: account.created_at,
+                    grade_level: account.grade_level,
+                    class_section: account.class_section,
+                }
+            })
+            .collect())
+    }
+
+    /// Every demerit, newest first.
+    pub fn history(&self) -> Result<Vec<DemeritHistoryRecord>, AppError> {
+        Ok(self.store.demerit_history()?)
+    }
+
+    /// Raw, effective, merit and net points per student, highest raw total
+    /// first.
+    pub fn student_summaries(&self) -> Result<Vec<StudentDemeritSummary>, AppError> {
+        self.with_policies(StudentScope::All, self.store.student_summaries()?)
+    }
+
+    /// Summary of the student signed in as `user_id`.
+    pub fn own(&self, user_id: i32) -> Result<StudentDemeritSummary, AppError> {
+        let not_found = || AppError::NotFound("Student not found".to_string());
+        let student = self.store.student_info(user_id)?.ok_or_else(not_found)?;
+        let summary = self
+            .store
+            .student_summary(student.student_id)?
+            .ok_or_else(not_found)?;
+        self.with_policies(StudentScope::Student(student.student_id), vec![summary])?
+            .pop()
+            .ok_or_else(not_found)
+    }
+
+    /// Demerit counts per category and per grade level.
+    pub fn distribution(&self) -> Result<DemeritDistribution, AppError> {
+        Ok(DemeritDistribution {
+            categories: self.store.demerits_by_category()?,
+            grades: self.store.demerits_by_grade()?,
+        })
+    }
+
+    pub fn trend(&self) -> Result<Vec<DemeritTimePoint>, AppError> {
+        Ok(self.store.demerit_trend()?)
+    }
+
+    /// Summaries of the children of the parent signed in as `user_id`.
+    pub fn children(&self, user_id: i32) -> Result<Vec<StudentDemeritSummary>, AppError> {
+        let parent_id = self
+            .store
+            .find_parent_id(user_id)?
+            .ok_or_else(|| AppError::NotFound("Parent not found".to_string()))?;
+        self.with_policies(
+            StudentScope::Children(parent_id),
+            self.store.children_summaries(parent_id)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::RoleDetails;
+    use crate::database::sqlite;
+    use crate::database::sqlite::fixtures::{demerit, merit, parent, student, teacher, user};
+
+    #[test]
+    fn children_are_only_reported_to_parents() {
+        let store = sqlite::memory_store();
+        let points = PointsConfig::default();
+        let school = SchoolConfig::default();
+        let reports = ReportService::new(&store, &points, &school);
+        let user_id = user(&store, "pat", RoleDetails::Admin);
+
+        assert_eq!(
+            reports.children(user_id).err(),
+            Some(AppError::NotFound("Parent not found".to_string()))
+        );
+        assert!(reports.distribution().unwrap().categories.is_empty());
+    }
+
+    #[test]
+    fn merits_offset_demerits_by_policy() {
+        let store = sqlite::memory_store();
+        let teacher_user = teacher(&store, "teacher");
+        let (student_user, student_id) = student(&store, "jane");
+        demerit(&store, teacher_user, student_id, 5);
+        merit(&store, teacher_user, student_id, 7);
+
+        let school = SchoolConfig::default();
+        let three_per_point = PointsConfig::default();
+        let reports = ReportService::new(&store, &three_per_point, &school);
+        let own = reports.own(student_user).unwrap();
+        assert_eq!(
+            (own.total_points, own.merit_points, own.net_points),
+            (5, 7, 3)
+        );
+        assert_eq!(reports.student_summaries().unwrap()[0].net_points, 3);
+
+        let no_offset = PointsConfig {
+            merits_per_demerit_point: 0,
+            ..PointsConfig::default()
+        };
+        let reports = ReportService::new(&store, &no_offset, &school);
+        assert_eq!(reports.own(student_user).unwrap().net_points, 5);
+        assert!(matches!(
+            reports.own(teacher_user),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn admin_accounts_use_the_point_policies() {
+        let store = sqlite::memory_store();
+        let teacher_user = teacher(&store, "teacher");
+        let (student_user, student_id) = student(&store, "jane");
+        let (parent_user, _) = parent(&store, "pat", &[student_id]);
+        demerit(&store, teacher_user, student_id, 5);
+        merit(&store, teacher_user, student_id, 7);
+
+        let points = PointsConfig::default();
+        let school = SchoolConfig::default();
+        let accounts = ReportService::new(&store, &points, &school)
+            .accounts()
+            .unwrap();
+        assert_eq!(accounts.len(), 3);
+        let jane = accounts.iter().find(|a| a.user_id == student_user).unwrap();
+        assert_eq!(
+            (jane.total_demerits, jane.merit_points, jane.net_points),
+            (5, 7, 3)
+        );
+        assert_eq!(jane.effective_points, 5);
+        let pat = accounts.iter().find(|a| a.user_id == parent_user).unwrap();
+        assert_eq!(pat.children.len(), 1);
+        assert_eq!(pat.children[0].id, student_id);
+        assert_eq!(pat.net_points, 0);
+    }
+
+    #[test]
+    fn decay_lowers_effective_points_but_keeps_the_raw_total() {
+        let store = sqlite::memory_store();
+        let teacher_user = teacher(&store, "teacher");
+        let (student_user, student_id) = student(&store, "jane");
+        demerit(&store, teacher_user, student_id, 4);
+        demerit(&store, teacher_user, student_id, 2);
+        store
+            .connection()
+            .execute(
+                "UPDATE demerit_records SET date_issued = datetime('now', '-400 days')
+                 WHERE points = 4",
+                [],
+            )
+            .unwrap();
+        let days_ago = |days| {
+            (Utc::now() - chrono::Duration::days(days))
+                .format("%Y-%m-%d")
+                .to_string()
+        };
+
+        let expiry = PointsConfig {
+            decay: PointDecay::Expiry,
+            expiry_days: 365,
+            ..PointsConfig::default()
+        };
+        let school = SchoolConfig::default();
+        let own = ReportService::new(&store, &expiry, &school)
+            .own(student_user)
+            .unwrap();
+        assert_eq!(
+            (own.total_points, own.effective_points, own.net_points),
+            (6, 2, 2)
+        );
+
+        // The old demerit has seen one new term start, the recent one none
+        let halving = PointsConfig {
+            decay: PointDecay::TermHalving,
+            ..PointsConfig::default()
+        };
+        let school = SchoolConfig {
+            term_starts: vec![days_ago(500), days_ago(100)],
+        };
+        let reports = ReportService::new(&store, &halving, &school);
+        let summaries = reports.student_summaries().unwrap();
+        assert_eq!(
+            (summaries[0].total_points, summaries[0].effective_points),
+            (6, 4)
+        );
+        assert_eq!(
+            reports
+                .effective_points(StudentScope::Student(student_id))
+                .unwrap()[&student_id],
+            4
+        );
+    }
+}
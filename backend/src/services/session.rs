@@ -0,0 +1,186 @@
+use chrono::{Duration, Utc};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config;
+use crate::database::repository::{NewSession, SessionUser, Store};
+use crate::error::AppError;
+use crate::services::secret;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A freshly created or rotated session, with the refresh token to hand back
+/// to the client. Only a hash of the token is stored.
+pub struct IssuedSession {
+    pub session_id: String,
+    pub user_id: i32,
+    pub refresh_token: String,
+}
+
+/// Refresh tokens have the form `<session_id>.<secret>`.
+fn split_refresh_token(token: &str) -> Result<(&str, &str), AppError> {
+    token
+        .split_once('.')
+        .ok_or_else(|| AppError::Unauthenticated("Malformed refresh token".to_string()))
+}
+
+pub fn create_session(store: &dyn Store, user_id: i32) -> Result<IssuedSession, AppError> {
+    open_session(
+        store,
+        user_id,
+        Duration::days(config::get().auth.session_days),
+    )
+}
+
+/// Starts a session that ends after `lifetime` unless it is revoked first.
+pub(crate) fn open_session(
+    store: &dyn Store,
+    user_id: i32,
+    lifetime: Duration,
+) -> Result<IssuedSession, AppError> {
+    let session_id = Uuid::new_v4().to_string();
+    let refresh_secret = secret::generate(48);
+
+    store.create_session(&NewSession {
+        session_id: session_id.clone(),
+        user_id,
+        refresh_token_hash: secret::hash(&refresh_secret),
+        expires_at: (Utc::now() + lifetime).format(TIMESTAMP_FORMAT).to_string(),
+    })?;
+
+    Ok(IssuedSession {
+        refresh_token: format!("{}.{}", session_id, refresh_secret),
+        session_id,
+        user_id,
+    })
+}
+
+/// Exchanges a refresh token for a new one. Presenting a token that has
+/// already been rotated revokes the whole session, since it means the token
+/// was copied.
+pub fn rotate_refresh_token(store: &dyn Store, token: &str) -> Result<IssuedSession, AppError> {
+    let (session_id, presented) = split_refresh_token(token)?;
+
+    let user_id = store
+        .active_session_owner(session_id)?
+        .ok_or_else(|| AppError::Unauthenticated("Session has expired".to_string()))?;
+
+    // Only the current token matches, so of two requests racing with the
+    // same token exactly one succeeds
+    let new_secret = secret::generate(48);
+    let rotated = store.rotate_refresh_token(
+        session_id,
+        &secret::hash(presented),
+        &secret::hash(&new_secret),
+    )?;
+
+    if !rotated {
+        warn!(
+            user_id,
+            session_id, "Refresh token reused, revoking session"
+        );
+        store.revoke_session(session_id)?;
+        return Err(AppError::Unauthenticated(
+            "Refresh token has already been used".to_string(),
+        ));
+    }
+
+    Ok(IssuedSession {
+        session_id: session_id.to_string(),
+        user_id,
+        refresh_token: format!("{}.{}", session_id, new_secret),
+    })
+}
+
+/// Returns the account of `session_id`, or `None` once the session has been
+/// revoked or has expired.
+pub fn active_session_user(
+    store: &dyn Store,
+    session_id: &str,
+) -> Result<Option<SessionUser>, AppError> {
+    Ok(store.session_user(session_id)?)
+}
+
+pub fn revoke_session(store: &dyn Store, session_id: &str) -> Result<(), AppError> {
+    Ok(store.revoke_session(session_id)?)
+}
+
+/// Revokes every open session of a user and returns how many were closed.
+pub fn revoke_all_sessions(store: &dyn Store, user_id: i32) -> Result<usize, AppError> {
+    Ok(store.revoke_user_sessions(user_id)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite::{self, fixtures};
+
+    const MONTH: Duration = Duration::days(30);
+
+    fn is_active(store: &dyn Store, session_id: &str) -> bool {
+        active_session_user(store, session_id).unwrap().is_some()
+    }
+
+    #[test]
+    fn refresh_tokens_rotate_and_a_reused_token_ends_the_session() {
+        let store = sqlite::memory_store();
+        let user_id = fixtures::teacher(&store, "ada");
+        let session = open_session(&store, user_id, MONTH).unwrap();
+
+        let rotated = rotate_refresh_token(&store, &session.refresh_token).unwrap();
+        assert_eq!(
+            (rotated.session_id.as_str(), rotated.user_id),
+            (session.session_id.as_str(), user_id)
+        );
+        assert_ne!(rotated.refresh_token, session.refresh_token);
+        let latest = rotate_refresh_token(&store, &rotated.refresh_token).unwrap();
+        assert!(is_active(&store, &session.session_id));
+
+        assert_eq!(
+            rotate_refresh_token(&store, &session.refresh_token).err(),
+            Some(AppError::Unauthenticated(
+                "Refresh token has already been used".to_string()
+            ))
+        );
+        assert!(!is_active(&store, &session.session_id));
+        assert!(rotate_refresh_token(&store, &latest.refresh_token).is_err());
+        assert!(rotate_refresh_token(&store, "no-separator").is_err());
+    }
+
+    #[test]
+    fn logging_out_everywhere_leaves_other_users_signed_in() {
+        let store = sqlite::memory_store();
+        let ada = fixtures::teacher(&store, "ada");
+        let bob = fixtures::teacher(&store, "bob");
+        let laptop = open_session(&store, ada, MONTH).unwrap();
+        let phone = open_session(&store, ada, MONTH).unwrap();
+        let other = open_session(&store, bob, MONTH).unwrap();
+        revoke_session(&store, &phone.session_id).unwrap();
+
+        assert_eq!(revoke_all_sessions(&store, ada).unwrap(), 1);
+        assert!(!is_active(&store, &laptop.session_id));
+        assert!(rotate_refresh_token(&store, &laptop.refresh_token).is_err());
+        assert_eq!(
+            active_session_user(&store, &other.session_id).unwrap(),
+            Some(SessionUser {
+                user_id: bob,
+                user_type: "teacher".to_string(),
+                must_change_password: false,
+                must_enroll_two_factor: false,
+            })
+        );
+    }
+
+    #[test]
+    fn expired_sessions_are_rejected() {
+        let store = sqlite::memory_store();
+        let user_id = fixtures::teacher(&store, "ada");
+        let session = open_session(&store, user_id, Duration::days(-1)).unwrap();
+
+        assert!(!is_active(&store, &session.session_id));
+        assert_eq!(
+            rotate_refresh_token(&store, &session.refresh_token).err(),
+            Some(AppError::Unauthenticated("Session has expired".to_string()))
+        );
+    }
+}
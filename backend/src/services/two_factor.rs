@@ -0,0 +1,323 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::services::{auth, secret};
+
+const ISSUER: &str = "Demerit System";
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+const CHALLENGE_LIFETIME_MINUTES: i64 = 5;
+const CHALLENGE_PURPOSE: &str = "two_factor";
+
+/// Claims of the short-lived token handed out between the password check and
+/// the second login step. It cannot be used as an access token.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChallengeClaims {
+    sub: i32,
+    purpose: String,
+    exp: i64,
+}
+
+/// A new, not yet confirmed TOTP secret for the user's authenticator app.
+pub struct Enrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Two-factor authentication is offered to the roles that can change records
+/// of other users.
+pub fn supports_role(user_type: &str) -> bool {
+    matches!(user_type, "admin" | "teacher")
+}
+
+fn totp(secret: &str, account: &str) -> Result<TOTP, AppError> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| AppError::Internal(format!("Invalid TOTP secret: {:?}", e)))?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP_SECONDS,
+        bytes,
+        Some(ISSUER.to_string()),
+        account.to_string(),
+    )
+    .map_err(|e| AppError::Internal(format!("Invalid TOTP parameters: {}", e)))
+}
+
+/// Codes may be typed with spaces or dashes.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect()
+}
+
+fn user_email(store: &dyn Store, user_id: i32) -> Result<String, AppError> {
+    store
+        .sign_in_account(user_id)?
+        .map(|account| account.email)
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))
+}
+
+pub fn is_enabled(store: &dyn Store, user_id: i32) -> Result<bool, AppError> {
+    Ok(store.totp_secret(user_id, false)?.is_some())
+}
+
+/// Whether an admin has made two-factor authentication mandatory for the
+/// user.
+pub fn is_required(store: &dyn Store, user_id: i32) -> Result<bool, AppError> {
+    store
+        .sign_in_account(user_id)?
+        .map(|account| account.two_factor_required)
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))
+}
+
+/// Creates a fresh secret for the user, replacing any unconfirmed one. The
+/// secret only protects logins once a code from it has been confirmed.
+pub fn begin_enrollment(store: &dyn Store, user_id: i32) -> Result<Enrollment, AppError> {
+    if is_enabled(store, user_id)? {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let email = user_email(store, user_id)?;
+    let secret = match Secret::generate_secret().to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => unreachable!("to_encoded always returns an encoded secret"),
+    };
+    let otpauth_uri = totp(&secret, &email)?.get_url();
+
+    store.save_totp_secret(user_id, &secret)?;
+
+    Ok(Enrollment {
+        secret,
+        otpauth_uri,
+    })
+}
+
+/// Checks a TOTP code against the user's secret, allowing one step of clock
+/// drift either way. A code is accepted at most once.
+fn verify_totp(
+    store: &dyn Store,
+    user_id: i32,
+    code: &str,
+    pending: bool,
+) -> Result<bool, AppError> {
+    let Some(stored) = store.totp_secret(user_id, pending)? else {
+        return Ok(false);
+    };
+    let totp = totp(&stored.secret, &user_email(store, user_id)?)?;
+
+    let current_step = Utc::now().timestamp() / STEP_SECONDS as i64;
+    let matched = (current_step - 1..=current_step + 1)
+        .filter(|step| stored.last_used_step.is_none_or(|last| *step > last))
+        .find(|step| totp.check(code, *step as u64 * STEP_SECONDS));
+
+    match matched {
+        Some(step) => Ok(store.use_totp_step(user_id, step)?),
+        None => Ok(false),
+    }
+}
+
+fn new_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| secret::generate(RECOVERY_CODE_LENGTH).to_lowercase())
+        .collect()
+}
+
+fn hashes(codes: &[String]) -> Vec<String> {
+    codes.iter().map(|code| secret::hash(code)).collect()
+}
+
+/// Replaces the user's recovery codes and returns the new ones. Only their
+/// hashes are stored.
+pub fn regenerate_recovery_codes(store: &dyn Store, user_id: i32) -> Result<Vec<String>, AppError> {
+    let codes = new_recovery_codes();
+    store.replace_recovery_codes(user_id, &hashes(&codes))?;
+    Ok(codes)
+}
+
+/// Turns on two-factor authentication once the user proves their app produces
+/// valid codes. Returns the initial recovery codes.
+pub fn confirm_enrollment(
+    store: &dyn Store,
+    user_id: i32,
+    code: &str,
+) -> Result<Vec<String>, AppError> {
+    if is_enabled(store, user_id)? {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+    if !verify_totp(store, user_id, &normalize_code(code), true)? {
+        return Err(AppError::invalid("code", AppError::InvalidCode.to_string()));
+    }
+
+    let codes = new_recovery_codes();
+    store.enable_totp(user_id, &hashes(&codes))?;
+    Ok(codes)
+}
+
+/// Accepts either a current TOTP code or an unused recovery code, which is
+/// consumed.
+pub fn verify_code(store: &dyn Store, user_id: i32, code: &str) -> Result<bool, AppError> {
+    let code = normalize_code(code);
+
+    if code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit()) {
+        return verify_totp(store, user_id, &code, false);
+    }
+
+    Ok(store.use_recovery_code(user_id, &secret::hash(&code.to_lowercase()))?)
+}
+
+/// Removes the user's secret and recovery codes.
+pub fn disable(store: &dyn Store, user_id: i32) -> Result<(), AppError> {
+    Ok(store.remove_two_factor(user_id)?)
+}
+
+/// Makes two-factor authentication mandatory (or optional again) for a user.
+pub fn set_required(store: &dyn Store, user_id: i32, required: bool) -> Result<(), AppError> {
+    let role = store
+        .find_role(user_id)?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    if !supports_role(role.as_str()) {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is only available to admin and teacher accounts".to_string(),
+        ));
+    }
+
+    if !store.set_two_factor_required(user_id, required)? {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+    Ok(())
+}
+
+/// Signs the token that lets `user_id` attempt the second login step.
+pub fn issue_challenge(user_id: i32) -> Result<String, AppError> {
+    challenge_token(user_id, auth::jwt_secret())
+}
+
+/// Returns the user a second-step challenge was issued to.
+pub fn verify_challenge(token: &str) -> Result<i32, AppError> {
+    challenge_user(token, auth::jwt_secret())
+}
+
+pub(crate) fn challenge_token(user_id: i32, secret: &[u8]) -> Result<String, AppError> {
+    let claims = ChallengeClaims {
+        sub: user_id,
+        purpose: CHALLENGE_PURPOSE.to_string(),
+        exp: (Utc::now() + Duration::minutes(CHALLENGE_LIFETIME_MINUTES)).timestamp(),
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret),
+    )
+    .map_err(|e| AppError::Internal(format!("Token signing error: {}", e)))
+}
+
+pub(crate) fn challenge_user(token: &str, secret: &[u8]) -> Result<i32, AppError> {
+    let claims = decode::<ChallengeClaims>(
+        token,
+        &DecodingKey::from_secret(secret),
+        &Validation::default(),
+    )
+    .map(|data| data.claims)
+    .map_err(|_| AppError::InvalidChallenge)?;
+
+    if claims.purpose != CHALLENGE_PURPOSE {
+        return Err(AppError::InvalidChallenge);
+    }
+    Ok(claims.sub)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite::{self, fixtures};
+
+    /// The code an authenticator app shows right now for `enrollment`.
+    fn current_code(enrollment: &Enrollment) -> String {
+        totp(&enrollment.secret, "ada@school.edu")
+            .unwrap()
+            .generate_current()
+            .unwrap()
+    }
+
+    #[test]
+    fn a_totp_code_is_accepted_only_once() {
+        let store = sqlite::memory_store();
+        let user_id = fixtures::teacher(&store, "ada");
+        let enrollment = begin_enrollment(&store, user_id).unwrap();
+        assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+        assert!(!is_enabled(&store, user_id).unwrap());
+        assert!(!verify_code(&store, user_id, &current_code(&enrollment)).unwrap());
+
+        assert!(matches!(
+            confirm_enrollment(&store, user_id, "000000"),
+            Err(AppError::Validation(_))
+        ));
+        let code = current_code(&enrollment);
+        confirm_enrollment(&store, user_id, &code).unwrap();
+        assert!(is_enabled(&store, user_id).unwrap());
+        assert!(matches!(
+            begin_enrollment(&store, user_id),
+            Err(AppError::Conflict(_))
+        ));
+
+        // The step used to confirm cannot be replayed to sign in
+        assert!(!verify_code(&store, user_id, &code).unwrap());
+    }
+
+    #[test]
+    fn recovery_codes_are_consumed() {
+        let store = sqlite::memory_store();
+        let user_id = fixtures::teacher(&store, "ada");
+        let other = fixtures::teacher(&store, "bob");
+        let enrollment = begin_enrollment(&store, user_id).unwrap();
+        let codes = confirm_enrollment(&store, user_id, &current_code(&enrollment)).unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+
+        let (head, tail) = codes[0].split_at(5);
+        let typed = format!("{}-{}", head, tail).to_uppercase();
+        assert!(!verify_code(&store, other, &typed).unwrap());
+        assert!(verify_code(&store, user_id, &typed).unwrap());
+        assert!(!verify_code(&store, user_id, &codes[0]).unwrap());
+        assert!(verify_code(&store, user_id, &codes[1]).unwrap());
+
+        let fresh = regenerate_recovery_codes(&store, user_id).unwrap();
+        assert!(!verify_code(&store, user_id, &codes[2]).unwrap());
+        assert!(verify_code(&store, user_id, &fresh[0]).unwrap());
+
+        disable(&store, user_id).unwrap();
+        assert!(!is_enabled(&store, user_id).unwrap());
+        assert!(!verify_code(&store, user_id, &fresh[1]).unwrap());
+    }
+
+    #[test]
+    fn only_staff_can_be_required_to_use_two_factor() {
+        let store = sqlite::memory_store();
+        let teacher = fixtures::teacher(&store, "ada");
+        let (student, _) = fixtures::student(&store, "jane");
+
+        set_required(&store, teacher, true).unwrap();
+        assert!(matches!(
+            set_required(&store, student, true),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            set_required(&store, teacher + 100, true),
+            Err(AppError::NotFound(_))
+        ));
+    }
+}
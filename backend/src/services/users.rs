@@ -0,0 +1,351 @@
+//! Account administration shared by the admin endpoints and `demerit-admin`.
+
+use std::collections::BTreeSet;
+
+use bcrypt::{hash, DEFAULT_COST};
+
+use crate::database::repository::{
+    AccountChanges, NewAccount, ParentOption, RoleDetails, Store, StudentInfo, StudentOption,
+};
+use crate::error::AppError;
+use crate::models::{AdminUserRecord, Role};
+use crate::services::password;
+
+pub struct NewUser {
+    pub username: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub password: String,
+    /// Whether the password must be changed at first login.
+    pub must_change_password: bool,
+    pub details: RoleDetails,
+}
+
+pub struct UserService<'a> {
+    store: &'a dyn Store,
+}
+
+impl<'a> UserService<'a> {
+    pub fn new(store: &'a dyn Store) -> Self {
+        UserService { store }
+    }
+
+    /// Creates an active account with its teacher, student or parent record,
+    /// and returns the new user id.
+    pub fn create_user(&self, user: NewUser) -> Result<i32, AppError> {
+        password::validate_new_password("password", &user.password)?;
+        if self.store.find_user_id(&user.username)?.is_some()
+            || self.store.find_user_id(&user.email)?.is_some()
+        {
+            return Err(AppError::Conflict(
+                "Username or email already exists".to_string(),
+            ));
+        }
+        let password_hash = hash(&user.password, DEFAULT_COST)
+            .map_err(|e| AppError::Internal(format!("Password hashing error: {}", e)))?;
+
+        Ok(self.store.create_user(&NewAccount {
+            username: user.username,
+            email: user.email,
+            password_hash,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            must_change_password: user.must_change_password,
+            details: user.details,
+        })?)
+    }
+
+    /// Looks up a user id by username or email.
+    pub fn find(&self, username_or_email: &str) -> Result<i32, AppError> {
+        self.store.find_user_id(username_or_email)?.ok_or_else(|| {
+            AppError::NotFound(format!(
+                "No user with username or email {}",
+                username_or_email
+            ))
+        })
+    }
+
+    pub fn change_role(&self, user_id: i32, role: &str) -> Result<(), AppError> {
+        let role: Role = role
+            .parse()
+            .map_err(|e: String| AppError::invalid("new_role", e))?;
+        if !self.store.set_role(user_id, role)? {
+            return Err(AppError::NotFound("User not found".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Applies an admin's edit of an account. A new role gets a fresh role
+    /// record: teachers without subject and department, students with the
+    /// grade and class from the edit.
+    pub fn update_account(&self, user: &AdminUserRecord) -> Result<(), AppError> {
+        let role: Role = user
+            .user_type
+            .parse()
+            .map_err(|e: String| AppError::invalid("user_type", e))?;
+        for name in [&user.username, &user.email] {
+            if self
+                .store
+                .find_user_id(name)?
+                .is_some_and(|id| id != user.user_id)
+            {
+                return Err(AppError::Conflict(
+                    "Username or email already exists".to_string(),
+                ));
+            }
+        }
+
+        let details = match role {
+            Role::Admin => RoleDetails::Admin,
+            Role::Parent => RoleDetails::Parent,
+            Role::Teacher => RoleDetails::Teacher {
+                subject: "Not Set".to_string(),
+                department: "Not Set".to_string(),
+            },
+            Role::Student => RoleDetails::Student {
+                grade_level: user.grade_level.unwrap_or(0),
+                class_section: user
+                    .class_section
+                    .clone()
+                    .unwrap_or_else(|| "Not Set".to_string()),
+            },
+        };
+        let updated = self.store.update_account(&AccountChanges {
+            user_id: user.user_id,
+            username: user.username.clone(),
+            email: user.email.clone(),
+            first_name: user.first_name.clone(),
+            last_name: user.last_name.clone(),
+            details,
+        })?;
+        if !updated {
+            return Err(AppError::NotFound("User not found".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn students(&self) -> Result<Vec<StudentOption>, AppError> {
+        Ok(self.store.list_students()?)
+    }
+
+    /// The student record of the student signed in as `user_id`.
+    pub fn student_info(&self, user_id: i32) -> Result<StudentInfo, AppError> {
+        self.store
+            .student_info(user_id)?
+            .ok_or_else(|| AppError::NotFound("Student not found".to_string()))
+    }
+
+    pub fn parents(&self) -> Result<Vec<ParentOption>, AppError> {
+        Ok(self.store.list_parents()?)
+    }
+
+    pub fn link_child(&self, parent_id: i32, student_id: i32) -> Result<(), AppError> {
+        if !self.store.student_exists(student_id)? {
+            return Err(AppError::invalid("student_id", "Student not found"));
+        }
+        if self.store.is_linked(parent_id, student_id)? {
+            return Err(AppError::Conflict(
+                "This parent-student relationship already exists".to_string(),
+            ));
+        }
+        Ok(self.store.link_student(parent_id, student_id)?)
+    }
+
+    /// Makes `student_ids` the only children of `parent_id` and returns how
+    /// many there are.
+    pub fn set_children(&self, parent_id: i32, student_ids: &[i32]) -> Result<usize, AppError> {
+        let student_ids: BTreeSet<i32> = student_ids.iter().copied().collect();
+        for &student_id in &student_ids {
+            if !self.store.student_exists(student_id)? {
+                return Err(AppError::invalid(
+                    "student_ids",
+                    format!("Student {} not found", student_id),
+                ));
+            }
+        }
+
+        let student_ids: Vec<i32> = student_ids.into_iter().collect();
+        self.store.replace_students(parent_id, &student_ids)?;
+        Ok(student_ids.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::params;
+
+    use super::*;
+    use crate::database::repository::{ParentRepository, UserRepository};
+    use crate::database::sqlite::{self, SqliteStore};
+
+    fn teacher(username: &str, email: &str) -> NewUser {
+        NewUser {
+            username: username.to_string(),
+            email: email.to_string(),
+            first_name: "Ada".to_string(),
+            last_name: "Lovelace".to_string(),
+            password: "correct horse".to_string(),
+            must_change_password: true,
+            details: RoleDetails::Teacher {
+                subject: "Math".to_string(),
+                department: "Science".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn creates_account_with_role_record() {
+        let store = sqlite::memory_store();
+        let users = UserService::new(&store);
+        let user_id = users.create_user(teacher("ada", "ada@school.edu")).unwrap();
+        assert_eq!(users.find("ada@school.edu").unwrap(), user_id);
+
+        let conn = store.connection();
+        let (user_type, must_change): (String, bool) = conn
+            .query_row(
+                "SELECT user_type, must_change_password FROM users WHERE user_id = ?1",
+                params![user_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        let subject: String = conn
+            .query_row(
+                "SELECT subject FROM teachers WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        assert_eq!(user_type, "teacher");
+        assert!(must_change);
+        assert_eq!(subject, "Math");
+    }
+
+    #[test]
+    fn rejects_duplicates_and_weak_passwords() {
+        let store = sqlite::memory_store();
+        let users = UserService::new(&store);
+        users.create_user(teacher("ada", "ada@school.edu")).unwrap();
+
+        assert!(matches!(
+            users.create_user(teacher("ada", "other@school.edu")),
+            Err(AppError::Conflict(_))
+        ));
+
+        let mut weak = teacher("grace", "grace@school.edu");
+        weak.password = "short".to_string();
+        assert!(matches!(
+            users.create_user(weak),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(users.find("grace"), Err(AppError::NotFound(_))));
+    }
+
+    fn parent_and_student(store: &SqliteStore) -> (i32, i32) {
+        let users = UserService::new(store);
+        let mut parent = teacher("pat", "pat@school.edu");
+        parent.details = RoleDetails::Parent;
+        let parent_user = users.create_user(parent).unwrap();
+        let mut student = teacher("sam", "sam@school.edu");
+        student.details = RoleDetails::Student {
+            grade_level: 7,
+            class_section: "A".to_string(),
+        };
+        let student_user = users.create_user(student).unwrap();
+        (
+            store.find_parent_id(parent_user).unwrap().unwrap(),
+            users.student_info(student_user).unwrap().student_id,
+        )
+    }
+
+    #[test]
+    fn links_children_once() {
+        let store = sqlite::memory_store();
+        let users = UserService::new(&store);
+        let (parent_id, student_id) = parent_and_student(&store);
+
+        users.link_child(parent_id, student_id).unwrap();
+        assert!(matches!(
+            users.link_child(parent_id, student_id),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            users.set_children(parent_id, &[student_id, student_id + 100]),
+            Err(AppError::Validation(_))
+        ));
+        assert_eq!(
+            users
+                .set_children(parent_id, &[student_id, student_id])
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn change_role_rejects_unknown_roles_and_users() {
+        let store = sqlite::memory_store();
+        let users = UserService::new(&store);
+        let user_id = users.create_user(teacher("ada", "ada@school.edu")).unwrap();
+
+        assert!(matches!(
+            users.change_role(user_id, "janitor"),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            users.change_role(user_id + 100, "admin"),
+            Err(AppError::NotFound(_))
+        ));
+        users.change_role(user_id, "admin").unwrap();
+    }
+
+    fn record(user_id: i32, username: &str, user_type: &str) -> AdminUserRecord {
+        AdminUserRecord {
+            user_id,
+            username: username.to_string(),
+            email: format!("{}@school.edu", username),
+            user_type: user_type.to_string(),
+            first_name: "Ada".to_string(),
+            last_name: "King".to_string(),
+            total_demerits: 0,
+            created_at: String::new(),
+            grade_level: Some(9),
+            class_section: None,
+            children: None,
+        }
+    }
+
+    #[test]
+    fn update_account_moves_role_records() {
+        let store = sqlite::memory_store();
+        let users = UserService::new(&store);
+        let ada = users.create_user(teacher("ada", "ada@school.edu")).unwrap();
+        users
+            .create_user(teacher("grace", "grace@school.edu"))
+            .unwrap();
+
+        assert!(matches!(
+            users.update_account(&record(ada, "grace", "teacher")),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            users.update_account(&record(ada, "ada", "janitor")),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            users.update_account(&record(ada + 100, "nobody", "admin")),
+            Err(AppError::NotFound(_))
+        ));
+
+        users
+            .update_account(&record(ada, "ada.king", "student"))
+            .unwrap();
+        assert_eq!(users.find("ada.king@school.edu").unwrap(), ada);
+        let info = users.student_info(ada).unwrap();
+        assert_eq!(
+            (info.grade_level, info.class_section.as_str()),
+            (9, "Not Set")
+        );
+        assert_eq!(store.find_teacher_id(ada).unwrap(), None);
+    }
+}
@@ -0,0 +1,154 @@
+use chrono::{Duration, Utc};
+use tracing::info;
+
+use crate::config;
+use crate::database::repository::Store;
+use crate::error::AppError;
+use crate::services::mail::{self, Email};
+use crate::services::secret;
+
+const TOKEN_LIFETIME_HOURS: i64 = 48;
+/// Minimum time between two verification emails to the same account.
+const RESEND_COOLDOWN: Duration = Duration::minutes(1);
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub const STATUS_PENDING: &str = "pending";
+const INVALID_TOKEN: &str = "Verification link is invalid or has expired";
+
+/// Issues a new verification token for a pending account, replacing earlier
+/// ones, and emails the link to `email`.
+pub fn send_verification(store: &dyn Store, user_id: i32, email: &str) -> Result<(), AppError> {
+    let transport = mail::transport().map_err(AppError::Internal)?;
+    let token = issue_token(store, user_id)?;
+
+    transport
+        .send(&Email {
+            to: email.to_string(),
+            subject: "Confirm your email address".to_string(),
+            body: format!(
+                "Welcome to the Demerit System.\n\n\
+             Open this link to confirm your email address and activate your account:\n\n\
+             {}/verify-email?token={}\n\n\
+             The link expires in {} hours. If you did not register, ignore this email.\n",
+                config::get().server.public_url,
+                token,
+                TOKEN_LIFETIME_HOURS
+            ),
+        })
+        .map_err(AppError::Internal)?;
+
+    info!(user_id, "Sent verification email");
+    Ok(())
+}
+
+/// Stores a new token for `user_id` and returns it. Earlier tokens stop
+/// working.
+fn issue_token(store: &dyn Store, user_id: i32) -> Result<String, AppError> {
+    let token = secret::generate(32);
+    let expires_at = (Utc::now() + Duration::hours(TOKEN_LIFETIME_HOURS))
+        .format(TIMESTAMP_FORMAT)
+        .to_string();
+
+    store.add_verification_token(user_id, &secret::hash(&token), &expires_at)?;
+    Ok(token)
+}
+
+/// Consumes a verification token and activates the account it belongs to.
+pub fn verify_email(store: &dyn Store, token: &str) -> Result<i32, AppError> {
+    store
+        .redeem_verification_token(&secret::hash(token.trim()))?
+        .ok_or_else(|| AppError::InvalidToken(INVALID_TOKEN.to_string()))
+}
+
+/// Sends a fresh link if `email` belongs to a pending account and no link was
+/// sent in the last minute. Says nothing about whether the account exists.
+pub fn resend(store: &dyn Store, email: &str) -> Result<(), AppError> {
+    let email = email.trim();
+    let sent_after = (Utc::now() - RESEND_COOLDOWN)
+        .format(TIMESTAMP_FORMAT)
+        .to_string();
+
+    match store.pending_account(email, &sent_after)? {
+        Some(user_id) => send_verification(store, user_id, email),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::repository::{CredentialRepository, RoleDetails};
+    use crate::database::sqlite::{self, fixtures};
+    use crate::models::{LoginRequest, RegisterRequest};
+    use crate::services::auth;
+    use crate::services::registration::RegistrationPolicy;
+
+    fn invalid_token() -> AppError {
+        AppError::InvalidToken(INVALID_TOKEN.to_string())
+    }
+
+    fn login(store: &dyn Store, password: &str) -> AppError {
+        let request = LoginRequest {
+            email: "pat@gmail.com".to_string(),
+            password: password.to_string(),
+            username: None,
+            first_name: None,
+            last_name: None,
+        };
+        match auth::auth_request(store, request, "10.0.0.1") {
+            Ok(_) => panic!("login succeeded"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn login_is_blocked_until_the_email_is_verified() {
+        let store = sqlite::memory_store();
+        let user_id = auth::create_parent_account(
+            &store,
+            &RegistrationPolicy::default(),
+            &RegisterRequest {
+                email: "pat@gmail.com".to_string(),
+                password: "long enough".to_string(),
+                username: Some("pat".to_string()),
+                first_name: Some("Pat".to_string()),
+                last_name: Some("Doe".to_string()),
+                invite_code: None,
+            },
+        )
+        .unwrap();
+        let replaced = issue_token(&store, user_id).unwrap();
+        let token = issue_token(&store, user_id).unwrap();
+
+        assert_eq!(login(&store, "long enough"), AppError::EmailNotVerified);
+        assert_eq!(
+            login(&store, "wrong password"),
+            AppError::InvalidCredentials
+        );
+
+        assert_eq!(verify_email(&store, &replaced).err(), Some(invalid_token()));
+        assert_eq!(verify_email(&store, &format!(" {}\n", token)), Ok(user_id));
+        assert_eq!(verify_email(&store, &token).err(), Some(invalid_token()));
+        let account = store.sign_in_account(user_id).unwrap().unwrap();
+        assert_eq!(account.status, "active");
+
+        // Nothing is sent for accounts that are no longer pending
+        resend(&store, "pat@gmail.com").unwrap();
+    }
+
+    #[test]
+    fn expired_verification_links_are_rejected() {
+        let store = sqlite::memory_store();
+        let user_id = fixtures::user(&store, "pat", RoleDetails::Parent);
+        let token = issue_token(&store, user_id).unwrap();
+        store
+            .connection()
+            .execute(
+                "UPDATE email_verifications SET expires_at = datetime('now', '-1 minute')",
+                [],
+            )
+            .unwrap();
+
+        assert_eq!(verify_email(&store, &token).err(), Some(invalid_token()));
+    }
+}
@@ -0,0 +1,661 @@
+//! Route-level tests: requests go through the full `/api/v1` route table,
+//! role guards included, against a migrated SQLite database.
+
+use std::env;
+use std::sync::{Arc, OnceLock};
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use serde_json::{json, Value};
+
+use demerit_backend::config::{self, Config};
+use demerit_backend::database::db::{self, DbPool};
+use demerit_backend::database::migrations;
+use demerit_backend::database::repository::{
+    DemeritRepository, NewAccount, NewDemerit, ParentRepository, RoleDetails, Store,
+    StudentRepository, UserRepository,
+};
+use demerit_backend::database::sqlite::SqliteStore;
+use demerit_backend::error::json_error_handler;
+use demerit_backend::handlers::routes;
+use demerit_backend::services::{auth, session};
+
+const PASSWORD: &str = "correct horse battery";
+
+static POOL: OnceLock<DbPool> = OnceLock::new();
+
+/// One database for the whole suite; every test creates its own users.
+fn pool() -> DbPool {
+    POOL.get_or_init(|| {
+        let dir = env::temp_dir().join(format!("demerit-routes-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let mut config = Config::default();
+        config.auth.jwt_secret = "route-test-secret".to_string();
+        config.database.path = path("demerit.db");
+        config.uploads.dir = path("uploads");
+        config.backup.dir = path("backups");
+        config.mail.dir = path("mail");
+        config::init(config);
+
+        let database = &config::get().database;
+        migrations::migrate_up(&db::open(&database.path).unwrap()).unwrap();
+        db::create_pool(database).unwrap()
+    })
+    .clone()
+}
+
+fn store() -> SqliteStore {
+    SqliteStore::new(pool())
+}
+
+/// Sends `req` to an app configured like the server.
+async fn call(req: test::TestRequest) -> ServiceResponse {
+    let store: Arc<dyn Store> = Arc::new(store());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool()))
+            .app_data(web::Data::from(store))
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+            .configure(routes::configure),
+    )
+    .await;
+    test::call_service(&app, req.to_request()).await
+}
+
+async fn call_json(req: test::TestRequest) -> Value {
+    let resp = call(req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    test::read_body_json(resp).await
+}
+
+/// Creates an account and returns (user_id, email).
+fn account(details: RoleDetails) -> (i32, String) {
+    let name = uuid::Uuid::new_v4().simple().to_string();
+    let email = format!("{}@school.edu", name);
+    let user_id = store()
+        .create_user(&NewAccount {
+            username: name.clone(),
+            email: email.clone(),
+            password_hash: bcrypt::hash(PASSWORD, 4).unwrap(),
+            first_name: name[..8].to_string(),
+            last_name: "Test".to_string(),
+            must_change_password: false,
+            details,
+        })
+        .unwrap();
+    (user_id, email)
+}
+
+fn teacher() -> i32 {
+    account(RoleDetails::Teacher {
+        subject: "Math".to_string(),
+        department: "Science".to_string(),
+    })
+    .0
+}
+
+/// Creates a student and returns (user_id, student_id).
+fn student() -> (i32, i32) {
+    let (user_id, _) = account(RoleDetails::Student {
+        grade_level: 7,
+        class_section: "A".to_string(),
+    });
+    (user_id, store().find_student_id(user_id).unwrap().unwrap())
+}
+
+/// Creates a parent linked to `children` and returns its user id.
+fn parent(children: &[i32]) -> i32 {
+    let (user_id, _) = account(RoleDetails::Parent);
+    let store = store();
+    let parent_id = store.find_parent_id(user_id).unwrap().unwrap();
+    for &student_id in children {
+        store.link_student(parent_id, student_id).unwrap();
+    }
+    user_id
+}
+
+/// Records a 2-point demerit and returns its id.
+fn demerit(teacher_user: i32, student_id: i32, description: &str) -> i32 {
+    let store = store();
+    let teacher_id = store.find_teacher_id(teacher_user).unwrap().unwrap();
+    store
+        .add_demerit(&NewDemerit {
+            student_id,
+            teacher_id,
+            category_id: 1,
+            points: 2,
+            description: description.to_string(),
+        })
+        .unwrap()
+}
+
+fn bearer(user_id: i32, user_type: &str) -> (&'static str, String) {
+    let session = session::create_session(&store(), user_id).unwrap();
+    let token = auth::issue_token(user_id, user_type, &session.session_id, false, false).unwrap();
+    ("Authorization", format!("Bearer {}", token))
+}
+
+fn get(uri: &str, user_id: i32, user_type: &str) -> test::TestRequest {
+    test::TestRequest::get()
+        .uri(uri)
+        .insert_header(bearer(user_id, user_type))
+}
+
+#[actix_web::test]
+async fn login_cookie_opens_the_role_scope() {
+    let (_, email) = account(RoleDetails::Student {
+        grade_level: 7,
+        class_section: "A".to_string(),
+    });
+
+    let resp = call(
+        test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({ "email": email, "password": PASSWORD })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let cookies: Vec<_> = resp.response().cookies().collect();
+    let refresh = cookies
+        .iter()
+        .find(|c| c.name() == "refresh_token")
+        .unwrap();
+    assert_eq!(refresh.path(), Some("/api/v1/auth/refresh"));
+    let access = cookies.iter().find(|c| c.name() == "auth_token").unwrap();
+
+    let resp = call(
+        test::TestRequest::get()
+            .uri("/api/v1/student/demerits")
+            .cookie(access.clone().into_owned()),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn role_scopes_reject_other_roles() {
+    let (student_user, _) = student();
+    let teacher_user = teacher();
+
+    for uri in [
+        "/api/v1/admin/users",
+        "/api/v1/staff/students",
+        "/api/v1/teacher/demerits",
+        "/api/v1/parent/demerits",
+    ] {
+        let resp = call(get(uri, student_user, "student")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{}", uri);
+    }
+    let resp = call(get("/api/v1/admin/users", teacher_user, "teacher")).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = call(get("/api/v1/staff/students", teacher_user, "teacher")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn role_scopes_require_a_token() {
+    let resp = call(test::TestRequest::get().uri("/api/v1/student/demerits")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn unversioned_paths_are_gone() {
+    let (student_user, _) = student();
+    for uri in ["/student_data", "/my_demerits", "/time"] {
+        let resp = call(get(uri, student_user, "student")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn student_sees_only_own_demerits_and_profile() {
+    let teacher_user = teacher();
+    let (alice, alice_student) = student();
+    let (_, bob_student) = student();
+    demerit(teacher_user, alice_student, "alice's demerit");
+    demerit(teacher_user, bob_student, "bob's demerit");
+
+    let body = call_json(get("/api/v1/student/demerits", alice, "student")).await;
+    let body = body.as_array().unwrap();
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0]["description"], "alice's demerit");
+
+    let body = call_json(get("/api/v1/student/profile", alice, "student")).await;
+    assert_eq!(body["student_id"], alice_student);
+}
+
+#[actix_web::test]
+async fn parent_demerits_cover_linked_children_only() {
+    let teacher_user = teacher();
+    let (_, jane) = student();
+    let (_, bob) = student();
+    demerit(teacher_user, jane, "jane's demerit");
+    demerit(teacher_user, bob, "bob's demerit");
+    let parent_user = parent(&[jane]);
+
+    let body = call_json(get("/api/v1/parent/demerits", parent_user, "parent")).await;
+    let body = body.as_array().unwrap();
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0]["points"], 2);
+    assert!(body[0]["date_issued"].as_str().unwrap().starts_with("20"));
+
+    let own = format!("/api/v1/parent/children/{}/demerits", jane);
+    let resp = call(get(&own, parent_user, "parent")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let other = format!("/api/v1/parent/children/{}/demerits", bob);
+    let resp = call(get(&other, parent_user, "parent")).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn teacher_records_demerits_as_themselves() {
+    let teacher_user = teacher();
+    let (_, student_id) = student();
+
+    let resp = call(
+        test::TestRequest::post()
+            .uri("/api/v1/teacher/demerits")
+            .insert_header(bearer(teacher_user, "teacher"))
+            .set_json(json!({
+                "student_id": student_id,
+                "category_id": 1,
+                "points": 3,
+                "description": "Late"
+            })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body = call_json(get("/api/v1/teacher/demerits", teacher_user, "teacher")).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["points"], 3);
+}
+
+#[actix_web::test]
+async fn issuing_teacher_edits_and_voids_demerits() {
+    let issuer = teacher();
+    let other = teacher();
+    let (student_user, student_id) = student();
+    let demerit_id = demerit(issuer, student_id, "Late");
+    let uri = format!("/api/v1/staff/demerits/{}", demerit_id);
+
+    let edit = |user_id| {
+        test::TestRequest::patch()
+            .uri(&uri)
+            .insert_header(bearer(user_id, "teacher"))
+            .set_json(json!({ "points": 5, "reason": "Second offence" }))
+    };
+    let resp = call(edit(other)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = call(edit(issuer)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let void = |reason: &str| {
+        test::TestRequest::post()
+            .uri(&format!("{}/void", uri))
+            .insert_header(bearer(issuer, "teacher"))
+            .set_json(json!({ "reason": reason }))
+    };
+    let resp = call(void("")).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let resp = call(void("Wrong student")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = call(void("Wrong student")).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let body = call_json(get(&format!("{}/revisions", uri), issuer, "teacher")).await;
+    let actions: Vec<&str> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["created", "edited", "voided"]);
+    assert_eq!(body[1]["points"], 5);
+    assert_eq!(body[1]["reason"], "Second offence");
+
+    let body = call_json(get("/api/v1/student/demerits", student_user, "student")).await;
+    assert_eq!(body[0]["void_reason"], "Wrong student");
+    let body = call_json(get("/api/v1/staff/reports/students", issuer, "teacher")).await;
+    let summary = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["student_id"] == student_id)
+        .unwrap();
+    assert_eq!(summary["total_points"], 0);
+}
+
+#[actix_web::test]
+async fn appeals_move_from_student_to_issuing_teacher() {
+    let issuer = teacher();
+    let (student_user, student_id) = student();
+    let (other_student, _) = student();
+    let parent_user = parent(&[student_id]);
+    let demerit_id = demerit(issuer, student_id, "Late");
+
+    let file = |user_id, user_type: &str| {
+        test::TestRequest::post()
+            .uri(&format!(
+                "/api/v1/{}/demerits/{}/appeal",
+                user_type, demerit_id
+            ))
+            .insert_header(bearer(user_id, user_type))
+            .set_json(json!({ "reason": "The bus was late" }))
+    };
+    let resp = call(file(other_student, "student")).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = call(file(student_user, "student")).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = test::read_body_json(resp).await;
+    let appeal_id = body["appeal_id"].as_i64().unwrap();
+    let resp = call(file(parent_user, "parent")).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let body = call_json(get("/api/v1/staff/appeals", issuer, "teacher")).await;
+    assert_eq!(body[0]["appeal_id"], appeal_id);
+    assert_eq!(body[0]["status"], "open");
+
+    let post = |path: &str, body: Value| {
+        test::TestRequest::post()
+            .uri(&format!("/api/v1/staff/appeals/{}/{}", appeal_id, path))
+            .insert_header(bearer(issuer, "teacher"))
+            .set_json(body)
+    };
+    let resp = call(post("review", json!({}))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = call_json(get("/api/v1/parent/demerits", parent_user, "parent")).await;
+    assert_eq!(body[0]["appeal_status"], "under_review");
+
+    let resp = call(post(
+        "decide",
+        json!({ "outcome": "upheld", "comment": "The bus was on time" }),
+    ))
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    for (user_id, user_type) in [(student_user, "student"), (parent_user, "parent")] {
+        let uri = format!("/api/v1/{}/demerits", user_type);
+        let body = call_json(get(&uri, user_id, user_type)).await;
+        assert_eq!(body[0]["appeal_status"], "upheld", "{}", user_type);
+    }
+    let body = call_json(get("/api/v1/teacher/demerits", issuer, "teacher")).await;
+    assert_eq!(body[0]["appeal_status"], "upheld");
+    let body = call_json(get("/api/v1/student/appeals", student_user, "student")).await;
+    assert_eq!(body[0]["decision_comment"], "The bus was on time");
+}
+
+#[actix_web::test]
+async fn crossing_a_threshold_raises_an_escalation() {
+    let (admin, _) = account(RoleDetails::Admin);
+    let issuer = teacher();
+    let (_, student_id) = student();
+
+    // High enough that demerits recorded by other tests never reach it.
+    let resp = call(
+        test::TestRequest::post()
+            .uri("/api/v1/admin/escalation_rules")
+            .insert_header(bearer(admin, "admin"))
+            .set_json(json!({
+                "name": "Parent meeting at 50",
+                "points": 50,
+                "window_days": 30,
+                "action": "parent_meeting",
+                "assigned_to": issuer
+            })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = call(get("/api/v1/admin/escalation_rules", issuer, "teacher")).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let record = |points| {
+        test::TestRequest::post()
+            .uri("/api/v1/teacher/demerits")
+            .insert_header(bearer(issuer, "teacher"))
+            .set_json(json!({
+                "student_id": student_id,
+                "category_id": 1,
+                "points": points,
+                "description": "Fighting"
+            }))
+    };
+    let body = call_json(record(30)).await;
+    assert_eq!(body["escalations"], json!([]));
+    let body = call_json(record(25)).await;
+    let escalation_id = body["escalations"][0].as_i64().unwrap();
+
+    let body = call_json(get("/api/v1/staff/escalations", issuer, "teacher")).await;
+    let open = body.as_array().unwrap();
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0]["action"], "parent_meeting");
+    assert_eq!(open[0]["points"], 55);
+    assert_eq!(open[0]["student_id"], student_id);
+
+    let resp = call(
+        test::TestRequest::post()
+            .uri(&format!(
+                "/api/v1/staff/escalations/{}/resolve",
+                escalation_id
+            ))
+            .insert_header(bearer(issuer, "teacher"))
+            .set_json(json!({ "resolution": "Met with both parents" })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = call_json(get("/api/v1/staff/escalations", issuer, "teacher")).await;
+    assert_eq!(body, json!([]));
+}
+
+#[actix_web::test]
+async fn merits_offset_demerits_in_every_summary() {
+    let (admin, _) = account(RoleDetails::Admin);
+    let issuer = teacher();
+    let (student_user, student_id) = student();
+    let guardian = parent(&[student_id]);
+    demerit(issuer, student_id, "Late");
+
+    let categories = call_json(get("/api/v1/staff/merit_categories", issuer, "teacher")).await;
+    assert_eq!(categories[0]["name"], "Helping Others");
+    let resp = call(
+        test::TestRequest::post()
+            .uri("/api/v1/teacher/merits")
+            .insert_header(bearer(issuer, "teacher"))
+            .set_json(json!({
+                "student_id": student_id,
+                "category_id": categories[0]["id"],
+                "points": 4,
+                "description": "Helped a new classmate"
+            })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let merits = call_json(get("/api/v1/student/merits", student_user, "student")).await;
+    assert_eq!(merits[0]["points"], 4);
+    let merits = call_json(get("/api/v1/parent/merits", guardian, "parent")).await;
+    assert_eq!(merits.as_array().unwrap().len(), 1);
+
+    // Two demerit points, less one cancelled by the first three merit points.
+    let expected = (json!(2), json!(4), json!(1));
+    let points = |summary: &Value| {
+        (
+            summary["total_points"].clone(),
+            summary["merit_points"].clone(),
+            summary["net_points"].clone(),
+        )
+    };
+    let own = call_json(get("/api/v1/student/summary", student_user, "student")).await;
+    assert_eq!(points(&own), expected);
+    // Nothing decays by default, so every point still counts
+    assert_eq!(own["effective_points"], 2);
+    let children = call_json(get("/api/v1/parent/children", guardian, "parent")).await;
+    assert_eq!(points(&children[0]), expected);
+    let report = call_json(get("/api/v1/staff/reports/students", issuer, "teacher")).await;
+    let row = report
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|row| row["student_id"] == student_id)
+        .unwrap();
+    assert_eq!(points(row), expected);
+    let users = call_json(get("/api/v1/admin/users", admin, "admin")).await;
+    let user = users
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|user| user["user_id"] == student_user)
+        .unwrap();
+    assert_eq!(
+        (
+            user["total_demerits"].clone(),
+            user["merit_points"].clone(),
+            user["net_points"].clone()
+        ),
+        expected
+    );
+    assert_eq!(user["effective_points"], 2);
+}
+
+#[actix_web::test]
+async fn role_and_two_factor_changes_apply_to_issued_tokens() {
+    let (admin_user, _) = account(RoleDetails::Admin);
+    let (other_admin, _) = account(RoleDetails::Admin);
+    let demoted = bearer(other_admin, "admin");
+    let required = bearer(admin_user, "admin");
+
+    let resp = call(
+        test::TestRequest::put()
+            .uri("/api/v1/admin/users/role")
+            .insert_header(bearer(admin_user, "admin"))
+            .set_json(json!({ "user_id": other_admin, "new_role": "teacher" })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = call(
+        test::TestRequest::get()
+            .uri("/api/v1/admin/users")
+            .insert_header(demoted),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = call(
+        test::TestRequest::post()
+            .uri(&format!(
+                "/api/v1/admin/users/{}/require_two_factor",
+                admin_user
+            ))
+            .insert_header(bearer(admin_user, "admin"))
+            .set_json(json!({ "required": true })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(
+        call(
+            test::TestRequest::get()
+                .uri("/api/v1/admin/users")
+                .insert_header(required),
+        )
+        .await,
+    )
+    .await;
+    assert_eq!(body["code"], "two_factor_enrollment_required");
+}
+
+#[actix_web::test]
+async fn registered_parents_sign_in_after_verifying_their_email() {
+    let name = uuid::Uuid::new_v4().simple().to_string();
+    let email = format!("{}@example.org", name);
+    let login = || {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({ "email": email, "password": PASSWORD }))
+    };
+
+    let resp = call(
+        test::TestRequest::post()
+            .uri("/api/v1/auth/register")
+            .set_json(json!({
+                "email": email,
+                "password": PASSWORD,
+                "username": name,
+                "first_name": "Pat",
+                "last_name": "Doe",
+            })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = call(login()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "email_not_verified");
+
+    let mail = std::fs::read_dir(&config::get().mail.dir)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .find(|message| message.contains(&email))
+        .expect("verification email");
+    let token = mail
+        .split("verify-email?token=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap();
+
+    let resp = call(
+        test::TestRequest::post()
+            .uri("/api/v1/auth/verify_email")
+            .set_json(json!({ "token": token })),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(call(login()).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn admins_import_students_from_an_uploaded_csv() {
+    teacher();
+    let (admin_user, _) = account(RoleDetails::Admin);
+    let name = format!(
+        "Imported {}",
+        &uuid::Uuid::new_v4().simple().to_string()[..8]
+    );
+    let boundary = "demerit-upload-boundary";
+    let body = format!(
+        "--{b}\r\n\
+         Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+         ignored\r\n\
+         --{b}\r\n\
+         Content-Disposition: form-data; name=\"file\"; filename=\"students.csv\"\r\n\
+         Content-Type: text/csv\r\n\r\n\
+         name,grade,class,demerits\n{name},8,C,3\n\r\n\
+         --{b}--\r\n",
+        b = boundary,
+        name = name
+    );
+
+    let body = call_json(
+        test::TestRequest::post()
+            .uri("/api/v1/admin/students/import")
+            .insert_header(bearer(admin_user, "admin"))
+            .insert_header((
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            ))
+            .set_payload(body),
+    )
+    .await;
+    assert_eq!(body["success_count"], 1);
+    assert_eq!(body["failure_count"], 0);
+
+    let username = name.replace(' ', "_").to_lowercase();
+    let user_id = store().find_user_id(&username).unwrap().unwrap();
+    let student_id = store().find_student_id(user_id).unwrap().unwrap();
+    let summary = store().student_summary(student_id).unwrap().unwrap();
+    assert_eq!((summary.grade_level, summary.total_points), (Some(8), 3));
+    let uploads = std::fs::read_dir(&config::get().uploads.dir).unwrap();
+    assert!(uploads
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .any(|file| file.ends_with("_students.csv")));
+}
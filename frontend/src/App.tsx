@@ -1,6 +1,9 @@
 import { BrowserRouter as Router, Routes, Route } from "react-router-dom";
 import { UserProvider } from "./contexts/UserContext";
 import AuthForm from "./components/AuthForm";
+import ChangePasswordForm from "./components/ChangePasswordForm";
+import TwoFactorSetup from "./components/TwoFactorSetup";
+import VerifyEmail from "./components/VerifyEmail";
 import { TeacherDashboard } from "./pages/TeacherDashboard";
 import { StudentDashboard } from "./pages/StudentDashboard";
 import { ParentDashboard } from "./pages/ParentDashboard";
@@ -15,6 +18,9 @@ function App() {
         <Clock />
         <Routes>
           <Route path="/" element={<AuthForm />} />
+          <Route path="/change-password" element={<ChangePasswordForm />} />
+          <Route path="/two-factor-setup" element={<TwoFactorSetup />} />
+          <Route path="/verify-email" element={<VerifyEmail />} />
           <Route
             path="/admin"
             element={
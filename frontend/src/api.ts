@@ -0,0 +1,2 @@
+/** Base URL of the versioned backend API. */
+export const API_BASE = "http://localhost:8080/api/v1";
@@ -1,6 +1,7 @@
 import React, { useState, useEffect, useRef } from "react";
 import { Form, FormInput, FormButton } from "./Form";
 import "./AddDemeritForm.css";
+import { API_BASE } from "../api";
 
 interface AddDemeritFormProps {
   onSubmit: (demerit: NewDemeritRecord) => void;
@@ -55,8 +56,8 @@ export const AddDemeritForm: React.FC<AddDemeritFormProps> = ({
     const fetchData = async () => {
       try {
         const [studentsRes, categoriesRes] = await Promise.all([
-          fetch("http://localhost:8080/students", { credentials: "include" }),
-          fetch("http://localhost:8080/demerit-categories", {
+          fetch(`${API_BASE}/staff/students`, { credentials: "include" }),
+          fetch(`${API_BASE}/staff/demerit_categories`, {
             credentials: "include",
           }),
         ]);
@@ -2,6 +2,7 @@ import React, { useState, useEffect } from "react";
 import "./DataTable.css";
 import type { AdminUserRecord } from "../types/demerit";
 import { EditUserModal } from "./EditUserModal";
+import { API_BASE } from "../api";
 
 export const AdminDataTable: React.FC = () => {
   const [selectedUser, setSelectedUser] = useState<AdminUserRecord | null>(
@@ -47,7 +48,7 @@ export const AdminDataTable: React.FC = () => {
 
       console.log("Sending update payload:", updatePayload);
 
-      const response = await fetch("http://localhost:8080/update_user", {
+      const response = await fetch(`${API_BASE}/admin/users`, {
         method: "PUT",
         headers: {
           "Content-Type": "application/json",
@@ -95,7 +96,7 @@ export const AdminDataTable: React.FC = () => {
     try {
       // Add cache-busting
       const response = await fetch(
-        `http://localhost:8080/admin_data?t=${new Date().getTime()}`,
+        `${API_BASE}/admin/users?t=${new Date().getTime()}`,
         {
           credentials: "include",
           headers: {
@@ -121,7 +122,7 @@ export const AdminDataTable: React.FC = () => {
   const handleSaveUser = async (updatedUser: AdminUserRecord) => {
     try {
       // First, save the basic user information
-      const response = await fetch("http://localhost:8080/update_user", {
+      const response = await fetch(`${API_BASE}/admin/users`, {
         method: "PUT",
         headers: {
           "Content-Type": "application/json",
@@ -142,7 +143,7 @@ export const AdminDataTable: React.FC = () => {
         updatedUser.children.length > 0
       ) {
         // First, get the parent_id
-        const parentsResponse = await fetch("http://localhost:8080/parents", {
+        const parentsResponse = await fetch(`${API_BASE}/admin/parents`, {
           credentials: "include",
         });
 
@@ -161,9 +162,9 @@ export const AdminDataTable: React.FC = () => {
 
           // Call our new endpoint to update relations
           const relationResponse = await fetch(
-            "http://localhost:8080/update_parent_students",
+            `${API_BASE}/admin/parents/students`,
             {
-              method: "POST",
+              method: "PUT",
               headers: {
                 "Content-Type": "application/json",
               },
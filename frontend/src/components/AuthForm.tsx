@@ -1,9 +1,10 @@
-import React, { useState } from "react";
+import React, { useEffect, useState } from "react";
 import { Form, FormInput, FormButton } from "./Form";
 import "./AuthForm.css";
 import "../App.css";
 import { useNavigate } from "react-router-dom";
 import { useUser } from "../contexts/UserContext";
+import { API_BASE } from "../api";
 
 type AuthMode = "login" | "register";
 
@@ -13,6 +14,7 @@ interface AuthFormData {
   username?: string;
   firstName?: string;
   lastName?: string;
+  inviteCode?: string;
 }
 
 interface AuthFormErrors {
@@ -36,13 +38,28 @@ const AuthForm: React.FC = () => {
     username: "",
     firstName: "",
     lastName: "",
+    inviteCode: "",
   });
+  const [inviteRequired, setInviteRequired] = useState(false);
 
   const [errors, setErrors] = useState<AuthFormErrors>({});
+  const [notice, setNotice] = useState<string | null>(null);
+  // Set when the password was right but the email address is not verified yet
+  const [unverified, setUnverified] = useState(false);
+  // Set when the password was accepted but a second factor is still needed
+  const [challengeToken, setChallengeToken] = useState<string | null>(null);
+  const [twoFactorCode, setTwoFactorCode] = useState("");
 
   const navigate = useNavigate();
   const { setUser } = useUser();
 
+  useEffect(() => {
+    fetch(`${API_BASE}/auth/registration_policy`)
+      .then((response) => response.json())
+      .then((policy) => setInviteRequired(Boolean(policy?.invite_required)))
+      .catch(() => setInviteRequired(false));
+  }, []);
+
   const handleChange = (e: React.ChangeEvent<HTMLInputElement>) => {
     const { name, value } = e.target;
     setFormData((prev) => ({
@@ -89,15 +106,79 @@ const AuthForm: React.FC = () => {
     return Object.keys(newErrors).length === 0;
   };
 
-  const handleSubmit = async (e: React.FormEvent<HTMLFormElement>) => {
-    // Fix the logging
-    console.log("Sending:", JSON.stringify(formData, null, 2));
+  const handleSignedIn = (data: any) => {
+    setUser({
+      ...data.user,
+      userType: data.user.permissions,
+    });
+
+    if (data.user.must_change_password) {
+      navigate("/change-password");
+      return;
+    }
+    if (data.user.must_enroll_two_factor) {
+      navigate("/two-factor-setup");
+      return;
+    }
+
+    switch (data.user.permissions) {
+      case "admin":
+        navigate("/admin");
+        break;
+      case "teacher":
+        navigate("/teacher");
+        break;
+      case "parent":
+        navigate("/parent");
+        break;
+      case "student":
+        navigate("/student");
+        break;
+      default:
+        throw new Error("Unknown user type");
+    }
+  };
 
+  const handleTwoFactorSubmit = async (e: React.FormEvent<HTMLFormElement>) => {
+    e.preventDefault();
+    setIsLoading(true);
+    setErrors({});
+
+    try {
+      const response = await fetch(`${API_BASE}/auth/login/two_factor`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+        },
+        body: JSON.stringify({
+          challenge_token: challengeToken,
+          code: twoFactorCode,
+        }),
+        credentials: "include",
+      });
+      const data = await response.json();
+
+      if (!response.ok) {
+        throw new Error(data?.message || "Authentication failed");
+      }
+      handleSignedIn(data);
+    } catch (error) {
+      setErrors({
+        submit: error instanceof Error ? error.message : "An error occurred",
+      });
+    } finally {
+      setIsLoading(false);
+    }
+  };
+
+  const handleSubmit = async (e: React.FormEvent<HTMLFormElement>) => {
     e.preventDefault();
     if (!validateForm()) return;
 
     setIsLoading(true);
     setErrors({});
+    setNotice(null);
+    setUnverified(false);
 
     try {
       // When sending login request, only send required fields
@@ -113,12 +194,11 @@ const AuthForm: React.FC = () => {
               username: formData.username,
               first_name: formData.firstName, // Match backend field names
               last_name: formData.lastName, // Match backend field names
+              invite_code: formData.inviteCode || undefined,
             };
 
-      console.log("Request data:", JSON.stringify(requestData, null, 2));
-
-      const endpoint = mode === "login" ? "/login" : "/register";
-      const response = await fetch(`http://localhost:8080${endpoint}`, {
+      const endpoint = mode === "login" ? "/auth/login" : "/auth/register";
+      const response = await fetch(`${API_BASE}${endpoint}`, {
         method: "POST",
         headers: {
           "Content-Type": "application/json",
@@ -127,49 +207,37 @@ const AuthForm: React.FC = () => {
         credentials: "include",
       });
 
-      // Log the raw response
-      console.log("Raw response:", response);
-
-      // Log the response text
       const responseText = await response.text();
-      console.log("Response text:", responseText);
 
       // Try to parse the JSON
       let data;
       try {
         data = JSON.parse(responseText);
-        console.log("Parsed data:", data);
       } catch (e) {
         console.error("Failed to parse JSON:", e);
       }
 
       if (!response.ok) {
+        setUnverified(data?.code === "email_not_verified");
         throw new Error(data?.message || "Authentication failed");
       }
 
+      if (data?.status === "pending") {
+        // New accounts stay inactive until the emailed link is opened
+        setMode("login");
+        setNotice(data.message);
+        return;
+      }
+
+      if (data?.two_factor_required) {
+        setChallengeToken(data.challenge_token);
+        setTwoFactorCode("");
+        return;
+      }
+
       // Call the success handler with the user data
       if (data?.user) {
-        setUser({
-          ...data.user,
-          userType: data.user.permissions,
-        });
-
-        switch (data.user.permissions) {
-          case "admin":
-            navigate("/admin");
-            break;
-          case "teacher":
-            navigate("/teacher");
-            break;
-          case "parent":
-            navigate("/parent");
-            break;
-          case "student":
-            navigate("/student");
-            break;
-          default:
-            throw new Error("Unknown user type");
-        }
+        handleSignedIn(data);
       } else {
         throw new Error("No user data received");
       }
@@ -183,9 +251,38 @@ const AuthForm: React.FC = () => {
     }
   };
 
+  const handleResendVerification = async () => {
+    setIsLoading(true);
+    try {
+      const response = await fetch(
+        `${API_BASE}/auth/resend_verification`,
+        {
+          method: "POST",
+          headers: {
+            "Content-Type": "application/json",
+          },
+          body: JSON.stringify({ email: formData.email }),
+        },
+      );
+      const data = await response.json();
+      setErrors({});
+      setUnverified(false);
+      setNotice(data?.message || "A new verification link has been sent");
+    } catch (error) {
+      setErrors({
+        submit: error instanceof Error ? error.message : "An error occurred",
+      });
+    } finally {
+      setIsLoading(false);
+    }
+  };
+
   const switchMode = (newMode: AuthMode) => {
     setMode(newMode);
     setErrors({});
+    setNotice(null);
+    setUnverified(false);
+    setChallengeToken(null);
   };
 
   return (
@@ -213,103 +310,165 @@ const AuthForm: React.FC = () => {
             </button>
           </div>
 
-          <Form onSubmit={handleSubmit} className="auth-form">
-            <h2>{mode === "login" ? "Welcome Back" : "Create Account"}</h2>
+          {challengeToken ? (
+            <Form onSubmit={handleTwoFactorSubmit} className="auth-form">
+              <h2>Two-Factor Authentication</h2>
+              <p>
+                Enter the code from your authenticator app, or one of your
+                recovery codes.
+              </p>
+
+              {errors.submit && (
+                <div className="error-banner">{errors.submit}</div>
+              )}
+
+              <FormInput
+                label="Authentication Code"
+                name="twoFactorCode"
+                value={twoFactorCode}
+                onChange={(e) => setTwoFactorCode(e.target.value)}
+                placeholder="123456"
+                required
+                disabled={isLoading}
+              />
 
-            {errors.submit && (
-              <div className="error-banner">{errors.submit}</div>
-            )}
+              <FormButton type="submit" disabled={isLoading}>
+                {isLoading ? "Verifying..." : "Verify"}
+              </FormButton>
+
+              <button
+                type="button"
+                className="forgot-password"
+                onClick={() => switchMode("login")}
+                disabled={isLoading}
+              >
+                Back to login
+              </button>
+            </Form>
+          ) : (
+            <Form onSubmit={handleSubmit} className="auth-form">
+              <h2>{mode === "login" ? "Welcome Back" : "Create Account"}</h2>
+
+              {notice && <div className="notice-banner">{notice}</div>}
+
+              {errors.submit && (
+                <div className="error-banner">{errors.submit}</div>
+              )}
+
+              {mode === "register" && (
+                <>
+                  <div className="name-fields">
+                    <FormInput
+                      label="First Name"
+                      name="firstName"
+                      value={formData.firstName || ""}
+                      onChange={handleChange}
+                      placeholder="Enter first name"
+                      error={errors.firstName}
+                      required
+                      disabled={isLoading}
+                    />
+                    <FormInput
+                      label="Last Name"
+                      name="lastName"
+                      value={formData.lastName || ""}
+                      onChange={handleChange}
+                      placeholder="Enter last name"
+                      error={errors.lastName}
+                      required
+                      disabled={isLoading}
+                    />
+                  </div>
 
-            {mode === "register" && (
-              <>
-                <div className="name-fields">
                   <FormInput
-                    label="First Name"
-                    name="firstName"
-                    value={formData.firstName || ""}
+                    label="Username"
+                    name="username"
+                    value={formData.username || ""}
                     onChange={handleChange}
-                    placeholder="Enter first name"
-                    error={errors.firstName}
+                    placeholder="Choose a username"
+                    error={errors.username}
                     required
                     disabled={isLoading}
                   />
+
                   <FormInput
-                    label="Last Name"
-                    name="lastName"
-                    value={formData.lastName || ""}
+                    label={
+                      inviteRequired ? "Invite Code" : "Invite Code (optional)"
+                    }
+                    name="inviteCode"
+                    value={formData.inviteCode || ""}
                     onChange={handleChange}
-                    placeholder="Enter last name"
-                    error={errors.lastName}
-                    required
+                    placeholder="Code from the school"
+                    required={inviteRequired}
                     disabled={isLoading}
                   />
-                </div>
-
-                <FormInput
-                  label="Username"
-                  name="username"
-                  value={formData.username || ""}
-                  onChange={handleChange}
-                  placeholder="Choose a username"
-                  error={errors.username}
-                  required
-                  disabled={isLoading}
-                />
-              </>
-            )}
-
-            <FormInput
-              label="Email"
-              type="email"
-              name="email"
-              value={formData.email}
-              onChange={handleChange}
-              placeholder="Enter your email"
-              error={errors.email}
-              required
-              disabled={isLoading}
-            />
+                </>
+              )}
 
-            <div className="password-input-wrapper">
               <FormInput
-                label="Password"
-                type={showPassword ? "text" : "password"}
-                name="password"
-                value={formData.password}
+                label="Email"
+                type="email"
+                name="email"
+                value={formData.email}
                 onChange={handleChange}
-                placeholder="Enter your password"
-                error={errors.password}
+                placeholder="Enter your email"
+                error={errors.email}
                 required
                 disabled={isLoading}
               />
-              <button
-                type="button"
-                className="toggle-password"
-                onClick={() => setShowPassword(!showPassword)}
-                disabled={isLoading}
-              >
-                {showPassword ? "👁️" : "👁️‍🗨️"}
-              </button>
-            </div>
-
-            <FormButton type="submit" disabled={isLoading}>
-              {isLoading
-                ? "Loading..."
-                : mode === "login"
-                  ? "Login"
-                  : "Register"}
-            </FormButton>
 
-            {mode === "login" && (
-              <button
-                type="button"
-                className="forgot-password"
-                disabled={isLoading}
-              >
-                Forgot Password?
-              </button>
-            )}
-          </Form>
+              <div className="password-input-wrapper">
+                <FormInput
+                  label="Password"
+                  type={showPassword ? "text" : "password"}
+                  name="password"
+                  value={formData.password}
+                  onChange={handleChange}
+                  placeholder="Enter your password"
+                  error={errors.password}
+                  required
+                  disabled={isLoading}
+                />
+                <button
+                  type="button"
+                  className="toggle-password"
+                  onClick={() => setShowPassword(!showPassword)}
+                  disabled={isLoading}
+                >
+                  {showPassword ? "👁️" : "👁️‍🗨️"}
+                </button>
+              </div>
+
+              <FormButton type="submit" disabled={isLoading}>
+                {isLoading
+                  ? "Loading..."
+                  : mode === "login"
+                    ? "Login"
+                    : "Register"}
+              </FormButton>
+
+              {mode === "login" && unverified && (
+                <button
+                  type="button"
+                  className="forgot-password"
+                  onClick={handleResendVerification}
+                  disabled={isLoading}
+                >
+                  Resend verification email
+                </button>
+              )}
+
+              {mode === "login" && (
+                <button
+                  type="button"
+                  className="forgot-password"
+                  disabled={isLoading}
+                >
+                  Forgot Password?
+                </button>
+              )}
+            </Form>
+          )}
         </div>
       </div>
     </div>
@@ -0,0 +1,114 @@
+import React, { useState } from "react";
+import { useNavigate } from "react-router-dom";
+import { Form, FormInput, FormButton } from "./Form";
+import { useUser } from "../contexts/UserContext";
+import "./AuthForm.css";
+import "../App.css";
+import { API_BASE } from "../api";
+
+const ChangePasswordForm: React.FC = () => {
+  const { user, setUser } = useUser();
+  const navigate = useNavigate();
+  const [currentPassword, setCurrentPassword] = useState("");
+  const [newPassword, setNewPassword] = useState("");
+  const [confirmPassword, setConfirmPassword] = useState("");
+  const [error, setError] = useState<string | null>(null);
+  const [isLoading, setIsLoading] = useState(false);
+
+  const handleSubmit = async (e: React.FormEvent<HTMLFormElement>) => {
+    e.preventDefault();
+    if (newPassword.length < 8) {
+      setError("Password must be at least 8 characters");
+      return;
+    }
+    if (newPassword !== confirmPassword) {
+      setError("Passwords do not match");
+      return;
+    }
+
+    setIsLoading(true);
+    setError(null);
+    try {
+      const response = await fetch(`${API_BASE}/auth/change_password`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+        },
+        body: JSON.stringify({
+          current_password: currentPassword,
+          new_password: newPassword,
+        }),
+        credentials: "include",
+      });
+      const data = await response.json();
+
+      if (!response.ok) {
+        throw new Error(data?.message || "Failed to change password");
+      }
+
+      setUser({
+        ...data.user,
+        userType: data.user.permissions,
+      });
+      navigate(`/${data.user.permissions}`);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "An error occurred");
+    } finally {
+      setIsLoading(false);
+    }
+  };
+
+  if (!user) {
+    navigate("/");
+    return null;
+  }
+
+  return (
+    <div className="main">
+      <div className="app">
+        <div className="auth-container">
+          <Form onSubmit={handleSubmit} className="auth-form">
+            <h2>Choose a New Password</h2>
+            <p>You need to replace your temporary password before continuing.</p>
+
+            {error && <div className="error-banner">{error}</div>}
+
+            <FormInput
+              label="Current Password"
+              type="password"
+              name="currentPassword"
+              value={currentPassword}
+              onChange={(e) => setCurrentPassword(e.target.value)}
+              required
+              disabled={isLoading}
+            />
+            <FormInput
+              label="New Password"
+              type="password"
+              name="newPassword"
+              value={newPassword}
+              onChange={(e) => setNewPassword(e.target.value)}
+              required
+              disabled={isLoading}
+            />
+            <FormInput
+              label="Confirm New Password"
+              type="password"
+              name="confirmPassword"
+              value={confirmPassword}
+              onChange={(e) => setConfirmPassword(e.target.value)}
+              required
+              disabled={isLoading}
+            />
+
+            <FormButton type="submit" disabled={isLoading}>
+              {isLoading ? "Saving..." : "Change Password"}
+            </FormButton>
+          </Form>
+        </div>
+      </div>
+    </div>
+  );
+};
+
+export default ChangePasswordForm;
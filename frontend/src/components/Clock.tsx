@@ -1,5 +1,6 @@
 import React, { useState, useEffect } from "react";
 import "./Clock.css";
+import { API_BASE } from "../api";
 
 interface TimeResponse {
   current_time: string;
@@ -21,7 +22,7 @@ function Clock() {
 
   const fetchTime = async () => {
     try {
-      const response = await fetch("http://localhost:8080/time");
+      const response = await fetch(`${API_BASE}/time`);
       const data: TimeResponse = await response.json();
       setTime(data.current_time);
     } catch (error) {
@@ -1,5 +1,6 @@
 import React, { useState, useRef } from "react";
 import "./CsvUploader.css";
+import { API_BASE } from "../api";
 
 interface CsvUploaderProps {
   onUploadSuccess?: (response: any) => void;
@@ -37,7 +38,7 @@ export const CsvUploader: React.FC<CsvUploaderProps> = ({
     formData.append("file", selectedFile);
 
     try {
-      const response = await fetch("http://localhost:8080/upload_csv", {
+      const response = await fetch(`${API_BASE}/admin/students/import`, {
         method: "POST",
         body: formData,
         credentials: "include",
@@ -2,6 +2,7 @@ import React, { useState, useEffect } from "react";
 import { useUser } from "../contexts/UserContext";
 import "./DataTable.css";
 import { DataRecord } from "../types/demerit";
+import { API_BASE } from "../api";
 interface Column {
   key: string;
   header: string;
@@ -34,7 +35,7 @@ const DataTable: React.FC<DataTableProps> = ({ title, refreshTrigger = 0 }) => {
       ]);
     } else if (user?.userType === "student") {
       setColumns([
-        { key: "category", header: "Category" },
+        { key: "category_name", header: "Category" },
         { key: "points", header: "Points" },
         { key: "teacher_name", header: "Issued By" },
         { key: "date_issued", header: "Date" },
@@ -54,7 +55,7 @@ const DataTable: React.FC<DataTableProps> = ({ title, refreshTrigger = 0 }) => {
     setLoading(true);
     setError(null);
     try {
-      const endpoint = `http://localhost:8080/${user?.userType}_data`;
+      const endpoint = `${API_BASE}/${user?.userType}/demerits`;
       console.log(`Fetching data from: ${endpoint}`);
 
       const response = await fetch(endpoint, {
@@ -15,6 +15,7 @@ import {
   TimeScale,
 } from "chart.js";
 import "chartjs-adapter-date-fns";
+import { API_BASE } from "../api";
 
 // Register Chart.js components
 ChartJS.register(
@@ -72,10 +73,10 @@ export const DataVisualizationPanel: React.FC<DataVisualizationPanelProps> = ({
 
         // Fetch both distribution and trend data
         const [distributionResponse, trendResponse] = await Promise.all([
-          fetch("http://localhost:8080/demerit_distribution", {
+          fetch(`${API_BASE}/staff/reports/distribution`, {
             credentials: "include",
           }),
-          fetch("http://localhost:8080/demerit_trend", {
+          fetch(`${API_BASE}/staff/reports/trend`, {
             credentials: "include",
           }),
         ]);
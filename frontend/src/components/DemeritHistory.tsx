@@ -1,5 +1,6 @@
 import React, { useState, useEffect } from "react";
 import "./DemeritHistory.css";
+import { API_BASE } from "../api";
 
 interface DemeritHistoryProps {
   onClose: () => void;
@@ -24,7 +25,7 @@ export const DemeritHistory: React.FC<DemeritHistoryProps> = ({ onClose }) => {
   useEffect(() => {
     const fetchDemeritHistory = async () => {
       try {
-        const response = await fetch("http://localhost:8080/demerit_history", {
+        const response = await fetch(`${API_BASE}/staff/reports/history`, {
           credentials: "include",
         });
 
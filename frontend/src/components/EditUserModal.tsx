@@ -2,6 +2,7 @@ import React, { useState, useEffect } from "react";
 import { AdminUserRecord } from "../types/demerit";
 import { Form, FormInput, FormButton } from "./Form";
 import "./EditUserModal.css";
+import { API_BASE } from "../api";
 
 interface EditUserModalProps {
   user: AdminUserRecord;
@@ -47,7 +48,7 @@ export const EditUserModal: React.FC<EditUserModalProps> = ({
   const fetchStudents = async () => {
     setLoadingStudents(true);
     try {
-      const response = await fetch("http://localhost:8080/students", {
+      const response = await fetch(`${API_BASE}/staff/students`, {
         credentials: "include",
       });
       if (!response.ok) throw new Error("Failed to fetch students");
@@ -166,7 +167,7 @@ export const EditUserModal: React.FC<EditUserModalProps> = ({
     // First we need the parent_id
     try {
       // Fetch the parent's ID from the database
-      const parentResponse = await fetch(`http://localhost:8080/parents`, {
+      const parentResponse = await fetch(`${API_BASE}/admin/parents`, {
         credentials: "include",
       });
       if (!parentResponse.ok) throw new Error("Failed to fetch parent info");
@@ -180,7 +181,7 @@ export const EditUserModal: React.FC<EditUserModalProps> = ({
 
       // Add relationships for each selected student
       for (const student of students) {
-        await fetch("http://localhost:8080/add_parent_student", {
+        await fetch(`${API_BASE}/admin/parents/students`, {
           method: "POST",
           headers: { "Content-Type": "application/json" },
           credentials: "include",
@@ -1,5 +1,6 @@
 import React, { useState, useEffect } from "react";
 import "./ParentChildManager.css";
+import { API_BASE } from "../api";
 
 interface Student {
   id: number;
@@ -25,8 +26,8 @@ export const ParentChildManager: React.FC = () => {
     const fetchData = async () => {
       try {
         const [parentsRes, studentsRes] = await Promise.all([
-          fetch("http://localhost:8080/parents", { credentials: "include" }),
-          fetch("http://localhost:8080/students", { credentials: "include" }),
+          fetch(`${API_BASE}/admin/parents`, { credentials: "include" }),
+          fetch(`${API_BASE}/staff/students`, { credentials: "include" }),
         ]);
 
         if (!parentsRes.ok || !studentsRes.ok) {
@@ -57,7 +58,7 @@ export const ParentChildManager: React.FC = () => {
     }
 
     try {
-      const response = await fetch("http://localhost:8080/add_parent_student", {
+      const response = await fetch(`${API_BASE}/admin/parents/students`, {
         method: "POST",
         headers: { "Content-Type": "application/json" },
         credentials: "include",
@@ -2,11 +2,15 @@ import React, { useState, useEffect } from "react";
 import "./DataTable.css";
 import { StudentDetailModal } from "./StudentDetailModal";
 import { useUser } from "../contexts/UserContext";
+import { API_BASE } from "../api";
 
 interface ChildSummary {
   student_id: number;
   student_name: string;
   total_points: number;
+  effective_points: number;
+  merit_points: number;
+  net_points: number;
   recent_demerit: string | null;
   grade_level: number | null;
   class_section: string | null;
@@ -26,20 +30,9 @@ export const ParentChildSummary: React.FC = () => {
     setLoading(true);
     setError(null);
     try {
-      // Convert user ID from string to number
-      const userId = parseInt(user?.id || "0");
-
-      // Make POST request with user ID
-      const response = await fetch(
-        "http://localhost:8080/parent_children_summary",
-        {
-          method: "POST",
-          headers: {
-            "Content-Type": "application/json",
-          },
-          body: JSON.stringify({ user_id: userId }),
-        },
-      );
+      const response = await fetch(`${API_BASE}/parent/children`, {
+        credentials: "include",
+      });
 
       if (!response.ok) {
         throw new Error("Failed to fetch children data");
@@ -97,6 +90,9 @@ export const ParentChildSummary: React.FC = () => {
               <th>Grade</th>
               <th>Class</th>
               <th>Total Points</th>
+              <th>Current Points</th>
+              <th>Merits</th>
+              <th>Net Points</th>
               <th>Most Recent Issue</th>
             </tr>
           </thead>
@@ -113,6 +109,11 @@ export const ParentChildSummary: React.FC = () => {
                 <td className={getDemeritClass(child.total_points)}>
                   {child.total_points}
                 </td>
+                <td>{child.effective_points}</td>
+                <td>{child.merit_points}</td>
+                <td className={getDemeritClass(child.net_points)}>
+                  {child.net_points}
+                </td>
                 <td>{child.recent_demerit || "None"}</td>
               </tr>
             ))}
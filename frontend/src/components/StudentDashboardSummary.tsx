@@ -1,6 +1,7 @@
 import React, { useState, useEffect } from "react";
 import { useUser } from "../contexts/UserContext";
 import "./DataTable.css";
+import { API_BASE } from "../api";
 
 interface DemeritDetail {
   demerit_id: number;
@@ -36,21 +37,10 @@ export const StudentDashboardSummary: React.FC = () => {
 
       setLoading(true);
       try {
-        // Convert user ID from string to number
-        const userId = parseInt(user.id);
-
-        // First, get the student's information using POST
-        const studentResponse = await fetch(
-          "http://localhost:8080/my_student_info",
-          {
-            method: "POST",
-            headers: {
-              "Content-Type": "application/json",
-            },
-            body: JSON.stringify({ user_id: userId }),
-            credentials: "include",
-          },
-        );
+        // First, get the student's information
+        const studentResponse = await fetch(`${API_BASE}/student/profile`, {
+          credentials: "include",
+        });
 
         if (!studentResponse.ok) {
           throw new Error("Failed to fetch student info");
@@ -58,18 +48,10 @@ export const StudentDashboardSummary: React.FC = () => {
 
         const studentInfo = await studentResponse.json();
 
-        // Then fetch the detailed demerit history using POST
-        const demeritResponse = await fetch(
-          "http://localhost:8080/my_demerits",
-          {
-            method: "POST",
-            headers: {
-              "Content-Type": "application/json",
-            },
-            body: JSON.stringify({ user_id: userId }),
-            credentials: "include",
-          },
-        );
+        // Then fetch the detailed demerit history
+        const demeritResponse = await fetch(`${API_BASE}/student/demerits`, {
+          credentials: "include",
+        });
 
         if (!demeritResponse.ok) {
           throw new Error("Failed to fetch demerit records");
@@ -1,11 +1,15 @@
 import React, { useState, useEffect } from "react";
 import "./DataTable.css"; // Reuse the data table styles
 import { StudentDetailModal } from "./StudentDetailModal";
+import { API_BASE } from "../api";
 
 interface StudentDemeritSummary {
   student_id: number;
   student_name: string;
   total_points: number;
+  effective_points: number;
+  merit_points: number;
+  net_points: number;
   recent_demerit: string | null;
   grade_level: number | null;
   class_section: string | null;
@@ -36,7 +40,7 @@ export const StudentDemeritSummary: React.FC<StudentDemeritSummaryProps> = ({
     setError(null);
     try {
       const response = await fetch(
-        "http://localhost:8080/student_demerit_summary",
+        `${API_BASE}/staff/reports/students`,
         {
           credentials: "include",
         },
@@ -127,6 +131,9 @@ export const StudentDemeritSummary: React.FC<StudentDemeritSummaryProps> = ({
               <th>Grade</th>
               <th>Class</th>
               <th>Total Points</th>
+              <th>Current Points</th>
+              <th>Merits</th>
+              <th>Net Points</th>
               <th>Most Recent Issue</th>
             </tr>
           </thead>
@@ -144,6 +151,11 @@ export const StudentDemeritSummary: React.FC<StudentDemeritSummaryProps> = ({
                 <td className={getDemeritClass(summary.total_points)}>
                   {summary.total_points}
                 </td>
+                <td>{summary.effective_points}</td>
+                <td>{summary.merit_points}</td>
+                <td className={getDemeritClass(summary.net_points)}>
+                  {summary.net_points}
+                </td>
                 <td>{summary.recent_demerit || "-"}</td>
               </tr>
             ))}
@@ -1,5 +1,7 @@
 import React, { useState, useEffect } from "react";
 import "./DemeritHistory.css";
+import { useUser } from "../contexts/UserContext";
+import { API_BASE } from "../api";
 
 interface StudentDetailModalProps {
   studentId: number;
@@ -21,6 +23,7 @@ export const StudentDetailModal: React.FC<StudentDetailModalProps> = ({
   studentName,
   onClose,
 }) => {
+  const { user } = useUser();
   const [demerits, setDemerits] = useState<DemeritDetail[]>([]);
   const [loading, setLoading] = useState(true);
   const [error, setError] = useState<string | null>(null);
@@ -28,9 +31,14 @@ export const StudentDetailModal: React.FC<StudentDetailModalProps> = ({
   useEffect(() => {
     const fetchStudentDemerits = async () => {
       try {
-        const response = await fetch(
-          `http://localhost:8080/student_demerits/${studentId}`,
-        );
+        // Parents reach their children's records through their own scope
+        const path =
+          user?.userType === "parent"
+            ? `/parent/children/${studentId}/demerits`
+            : `/staff/students/${studentId}/demerits`;
+        const response = await fetch(`${API_BASE}${path}`, {
+          credentials: "include",
+        });
 
         if (!response.ok) {
           throw new Error("Failed to fetch student demerits");
@@ -46,7 +54,7 @@ export const StudentDetailModal: React.FC<StudentDetailModalProps> = ({
     };
 
     fetchStudentDemerits();
-  }, [studentId]);
+  }, [studentId, user?.userType]);
 
   return (
     <div className="modal-overlay">
@@ -0,0 +1,154 @@
+import React, { useEffect, useState } from "react";
+import { useNavigate } from "react-router-dom";
+import { Form, FormInput, FormButton } from "./Form";
+import { useUser } from "../contexts/UserContext";
+import "./AuthForm.css";
+import "../App.css";
+import { API_BASE } from "../api";
+
+interface Enrollment {
+  secret: string;
+  otpauth_uri: string;
+}
+
+const TwoFactorSetup: React.FC = () => {
+  const { user, setUser } = useUser();
+  const navigate = useNavigate();
+  const [enrollment, setEnrollment] = useState<Enrollment | null>(null);
+  const [code, setCode] = useState("");
+  const [recoveryCodes, setRecoveryCodes] = useState<string[] | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const [isLoading, setIsLoading] = useState(false);
+
+  useEffect(() => {
+    if (!user?.id) return;
+
+    fetch(`${API_BASE}/auth/two_factor/setup`, {
+      method: "POST",
+      credentials: "include",
+    })
+      .then(async (response) => {
+        const data = await response.json();
+        if (!response.ok) {
+          throw new Error(data?.message || "Failed to start setup");
+        }
+        setEnrollment(data);
+      })
+      .catch((err) =>
+        setError(err instanceof Error ? err.message : "An error occurred"),
+      );
+  }, [user?.id]);
+
+  const handleSubmit = async (e: React.FormEvent<HTMLFormElement>) => {
+    e.preventDefault();
+    setIsLoading(true);
+    setError(null);
+
+    try {
+      const response = await fetch(`${API_BASE}/auth/two_factor/enable`, {
+        method: "POST",
+        headers: {
+          "Content-Type": "application/json",
+        },
+        body: JSON.stringify({ code }),
+        credentials: "include",
+      });
+      const data = await response.json();
+
+      if (!response.ok) {
+        throw new Error(data?.message || "Failed to enable two-factor login");
+      }
+
+      setUser({
+        ...data.user,
+        userType: data.user.permissions,
+      });
+      setRecoveryCodes(data.recovery_codes);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "An error occurred");
+    } finally {
+      setIsLoading(false);
+    }
+  };
+
+  if (!user) {
+    navigate("/");
+    return null;
+  }
+
+  if (recoveryCodes) {
+    return (
+      <div className="main">
+        <div className="app">
+          <div className="auth-container">
+            <div className="auth-form">
+              <h2>Save Your Recovery Codes</h2>
+              <p>
+                Each code signs you in once if you lose your authenticator.
+                They will not be shown again.
+              </p>
+              <ul>
+                {recoveryCodes.map((recoveryCode) => (
+                  <li key={recoveryCode}>
+                    <code>{recoveryCode}</code>
+                  </li>
+                ))}
+              </ul>
+              <FormButton
+                type="button"
+                onClick={() => navigate(`/${user.userType}`)}
+              >
+                Continue
+              </FormButton>
+            </div>
+          </div>
+        </div>
+      </div>
+    );
+  }
+
+  return (
+    <div className="main">
+      <div className="app">
+        <div className="auth-container">
+          <Form onSubmit={handleSubmit} className="auth-form">
+            <h2>Set Up Two-Factor Authentication</h2>
+            <p>
+              Add this account to your authenticator app, then enter the code
+              it shows.
+            </p>
+
+            {error && <div className="error-banner">{error}</div>}
+
+            {enrollment && (
+              <>
+                <p>
+                  <a href={enrollment.otpauth_uri}>Open in authenticator app</a>
+                </p>
+                <p>
+                  Or enter this key manually: <code>{enrollment.secret}</code>
+                </p>
+              </>
+            )}
+
+            <FormInput
+              label="Authentication Code"
+              name="code"
+              value={code}
+              onChange={(e) => setCode(e.target.value)}
+              placeholder="123456"
+              required
+              disabled={isLoading || !enrollment}
+            />
+
+            <FormButton type="submit" disabled={isLoading || !enrollment}>
+              {isLoading ? "Verifying..." : "Enable"}
+            </FormButton>
+          </Form>
+        </div>
+      </div>
+    </div>
+  );
+};
+
+export default TwoFactorSetup;
@@ -0,0 +1,62 @@
+import React, { useEffect, useState } from "react";
+import { useNavigate, useSearchParams } from "react-router-dom";
+import "./AuthForm.css";
+import "../App.css";
+import { API_BASE } from "../api";
+
+const VerifyEmail: React.FC = () => {
+  const [searchParams] = useSearchParams();
+  const navigate = useNavigate();
+  const [message, setMessage] = useState("Verifying your email address...");
+  const [failed, setFailed] = useState(false);
+
+  useEffect(() => {
+    const token = searchParams.get("token");
+    if (!token) {
+      setFailed(true);
+      setMessage("This verification link is incomplete.");
+      return;
+    }
+
+    fetch(`${API_BASE}/auth/verify_email`, {
+      method: "POST",
+      headers: {
+        "Content-Type": "application/json",
+      },
+      body: JSON.stringify({ token }),
+    })
+      .then(async (response) => {
+        const data = await response.json();
+        setFailed(!response.ok);
+        setMessage(data?.message || "Verification failed");
+      })
+      .catch(() => {
+        setFailed(true);
+        setMessage("Could not reach the server, please try again.");
+      });
+  }, [searchParams]);
+
+  return (
+    <div className="main">
+      <div className="app">
+        <div className="auth-container">
+          <div className="auth-form">
+            <h2>Email Verification</h2>
+            <div className={failed ? "error-banner" : "notice-banner"}>
+              {message}
+            </div>
+            <button
+              type="button"
+              className="forgot-password"
+              onClick={() => navigate("/")}
+            >
+              Back to login
+            </button>
+          </div>
+        </div>
+      </div>
+    </div>
+  );
+};
+
+export default VerifyEmail;
@@ -1,4 +1,5 @@
 import React, { createContext, useContext, useState, useEffect } from "react";
+import { API_BASE } from "../api";
 
 interface User {
   id: string;
@@ -40,6 +41,11 @@ export const UserProvider: React.FC<{ children: React.ReactNode }> = ({
   };
 
   const logout = () => {
+    // End the server-side session; the local state is cleared regardless
+    fetch(`${API_BASE}/auth/logout`, {
+      method: "POST",
+      credentials: "include",
+    }).catch(() => {});
     localStorage.removeItem("user");
     setUserState(null);
   };
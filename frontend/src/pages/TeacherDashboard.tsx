@@ -6,6 +6,7 @@ import { DemeritHistory } from "../components/DemeritHistory";
 import { DataVisualizationPanel } from "../components/DataVisualizationPanel";
 import "./TeacherDashboard.css";
 import { StudentDemeritSummary } from "../components/StudentDemeritSummary";
+import { API_BASE } from "../api";
 
 export const TeacherDashboard = () => {
   const { user } = useUser();
@@ -32,7 +33,7 @@ export const TeacherDashboard = () => {
         teacher_email: user?.email, // From the user context
       };
 
-      const response = await fetch("http://localhost:8080/add_demerit", {
+      const response = await fetch(`${API_BASE}/teacher/demerits`, {
         method: "POST",
         headers: {
           "Content-Type": "application/json",
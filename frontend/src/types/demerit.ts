@@ -3,14 +3,25 @@ export interface DemeritRecord {
   category: string;
   points: number;
   date_issued: string;
+  voided_at?: string | null;
+  void_reason?: string | null;
+  appeal_status?: "open" | "under_review" | "upheld" | "overturned" | null;
 }
 
 export interface TeacherRecord extends DemeritRecord {
   student_name: string;
 }
 
-export interface StudentRecord extends DemeritRecord {
+export interface StudentRecord {
+  demerit_id: number;
+  category_name: string;
+  points: number;
   teacher_name: string;
+  description: string;
+  date_issued: string;
+  voided_at?: string | null;
+  void_reason?: string | null;
+  appeal_status?: "open" | "under_review" | "upheld" | "overturned" | null;
 }
 
 export interface ParentRecord extends DemeritRecord {
@@ -31,10 +42,24 @@ export interface AdminUserRecord {
   first_name: string;
   last_name: string;
   total_demerits: number;
+  effective_points: number;
+  merit_points: number;
+  net_points: number;
   created_at: string;
   grade_level?: number | null; // Note the optional marker
   class_section?: string | null; // Note the optional marker
   children?: ChildRecord[];
 }
 
+export interface MeritRecord {
+  merit_id: number;
+  student_id: number;
+  student_name: string;
+  category_name: string;
+  points: number;
+  teacher_name: string;
+  description: string;
+  date_awarded: string;
+}
+
 export type DataRecord = TeacherRecord | StudentRecord | ParentRecord;